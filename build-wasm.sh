@@ -0,0 +1,10 @@
+#!/bin/bash
+set -e
+
+# Rebuild static/split_bills_wasm.wasm after changing split-bills-core.
+# Needs the target once: rustup target add wasm32-unknown-unknown
+
+cargo build --release --target wasm32-unknown-unknown -p split-bills-wasm
+cp target/wasm32-unknown-unknown/release/split_bills_wasm.wasm static/split_bills_wasm.wasm
+
+echo "✅ Wrote static/split_bills_wasm.wasm ($(wc -c < static/split_bills_wasm.wasm) bytes)"
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use crate::models::*;
+
+// Everything one name adds up to across their expense lines
+struct PersonSummary {
+    name: String,
+    amount_spent: f64,
+    tip: f64,
+    sponsor_amount: f64,
+    is_sponsor: bool,
+    is_receiver: bool,
+    will_receive_from_others: f64,  // Amount they will receive as reimbursement
+    owes_to_others: f64, // Amount they owe to reimburse others
+    delegated_self: f64, // Amount they marked as paid_by themselves (private expense)
+}
+
+/// Split a bill.
+///
+/// Lines are grouped by name. Shared lines (and their tips) go into a pool; the
+/// global tip percentage is added on top, sponsorship and the fund amount come off,
+/// and the rest is split equally between participants. Private and reimbursed lines
+/// (see [`Person::paid_by`]) are settled between the people involved only.
+///
+/// The calculation never fails; check the request with
+/// [`validate_request`](crate::validate_request) first if it comes from untrusted input.
+pub fn calculate_split(request: CalculateRequest) -> CalculateResponse {
+    let people = request.people;
+    let include_sponsor = request.include_sponsor;
+    let _restrict_sponsor = request.restrict_sponsor_to_spent.unwrap_or(true);
+    let fund_amount = request.fund_amount;
+    let tip_percentage = request.tip_percentage;
+
+    // Group people by name to handle multiple entries for the same person
+    let mut grouped_people: HashMap<String, PersonSummary> = HashMap::new();
+
+    for person in &people {
+        let entry = grouped_people.entry(person.name.clone()).or_insert(PersonSummary {
+            name: person.name.clone(),
+            amount_spent: 0.0,
+            tip: 0.0,
+            sponsor_amount: 0.0,
+            is_sponsor: false,
+            is_receiver: false,
+            will_receive_from_others: 0.0,
+            owes_to_others: 0.0,
+            delegated_self: 0.0,
+        });
+
+        // Handle sponsor status
+        entry.sponsor_amount += person.sponsor_amount;
+        if person.is_sponsor {
+            entry.is_sponsor = true;
+        }
+        if person.is_receiver {
+            entry.is_receiver = true;
+        }
+
+        // Track expenses with "paid_by" set
+        if let Some(ref payer_name) = person.paid_by {
+            let total_expense = (person.amount_spent * person.quantity as f64) + person.tip;
+            
+            if payer_name == &person.name {
+                // Self-payment: This is a private expense, exclude from shared pool
+                entry.delegated_self += total_expense;
+                // Also add to amount_spent since they're paying for it
+                entry.amount_spent += person.amount_spent * person.quantity as f64;
+                entry.tip += person.tip;
+            } else {
+                // Someone else will reimburse this person for this expense
+                // The current person (who actually paid) will receive reimbursement
+                entry.will_receive_from_others += total_expense;
+                // DON'T add to amount_spent - it's offset by the reimbursement
+                
+                // The designated payer owes this amount
+                let payer_entry = grouped_people.entry(payer_name.clone()).or_insert(PersonSummary {
+                    name: payer_name.clone(),
+                    amount_spent: 0.0,
+                    tip: 0.0,
+                    sponsor_amount: 0.0,
+                    is_sponsor: false,
+                    is_receiver: false,
+                    will_receive_from_others: 0.0,
+                    owes_to_others: 0.0,
+                    delegated_self: 0.0,
+                });
+                payer_entry.owes_to_others += total_expense;
+            }
+        } else {
+            // No paid_by: Normal shared expense
+            entry.amount_spent += person.amount_spent * person.quantity as f64;
+            entry.tip += person.tip;
+        }
+    }
+
+    // Convert map to vector for processing
+    let unique_people: Vec<PersonSummary> = grouped_people.into_values().collect();
+
+    let tip_multiplier = 1.0 + (tip_percentage / 100.0);
+
+    // Calculate totals with tip included (as if it's a tax)
+    let total_spent_base: f64 = unique_people.iter().map(|p| p.amount_spent).sum();
+    let total_explicit_tip: f64 = unique_people.iter().map(|p| p.tip).sum();
+    
+    // Total spent with tip = (Base * Global Tax) + Explicit Tips
+    // Note: We assume explicit tips are NOT taxed by the global percentage
+    let total_spent_with_tip = (total_spent_base * tip_multiplier) + total_explicit_tip;
+    
+    // Calculate all delegated expenses (only private expenses paid_by self)
+    // Reimbursement expenses (will_receive_from_others) are already excluded from amount_spent
+    let all_delegated_expenses: f64 = unique_people.iter().map(|p| p.delegated_self).sum();
+    
+    // Sponsorship is a fixed amount, not affected by tip/tax
+    let total_sponsored: f64 = unique_people.iter().map(|p| p.sponsor_amount).sum();
+    
+    // Handle sponsorship logic based on restriction setting
+    // Always restrict sponsorship to total spent to ensure no one profits (negative share)
+    let (effective_total_sponsored, sponsorship_ratio) = if total_sponsored > total_spent_with_tip {
+        // Restrict enabled: Cap sponsorship at total spent (including tip)
+        // Scale down sponsor contributions
+        let ratio = if total_sponsored > 0.0 { total_spent_with_tip / total_sponsored } else { 0.0 };
+        (total_spent_with_tip, ratio)
+    } else {
+        // Sponsorship <= spent: Use actual sponsorship amount
+        (total_sponsored, 1.0)
+    };
+    
+    // The amount that needs to be shared among participants
+    // Subtract all delegated expenses (including self-payment) because those are private transactions
+    // Fund amount is flat cash, so it's subtracted from the total needed
+    let amount_to_share = (total_spent_with_tip - effective_total_sponsored - fund_amount - all_delegated_expenses).max(0.0);
+
+    let participants: Vec<&PersonSummary> = if include_sponsor {
+        unique_people.iter().collect()
+    } else {
+        unique_people.iter().filter(|p| !p.is_sponsor).collect()
+    };
+
+    let num_participants = participants.len();
+    let per_person_share = if num_participants > 0 {
+        amount_to_share / num_participants as f64
+    } else {
+        0.0
+    };
+
+    let mut settlements: Vec<Settlement> = unique_people
+        .iter()
+        .map(|person| {
+            // Calculate tip paid by this person
+            // = Explicit Tip + (Amount Spent * Global Tax Rate)
+            let global_tip_part = if tip_percentage > 0.0 {
+                person.amount_spent * (tip_percentage / 100.0)
+            } else {
+                0.0
+            };
+            
+            let tip_paid = person.tip + global_tip_part;
+
+            // Calculate how much this person should pay (cost)
+            let sponsor_cost = if person.is_sponsor {
+                // Sponsorship is flat amount
+                person.sponsor_amount * sponsorship_ratio
+            } else {
+                0.0
+            };
+            
+            // If they are a participant in the split, add the shared amount
+            let is_participant = if include_sponsor {
+                true
+            } else {
+                !person.is_sponsor
+            };
+
+            let share_cost = if is_participant {
+                per_person_share
+            } else {
+                0.0
+            };
+
+            // What they should pay: sponsor_cost + share_cost + delegated_self + owes_to_others
+            // delegated_self is their private expense
+            // owes_to_others is reimbursements they owe to others
+            let total_cost = sponsor_cost + share_cost + person.delegated_self + person.owes_to_others;
+
+            // Balance calculation:
+            // What they paid: amount_spent + tip_paid
+            // Plus what they will receive back: will_receive_from_others
+            // Minus what they should pay: total_cost (share + private + reimbursements owed)
+            // Balance = (amount_spent + tip_paid + will_receive_from_others) - total_cost
+            // Positive balance = they should receive money
+            // Negative balance = they should pay money
+            let balance = (person.amount_spent + tip_paid + person.will_receive_from_others) - total_cost;
+
+            let settlement_type = if balance > 0.01 {
+                "receive".to_string()
+            } else if balance < -0.01 {
+                "pay".to_string()
+            } else {
+                "settled".to_string()
+            };
+
+            Settlement {
+                name: person.name.clone(),
+                amount_spent: person.amount_spent,
+                tip_paid,
+                sponsor_cost,
+                share_cost,
+                balance,
+                settlement_type,
+                is_receiver: person.is_receiver,
+                will_receive_from_others: person.will_receive_from_others,
+                owes_to_others: person.owes_to_others,
+                delegated_self: person.delegated_self,
+            }
+        })
+        .collect();
+    
+    // Sort settlements: Payers (negative balance) first, then Receivers (positive balance)
+    settlements.sort_by(|a, b| {
+        a.balance.partial_cmp(&b.balance)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    CalculateResponse {
+        total_spent: total_spent_base + total_explicit_tip,
+        total_sponsored: effective_total_sponsored,
+        fund_amount,
+        total_tip: total_spent_with_tip - (total_spent_base + total_explicit_tip),
+        amount_to_share,
+        num_participants,
+        per_person_share,
+        settlements,
+    }
+}
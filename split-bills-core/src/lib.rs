@@ -0,0 +1,63 @@
+//! The bill splitting engine behind the split-bills server, with no web, database or
+//! HTTP client dependencies.
+//!
+//! Build a [`CalculateRequest`], optionally check it with [`validate_request`], run
+//! [`calculate_split`] and turn the settlements into payments with [`plan_transfers`]:
+//!
+//! ```
+//! use split_bills_core::{calculate_split, plan_transfers, validate_request, CalculateRequest, Person};
+//!
+//! let person = |id, name: &str, amount_spent| Person {
+//!     id,
+//!     name: name.to_string(),
+//!     description: String::new(),
+//!     amount_spent,
+//!     quantity: 1,
+//!     tip: 0.0,
+//!     is_sponsor: false,
+//!     sponsor_amount: 0.0,
+//!     is_receiver: false,
+//!     paid_by: None,
+//! };
+//!
+//! let request = CalculateRequest {
+//!     people: vec![person(1, "Alice", 90.0), person(2, "Bob", 0.0), person(3, "Carol", 0.0)],
+//!     include_sponsor: true,
+//!     restrict_sponsor_to_spent: None,
+//!     fund_amount: 0.0,
+//!     tip_percentage: 0.0,
+//! };
+//! validate_request(&request).unwrap();
+//!
+//! let response = calculate_split(request);
+//! assert_eq!(response.per_person_share, 30.0);
+//!
+//! let transfers = plan_transfers(&response.settlements);
+//! assert_eq!(transfers.len(), 2);
+//! assert!(transfers.iter().all(|t| t.to == "Alice" && t.amount == 30.0));
+//! ```
+//!
+//! All types serialize to the same JSON as the server's `/api/v1/calculate` endpoint.
+//! With the `openapi` feature they also implement `utoipa::ToSchema`, for services that
+//! describe them in an OpenAPI document.
+//!
+//! # Stability
+//!
+//! Everything exported here follows semver. Until 1.0, a breaking change bumps the
+//! minor version. Adding a field to a struct is a breaking change; adding a variant
+//! to [`ValidationError`] is not.
+
+#![warn(missing_docs)]
+
+mod calculation;
+mod models;
+mod settlement;
+mod validation;
+
+pub use calculation::calculate_split;
+pub use models::*;
+pub use settlement::{build_ledger, member_balances, net_group_balances, plan_transfers, settle_balances};
+pub use validation::{validate_request, ValidationError};
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+/// Default for [`Person::quantity`] when it is missing from the input.
+pub fn default_quantity() -> u32 {
+    1
+}
+
+/// Default for [`CalculateRequest::include_sponsor`] and the other places that
+/// accept it: sponsors take a share like everyone else.
+pub fn default_include_sponsor() -> bool {
+    true
+}
+
+/// One expense line. Several lines may share a `name`; they are added up into one
+/// participant by the calculation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Person {
+    /// Client-chosen identifier for the line. Not used by the calculation.
+    pub id: u64,
+    /// Who the line belongs to.
+    pub name: String,
+    /// Free text, e.g. what was bought.
+    pub description: String,
+    /// Unit price paid. The line's total is `amount_spent * quantity + tip`.
+    pub amount_spent: f64,
+    /// Number of units bought.
+    #[serde(default = "default_quantity")]
+    pub quantity: u32,
+    /// Tip paid on this line on top of the amount. Not affected by the global tip percentage.
+    #[serde(default)]
+    pub tip: f64,
+    /// Whether this person sponsors part of the bill.
+    pub is_sponsor: bool,
+    /// Flat amount sponsored. Capped so sponsors never cover more than the whole bill.
+    pub sponsor_amount: f64,
+    /// Marks the person who collects and pays out everything; see [`plan_transfers`](crate::plan_transfers).
+    #[serde(default)]
+    pub is_receiver: bool,
+    /// Who is responsible for this line instead of the shared pool. Naming the line's
+    /// own person makes it a private expense; naming someone else makes them reimburse it.
+    #[serde(default)]
+    pub paid_by: Option<String>,
+}
+
+/// Input to [`calculate_split`](crate::calculate_split).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CalculateRequest {
+    /// All expense lines.
+    pub people: Vec<Person>,
+    /// Whether sponsors also take a share of what is left after sponsorship.
+    pub include_sponsor: bool,
+    /// Accepted for compatibility. Sponsorship is always capped at the total spent.
+    pub restrict_sponsor_to_spent: Option<bool>,
+    /// Cash already collected towards the bill, subtracted before splitting.
+    #[serde(default)]
+    pub fund_amount: f64,
+    /// Percentage added on top of every amount spent, e.g. tax or service.
+    #[serde(default)]
+    pub tip_percentage: f64,
+}
+
+/// Result of [`calculate_split`](crate::calculate_split).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CalculateResponse {
+    /// Everything spent, including per-line tips but not the global tip percentage.
+    pub total_spent: f64,
+    /// Sponsorship actually applied, after capping.
+    pub total_sponsored: f64,
+    /// The fund amount from the request.
+    pub fund_amount: f64,
+    /// What the global tip percentage added.
+    pub total_tip: f64,
+    /// What is left to split equally between participants.
+    pub amount_to_share: f64,
+    /// How many people take a share.
+    pub num_participants: usize,
+    /// Each participant's share of `amount_to_share`.
+    pub per_person_share: f64,
+    /// One entry per unique name, people who pay first.
+    pub settlements: Vec<Settlement>,
+}
+
+/// Where one person stands once the bill is split.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Settlement {
+    /// The person's name.
+    pub name: String,
+    /// Total of their shared lines.
+    pub amount_spent: f64,
+    /// Per-line tips plus their part of the global tip percentage.
+    pub tip_paid: f64,
+    /// What their sponsorship costs them.
+    pub sponsor_cost: f64,
+    /// Their share of the shared costs.
+    pub share_cost: f64,
+    /// Positive when they get money back, negative when they owe.
+    pub balance: f64,
+    /// `"pay"`, `"receive"` or `"settled"` (within one cent).
+    pub settlement_type: String,
+    /// Copied from [`Person::is_receiver`].
+    pub is_receiver: bool,
+    /// Lines they paid that someone else reimburses.
+    pub will_receive_from_others: f64,
+    /// Lines of others that they reimburse.
+    pub owes_to_others: f64,
+    /// Their private expenses.
+    pub delegated_self: f64,
+}
+
+/// A single payment needed to settle up: `from` pays `amount` to `to`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Transfer {
+    /// Who pays.
+    pub from: String,
+    /// Who is paid.
+    pub to: String,
+    /// How much.
+    pub amount: f64,
+}
+
+/// One line of a person's balance breakdown; credits are positive, debits negative.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LedgerEntry {
+    /// What the line is, e.g. `"Share of shared costs"`.
+    pub label: String,
+    /// Signed amount.
+    pub amount: f64,
+}
+
+/// A person's balance, as kept per session and netted across sessions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MemberBalance {
+    /// The person's name.
+    pub name: String,
+    /// Positive when they get money back, negative when they owe.
+    pub balance: f64,
+}
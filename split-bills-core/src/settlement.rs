@@ -0,0 +1,110 @@
+use crate::calculation::calculate_split;
+use crate::models::*;
+
+/// The payments that settle a calculation.
+///
+/// With a designated receiver (see [`Person::is_receiver`]) every payment goes to or
+/// from them. Otherwise the balances are settled with [`settle_balances`].
+pub fn plan_transfers(settlements: &[Settlement]) -> Vec<Transfer> {
+    // With a designated receiver, all money flows through them (matches the UI wording)
+    if let Some(receiver) = settlements.iter().find(|s| s.is_receiver) {
+        let mut transfers = Vec::new();
+        for s in settlements.iter().filter(|s| !s.is_receiver) {
+            if s.balance < -0.01 {
+                transfers.push(Transfer { from: s.name.clone(), to: receiver.name.clone(), amount: -s.balance });
+            } else if s.balance > 0.01 {
+                transfers.push(Transfer { from: receiver.name.clone(), to: s.name.clone(), amount: s.balance });
+            }
+        }
+        return transfers;
+    }
+
+    let balances: Vec<(String, f64)> = settlements
+        .iter()
+        .map(|s| (s.name.clone(), s.balance))
+        .collect();
+    settle_balances(&balances)
+}
+
+/// A small set of transfers that brings every balance to zero, found by greedily
+/// matching the largest debtor with the largest creditor. Balances within one cent
+/// of zero are ignored.
+pub fn settle_balances(balances: &[(String, f64)]) -> Vec<Transfer> {
+    let mut transfers = Vec::new();
+    let mut debtors: Vec<(String, f64)> = balances
+        .iter()
+        .filter(|(_, balance)| *balance < -0.01)
+        .map(|(name, balance)| (name.clone(), -balance))
+        .collect();
+    let mut creditors: Vec<(String, f64)> = balances
+        .iter()
+        .filter(|(_, balance)| *balance > 0.01)
+        .map(|(name, balance)| (name.clone(), *balance))
+        .collect();
+    debtors.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.0.cmp(&b.0)));
+    creditors.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.0.cmp(&b.0)));
+
+    let (mut i, mut j) = (0, 0);
+    while i < debtors.len() && j < creditors.len() {
+        let amount = debtors[i].1.min(creditors[j].1);
+        transfers.push(Transfer { from: debtors[i].0.clone(), to: creditors[j].0.clone(), amount });
+        debtors[i].1 -= amount;
+        creditors[j].1 -= amount;
+        if debtors[i].1 <= 0.01 {
+            i += 1;
+        }
+        if creditors[j].1 <= 0.01 {
+            j += 1;
+        }
+    }
+
+    transfers
+}
+
+/// Break a settlement down into the lines that add up to its balance. Zero lines
+/// are left out.
+pub fn build_ledger(settlement: &Settlement) -> Vec<LedgerEntry> {
+    let lines = [
+        ("Spent", settlement.amount_spent),
+        ("Tip/tax paid", settlement.tip_paid),
+        ("Reimbursements to receive", settlement.will_receive_from_others),
+        ("Sponsorship", -settlement.sponsor_cost),
+        ("Share of shared costs", -settlement.share_cost),
+        ("Private expenses", -settlement.delegated_self),
+        ("Reimbursements owed", -settlement.owes_to_others),
+    ];
+
+    lines
+        .into_iter()
+        .filter(|(_, amount)| *amount != 0.0)
+        .map(|(label, amount)| LedgerEntry { label: label.to_string(), amount })
+        .collect()
+}
+
+/// Each person's balance from a calculation, for netting with [`net_group_balances`].
+pub fn member_balances(request: CalculateRequest) -> Vec<MemberBalance> {
+    calculate_split(request)
+        .settlements
+        .into_iter()
+        .map(|s| MemberBalance { name: s.name, balance: s.balance })
+        .collect()
+}
+
+/// Sum balances per person across several calculations. `members` come first, in
+/// that order, even with no balance, followed by anyone who only appears in `sessions`.
+pub fn net_group_balances(members: &[String], sessions: &[Vec<MemberBalance>]) -> Vec<MemberBalance> {
+    let mut balances: Vec<MemberBalance> = members
+        .iter()
+        .map(|name| MemberBalance { name: name.clone(), balance: 0.0 })
+        .collect();
+
+    for session in sessions {
+        for entry in session {
+            match balances.iter_mut().find(|b| b.name == entry.name) {
+                Some(existing) => existing.balance += entry.balance,
+                None => balances.push(entry.clone()),
+            }
+        }
+    }
+    balances
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+
+use crate::models::*;
+
+/// Why [`validate_request`] rejected a request.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ValidationError {
+    /// A line has an empty or blank name.
+    EmptyName {
+        /// [`Person::id`] of the line.
+        person_id: u64,
+    },
+    /// A line's `paid_by` is set but blank.
+    EmptyPaidBy {
+        /// [`Person::id`] of the line.
+        person_id: u64,
+    },
+    /// A number is NaN, infinite, or negative where that makes no sense.
+    InvalidNumber {
+        /// Name of the field, e.g. `"sponsor_amount"`.
+        field: &'static str,
+        /// [`Person::id`] of the line, or `None` for a request-level field.
+        person_id: Option<u64>,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::EmptyName { person_id } => write!(f, "person {} has no name", person_id),
+            ValidationError::EmptyPaidBy { person_id } => write!(f, "person {} has a blank paid_by", person_id),
+            ValidationError::InvalidNumber { field, person_id: Some(id) } => {
+                write!(f, "person {} has an invalid {}", id, field)
+            }
+            ValidationError::InvalidNumber { field, person_id: None } => write!(f, "invalid {}", field),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Check a request before calculating it, returning the first problem found.
+///
+/// Amounts spent may be negative (discounts, refunds), but every number must be
+/// finite and sponsorship, tips, the fund amount and the tip percentage can't be
+/// negative.
+pub fn validate_request(request: &CalculateRequest) -> Result<(), ValidationError> {
+    check_number("fund_amount", None, request.fund_amount, false)?;
+    check_number("tip_percentage", None, request.tip_percentage, false)?;
+
+    for person in &request.people {
+        let id = Some(person.id);
+        if person.name.trim().is_empty() {
+            return Err(ValidationError::EmptyName { person_id: person.id });
+        }
+        if person.paid_by.as_deref().is_some_and(|p| p.trim().is_empty()) {
+            return Err(ValidationError::EmptyPaidBy { person_id: person.id });
+        }
+        check_number("amount_spent", id, person.amount_spent, true)?;
+        check_number("tip", id, person.tip, false)?;
+        check_number("sponsor_amount", id, person.sponsor_amount, false)?;
+    }
+
+    Ok(())
+}
+
+fn check_number(field: &'static str, person_id: Option<u64>, value: f64, allow_negative: bool) -> Result<(), ValidationError> {
+    if value.is_finite() && (allow_negative || value >= 0.0) {
+        Ok(())
+    } else {
+        Err(ValidationError::InvalidNumber { field, person_id })
+    }
+}
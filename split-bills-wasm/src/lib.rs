@@ -0,0 +1,120 @@
+//! [`split_bills_core`] compiled to WebAssembly, for calculating in the browser without
+//! reaching the server. `static/split-engine.js` wraps these exports in a
+//! `calculate(request)` function that takes and returns the same JSON as `/api/v1/calculate`.
+//!
+//! The module has no imports. Strings cross the boundary as UTF-8 bytes in the module's
+//! memory: the caller reserves room with [`alloc`], writes the request JSON there and
+//! calls [`calculate`], then reads the output from [`result_ptr`]/[`result_len`].
+
+use std::cell::RefCell;
+
+use split_bills_core::{calculate_split, validate_request, CalculateRequest};
+
+/// [`calculate`] succeeded; the output is a `CalculateResponse`.
+pub const STATUS_OK: u32 = 0;
+/// The input wasn't a valid `CalculateRequest`; the output is the parse error.
+pub const STATUS_INVALID_JSON: u32 = 1;
+/// The request failed validation; the output is the validation error.
+pub const STATUS_INVALID_REQUEST: u32 = 2;
+
+thread_local! {
+    // Output of the last call, kept until the next one so JS can read it
+    static RESULT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Reserve `len` bytes for the caller to write input into. Release with [`dealloc`].
+#[no_mangle]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// Release memory from [`alloc`].
+///
+/// # Safety
+///
+/// `ptr` and `len` must come from one call to [`alloc`], and the memory must not be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn dealloc(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+/// Validate and calculate the `CalculateRequest` JSON in `ptr..ptr + len`. Returns one
+/// of the `STATUS_*` codes; the response JSON or error message is then available from
+/// [`result_ptr`] and [`result_len`].
+///
+/// # Safety
+///
+/// `ptr..ptr + len` must be initialized memory, e.g. from [`alloc`].
+#[no_mangle]
+pub unsafe extern "C" fn calculate(ptr: *const u8, len: usize) -> u32 {
+    let input = std::slice::from_raw_parts(ptr, len);
+    let (status, output) = calculate_json(input);
+    RESULT.with(|result| *result.borrow_mut() = output.into_bytes());
+    status
+}
+
+/// Start of the output of the last [`calculate`] call.
+#[no_mangle]
+pub extern "C" fn result_ptr() -> *const u8 {
+    RESULT.with(|result| result.borrow().as_ptr())
+}
+
+/// Length in bytes of the output of the last [`calculate`] call.
+#[no_mangle]
+pub extern "C" fn result_len() -> usize {
+    RESULT.with(|result| result.borrow().len())
+}
+
+/// What [`calculate`] does, minus the memory handling: a status code and the output text.
+pub fn calculate_json(input: &[u8]) -> (u32, String) {
+    let request: CalculateRequest = match serde_json::from_slice(input) {
+        Ok(request) => request,
+        Err(e) => return (STATUS_INVALID_JSON, e.to_string()),
+    };
+    if let Err(e) = validate_request(&request) {
+        return (STATUS_INVALID_REQUEST, e.to_string());
+    }
+
+    let response = calculate_split(request);
+    match serde_json::to_string(&response) {
+        Ok(json) => (STATUS_OK, json),
+        Err(e) => (STATUS_INVALID_JSON, e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calculate_through_exports() {
+        let request = r#"{"people":[
+            {"id":1,"name":"Alice","description":"","amount_spent":90,"is_sponsor":false,"sponsor_amount":0},
+            {"id":2,"name":"Bob","description":"","amount_spent":0,"is_sponsor":false,"sponsor_amount":0,"paid_by":"Alice"}
+        ],"include_sponsor":true,"tip_percentage":10}"#;
+
+        let ptr = alloc(request.len());
+        let status = unsafe {
+            std::ptr::copy_nonoverlapping(request.as_ptr(), ptr, request.len());
+            let status = calculate(ptr, request.len());
+            dealloc(ptr, request.len());
+            status
+        };
+        let output = unsafe { std::slice::from_raw_parts(result_ptr(), result_len()) };
+
+        // Byte for byte what the server would answer
+        let expected = serde_json::to_string(&calculate_split(serde_json::from_str(request).unwrap())).unwrap();
+        assert_eq!(status, STATUS_OK);
+        assert_eq!(std::str::from_utf8(output).unwrap(), expected);
+
+        assert_eq!(calculate_json(b"{").0, STATUS_INVALID_JSON);
+        let (status, message) = calculate_json(
+            br#"{"people":[{"id":7,"name":"","description":"","amount_spent":1,"is_sponsor":false,"sponsor_amount":0}],"include_sponsor":true}"#,
+        );
+        assert_eq!((status, message.as_str()), (STATUS_INVALID_REQUEST, "person 7 has no name"));
+    }
+}
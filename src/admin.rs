@@ -0,0 +1,221 @@
+use chrono::{NaiveDate, Utc};
+use std::collections::BTreeMap;
+
+use crate::models::*;
+use crate::store::SessionStore;
+use crate::{import, session_expires_at, session_response};
+
+const USAGE: &str = "usage: split-bills <command>
+
+Without a command the web server starts. Commands use the database from DATABASE_URL:
+
+  list [limit]             Sessions, most recently accessed first
+  search <text>            Sessions whose id, participant names or emails contain the text
+  show <id>                A session as JSON, as returned by GET /api/v1/sessions/:id
+  export [id...]           All sessions (or the given ones) as JSON keyed by id
+  delete <id>...           Delete sessions and their participant links
+  extend <id> [days]       Reset a session's expiry clock, optionally with a new expiry in days
+  purge [--force]          Delete expired sessions now; --force ignores pending expiry warnings
+  migrate                  Create or upgrade the tables
+  vacuum                   Reclaim space left by deleted rows
+  import <file>...         Load legacy sessions.json or browser history exports
+  usage [date]             AI and email requests counted against the daily quotas (UTC, default today)
+  exemptions               IPs and sessions the per-client rate limits don't apply to
+  exempt <subject> [note]  Lift the per-client rate limits for an IP address or session id
+  unexempt <subject>       Remove an exemption
+  calculate [file]         Run a split offline; see `split-bills calculate --help`";
+
+// Run one admin command and print its output. Nothing here touches last_accessed_at
+// except `extend`, so inspecting a session doesn't keep it alive.
+pub async fn run(store: &dyn SessionStore, args: &[String], session_expiry_days: i64) -> Result<(), String> {
+    let command = args.first().map(String::as_str).unwrap_or("help");
+    let rest = &args[1..];
+
+    match command {
+        "list" => {
+            let limit = match rest.first() {
+                Some(limit) => limit.parse().map_err(|_| format!("invalid limit: {}", limit))?,
+                None => usize::MAX,
+            };
+            let sessions = store.all_sessions().await.map_err(db_error)?;
+            print_sessions(sessions.iter().take(limit), session_expiry_days);
+        }
+        "search" => {
+            let query = rest.first().ok_or(USAGE)?.to_lowercase();
+            let sessions = store.all_sessions().await.map_err(db_error)?;
+            print_sessions(sessions.iter().filter(|s| matches_search(s, &query)), session_expiry_days);
+        }
+        "show" => {
+            let id = rest.first().ok_or(USAGE)?;
+            let session = fetch(store, id).await?;
+            let response = session_response(session, session_expiry_days).map_err(|e| e.to_string())?;
+            println!("{}", serde_json::to_string_pretty(&response).map_err(|e| e.to_string())?);
+        }
+        "export" => {
+            let sessions = if rest.is_empty() {
+                store.all_sessions().await.map_err(db_error)?
+            } else {
+                let mut sessions = Vec::new();
+                for id in rest {
+                    sessions.push(fetch(store, id).await?);
+                }
+                sessions
+            };
+
+            let mut export = BTreeMap::new();
+            for session in sessions {
+                let id = session.id.clone();
+                let response = session_response(session, session_expiry_days)
+                    .map_err(|e| format!("{}: {}", id, e))?;
+                export.insert(id, response);
+            }
+            println!("{}", serde_json::to_string_pretty(&export).map_err(|e| e.to_string())?);
+        }
+        "delete" => {
+            if rest.is_empty() {
+                return Err(USAGE.to_string());
+            }
+            for id in rest {
+                fetch(store, id).await?;
+                store.delete_session(id).await.map_err(db_error)?;
+                println!("Deleted {}", id);
+            }
+        }
+        "extend" => {
+            let id = rest.first().ok_or(USAGE)?;
+            let mut session = fetch(store, id).await?;
+            if let Some(days) = rest.get(1) {
+                let days: i64 = days.parse().ok().filter(|d| (1..=crate::MAX_SESSION_EXPIRY_DAYS).contains(d))
+                    .ok_or_else(|| format!("invalid number of days: {}", days))?;
+                session.expiry_days = Some(days);
+            }
+            session.last_accessed_at = Utc::now();
+            store.update_session_expiry(&session).await.map_err(db_error)?;
+            match session_expires_at(&session, session_expiry_days) {
+                Some(expires_at) => println!("{} now expires at {}", id, expires_at.to_rfc3339()),
+                None => println!("{} is pinned and never expires", id),
+            }
+        }
+        "purge" => {
+            // Same rules as the hourly cleanup, which only holds sessions back for
+            // warnings while email is configured
+            let email_configured = crate::email::transport_from_env()
+                .map_err(|e| format!("invalid email configuration: {}", e))?
+                .is_some();
+            let warnings_enabled = email_configured && rest.first().map(String::as_str) != Some("--force");
+            let warning_days = warnings_enabled.then(crate::expiry_warning_days);
+            let now = Utc::now();
+            let deleted = store.delete_expired_sessions(session_expiry_days, warning_days, now)
+                .await
+                .map_err(db_error)?;
+            store.delete_expired_tokens(now).await.map_err(db_error)?;
+            println!("Deleted {} expired session(s)", deleted);
+        }
+        // Connecting already ran the migrations
+        "migrate" => println!("Database is up to date"),
+        "vacuum" => {
+            store.vacuum().await.map_err(db_error)?;
+            println!("Vacuumed database");
+        }
+        "import" => {
+            if rest.is_empty() {
+                return Err(USAGE.to_string());
+            }
+            let mut failed = false;
+            for path in rest {
+                let result = match std::fs::read_to_string(path) {
+                    Ok(contents) => import::import_json(store, &contents, Utc::now()).await,
+                    Err(e) => Err(e.to_string()),
+                };
+                match result {
+                    Ok(report) => print!("{}: {}", path, report),
+                    Err(e) => {
+                        eprintln!("{}: {}", path, e);
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                return Err("Some files could not be imported".to_string());
+            }
+        }
+        "usage" => {
+            let day = match rest.first() {
+                Some(day) => NaiveDate::parse_from_str(day, "%Y-%m-%d").map_err(|_| format!("invalid date: {}", day))?,
+                None => Utc::now().date_naive(),
+            };
+            let usage = store.daily_usage(day).await.map_err(db_error)?;
+            for entry in &usage {
+                println!("{:>7}  {}", entry.count, entry.subject);
+            }
+            println!("{} subject(s) on {}", usage.len(), day);
+        }
+        "exemptions" => {
+            let exemptions = store.rate_limit_exemptions().await.map_err(db_error)?;
+            for exemption in &exemptions {
+                println!("{:<40}  {}  {}", exemption.subject, exemption.created_at.format("%Y-%m-%d %H:%M"), exemption.note);
+            }
+            println!("{} exemption(s)", exemptions.len());
+        }
+        "exempt" => {
+            let subject = rest.first().ok_or(USAGE)?;
+            let exemption = DbRateLimitExemption {
+                subject: subject.clone(),
+                note: rest[1..].join(" "),
+                created_at: Utc::now(),
+            };
+            store.insert_rate_limit_exemption(&exemption).await.map_err(db_error)?;
+            println!("{} is exempt from the per-client rate limits", subject);
+        }
+        "unexempt" => {
+            let subject = rest.first().ok_or(USAGE)?;
+            if !store.delete_rate_limit_exemption(subject).await.map_err(db_error)? {
+                return Err(format!("{} is not exempt", subject));
+            }
+            println!("Removed the exemption for {}", subject);
+        }
+        "help" | "--help" | "-h" => println!("{}", USAGE),
+        _ => return Err(format!("unknown command: {}\n\n{}", command, USAGE)),
+    }
+
+    Ok(())
+}
+
+async fn fetch(store: &dyn SessionStore, id: &str) -> Result<DbSession, String> {
+    store.get_session(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| format!("session not found: {}", id))
+}
+
+fn db_error(e: sqlx::Error) -> String {
+    format!("database error: {}", e)
+}
+
+// `query` is already lowercased
+pub(crate) fn matches_search(session: &DbSession, query: &str) -> bool {
+    if session.id.to_lowercase().contains(query) || session.notification_emails.to_lowercase().contains(query) {
+        return true;
+    }
+    let people: Vec<Person> = serde_json::from_str(&session.people).unwrap_or_default();
+    people.iter().any(|p| p.name.to_lowercase().contains(query))
+}
+
+fn print_sessions<'a>(sessions: impl Iterator<Item = &'a DbSession>, session_expiry_days: i64) {
+    println!("{:<36}  {:>6}  {:<20}  {:<20}", "ID", "PEOPLE", "LAST ACCESSED", "EXPIRES");
+    for session in sessions {
+        let people: Vec<Person> = serde_json::from_str(&session.people).unwrap_or_default();
+        let expires = match session_expires_at(session, session_expiry_days) {
+            Some(at) if at < Utc::now() => format!("{} (expired)", at.format("%Y-%m-%d %H:%M")),
+            Some(at) => at.format("%Y-%m-%d %H:%M").to_string(),
+            None => "pinned".to_string(),
+        };
+        println!(
+            "{:<36}  {:>6}  {:<20}  {:<20}",
+            session.id,
+            people.len(),
+            session.last_accessed_at.format("%Y-%m-%d %H:%M"),
+            expires
+        );
+    }
+}
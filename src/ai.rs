@@ -1,13 +1,12 @@
-use core::panic;
-
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde::de::DeserializeOwned;
 use serde_json::json;
+use utoipa::ToSchema;
 use base64::{Engine as _, engine::general_purpose};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct ReceiptItem {
     pub name: String,
     pub amount: f64,
@@ -19,7 +18,7 @@ fn default_quantity() -> u32 {
     1
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct ReceiptData {
     pub description: String,
     pub amount: f64,
@@ -31,7 +30,7 @@ pub struct ReceiptData {
     pub items: Vec<ReceiptItem>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct AiExpense {
     pub name: String,
     pub description: String,
@@ -46,7 +45,7 @@ pub struct AiExpense {
     pub sponsor_amount: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct AiSplitResponse {
     pub expenses: Vec<AiExpense>,
     #[serde(default)]
@@ -67,13 +66,7 @@ pub struct OpenAiProvider {
 }
 
 impl OpenAiProvider {
-    pub fn new(api_key: String) -> Self {
-        let model = std::env::var("OPENAI_API_MODEL").unwrap_or_default();
-
-        if model.is_empty() {
-            panic!("OPENAI_API_MODEL environment variable is not set");
-        }
-
+    pub fn new(api_key: String, model: String) -> Self {
         Self {
             client: Client::new(),
             api_key,
@@ -0,0 +1,226 @@
+use serde::Deserialize;
+use std::io::Read;
+
+use crate::models::*;
+use split_bills_core::{calculate_split, plan_transfers, validate_request};
+
+const USAGE: &str = "usage: split-bills calculate [file] [options]
+
+Reads a CalculateRequest as JSON, or a CSV with a header row of
+name,description,amount,quantity,tip,sponsor,paid_by (only name and amount are
+required), from the file or from stdin when the file is missing or `-`.
+
+  --input json|csv                    Input format (default: from the file extension,
+                                      or guessed from the content)
+  --format table|json|csv             Output format (default: table)
+  --include-sponsor true|false        Whether sponsors also take a share (default: true)
+  --restrict-sponsor-to-spent true|false
+  --fund-amount <amount>              Cash already collected towards the bill
+  --tip-percentage <percent>          Tip added on top of every amount
+
+Options override the same fields in a JSON request.";
+
+// One CSV row. `sponsor` is the amount sponsored; blank or 0 means not a sponsor.
+#[derive(Deserialize)]
+struct CsvPerson {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    amount: f64,
+    #[serde(default)]
+    quantity: Option<u32>,
+    #[serde(default)]
+    tip: Option<f64>,
+    #[serde(default)]
+    sponsor: Option<f64>,
+    #[serde(default)]
+    paid_by: Option<String>,
+}
+
+#[derive(Default)]
+struct Options {
+    path: Option<String>,
+    input: Option<String>,
+    format: Option<String>,
+    include_sponsor: Option<bool>,
+    restrict_sponsor_to_spent: Option<bool>,
+    fund_amount: Option<f64>,
+    tip_percentage: Option<f64>,
+}
+
+// Run the split engine without a server or database and print the result
+pub fn run(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let options = parse_options(args)?;
+
+    let mut contents = String::new();
+    match options.path.as_deref() {
+        Some(path) if path != "-" => {
+            contents = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        _ => {
+            std::io::stdin().read_to_string(&mut contents).map_err(|e| e.to_string())?;
+        }
+    }
+
+    let is_csv = match options.input.as_deref() {
+        Some("csv") => true,
+        Some("json") => false,
+        Some(other) => return Err(format!("unknown input format: {}", other)),
+        None => match options.path.as_deref() {
+            Some(path) if path.to_lowercase().ends_with(".csv") => true,
+            Some(path) if path.to_lowercase().ends_with(".json") => false,
+            _ => !contents.trim_start().starts_with('{'),
+        },
+    };
+
+    let mut request = if is_csv {
+        CalculateRequest {
+            people: parse_csv(&contents)?,
+            include_sponsor: default_include_sponsor(),
+            restrict_sponsor_to_spent: None,
+            fund_amount: 0.0,
+            tip_percentage: 0.0,
+        }
+    } else {
+        serde_json::from_str(&contents).map_err(|e| format!("invalid request: {}", e))?
+    };
+
+    if let Some(include_sponsor) = options.include_sponsor {
+        request.include_sponsor = include_sponsor;
+    }
+    if options.restrict_sponsor_to_spent.is_some() {
+        request.restrict_sponsor_to_spent = options.restrict_sponsor_to_spent;
+    }
+    if let Some(fund_amount) = options.fund_amount {
+        request.fund_amount = fund_amount;
+    }
+    if let Some(tip_percentage) = options.tip_percentage {
+        request.tip_percentage = tip_percentage;
+    }
+
+    validate_request(&request).map_err(|e| format!("invalid request: {}", e))?;
+    let response = calculate_split(request);
+    let output = match options.format.as_deref().unwrap_or("table") {
+        "table" => format_table(&response),
+        "json" => serde_json::to_string_pretty(&response).map_err(|e| e.to_string())? + "\n",
+        "csv" => format_csv(&response)?,
+        other => return Err(format!("unknown output format: {}", other)),
+    };
+    print!("{}", output);
+    Ok(())
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            if options.path.replace(arg.clone()).is_some() {
+                return Err(USAGE.to_string());
+            }
+            continue;
+        }
+
+        let value = args.next().ok_or_else(|| format!("{} needs a value\n\n{}", arg, USAGE))?;
+        match arg.as_str() {
+            "--input" => options.input = Some(value.clone()),
+            "--format" => options.format = Some(value.clone()),
+            "--include-sponsor" => options.include_sponsor = Some(parse_value(arg, value)?),
+            "--restrict-sponsor-to-spent" => options.restrict_sponsor_to_spent = Some(parse_value(arg, value)?),
+            "--fund-amount" => options.fund_amount = Some(parse_value(arg, value)?),
+            "--tip-percentage" => options.tip_percentage = Some(parse_value(arg, value)?),
+            _ => return Err(format!("unknown option: {}\n\n{}", arg, USAGE)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value for {}: {}", option, value))
+}
+
+pub(crate) fn parse_csv(contents: &str) -> Result<Vec<Person>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(contents.as_bytes());
+
+    let mut people = Vec::new();
+    for (index, row) in reader.deserialize::<CsvPerson>().enumerate() {
+        // Line 1 is the header
+        let row = row.map_err(|e| format!("line {}: {}", index + 2, e))?;
+        let sponsor_amount = row.sponsor.unwrap_or(0.0);
+        people.push(Person {
+            id: index as u64 + 1,
+            name: row.name,
+            description: row.description.unwrap_or_default(),
+            amount_spent: row.amount,
+            quantity: row.quantity.unwrap_or_else(default_quantity),
+            tip: row.tip.unwrap_or(0.0),
+            is_sponsor: sponsor_amount > 0.0,
+            sponsor_amount,
+            is_receiver: false,
+            paid_by: row.paid_by.filter(|p| !p.is_empty()),
+        });
+    }
+    Ok(people)
+}
+
+fn format_table(response: &CalculateResponse) -> String {
+    let mut out = String::new();
+    out.push_str(&format!(
+        "{:<20} {:>12} {:>10} {:>12} {:>12} {:>12}  {}\n",
+        "NAME", "SPENT", "TIP", "SPONSORED", "SHARE", "BALANCE", "SETTLEMENT"
+    ));
+    for s in &response.settlements {
+        out.push_str(&format!(
+            "{:<20} {:>12.2} {:>10.2} {:>12.2} {:>12.2} {:>12.2}  {}\n",
+            s.name, s.amount_spent, s.tip_paid, s.sponsor_cost, s.share_cost, s.balance, s.settlement_type
+        ));
+    }
+
+    out.push_str(&format!(
+        "\nTotal spent {:.2}, tip {:.2}, sponsored {:.2}, fund {:.2}\n",
+        response.total_spent, response.total_tip, response.total_sponsored, response.fund_amount
+    ));
+    out.push_str(&format!(
+        "{:.2} shared by {} people: {:.2} each\n",
+        response.amount_to_share, response.num_participants, response.per_person_share
+    ));
+
+    let transfers = plan_transfers(&response.settlements);
+    if !transfers.is_empty() {
+        out.push_str("\nTransfers\n");
+        for t in transfers {
+            out.push_str(&format!("  {} pays {} {:.2}\n", t.from, t.to, t.amount));
+        }
+    }
+    out
+}
+
+fn format_csv(response: &CalculateResponse) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(["name", "amount_spent", "tip_paid", "sponsor_cost", "share_cost", "balance", "settlement_type"])
+        .map_err(|e| e.to_string())?;
+    for s in &response.settlements {
+        writer
+            .write_record([
+                s.name.clone(),
+                format!("{:.2}", s.amount_spent),
+                format!("{:.2}", s.tip_paid),
+                format!("{:.2}", s.sponsor_cost),
+                format!("{:.2}", s.share_cost),
+                format!("{:.2}", s.balance),
+                s.settlement_type.clone(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
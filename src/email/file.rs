@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::{EmailMessage, EmailResult, EmailTransport};
+
+// Writes each message to `<dir>/<timestamp>-<id>.json` instead of sending it, with its
+// attachments next to it as `<timestamp>-<id>-<filename>`, and logs where it went. For
+// development and tests.
+pub struct FileTransport {
+    dir: PathBuf,
+}
+
+impl FileTransport {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for FileTransport {
+    async fn send(&self, message: &EmailMessage) -> EmailResult<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let stem = format!("{}-{}", Utc::now().format("%Y%m%dT%H%M%S%.3f"), Uuid::new_v4());
+        for attachment in &message.attachments {
+            // Only the last path component, so a filename can't point outside the directory
+            let filename = attachment.filename.rsplit(['/', '\\']).next().unwrap_or_default();
+            tokio::fs::write(self.dir.join(format!("{}-{}", stem, filename)), &attachment.content).await?;
+        }
+        let path = self.dir.join(format!("{}.json", stem));
+        tokio::fs::write(&path, serde_json::to_vec_pretty(message)?).await?;
+        tracing::info!("Wrote email \"{}\" to {} as {}", message.subject, message.to.join(", "), path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use async_trait::async_trait;
+use resend_rs::{types::{CreateAttachment, CreateEmailBaseOptions}, Resend};
+
+use super::{EmailMessage, EmailResult, EmailTransport};
+
+// Sends through the Resend HTTP API
+pub struct ResendTransport {
+    client: Resend,
+}
+
+impl ResendTransport {
+    pub fn new(api_key: &str) -> Self {
+        Self { client: Resend::new(api_key) }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for ResendTransport {
+    async fn send(&self, message: &EmailMessage) -> EmailResult<()> {
+        let mut email = CreateEmailBaseOptions::new(&message.from, &message.to, &message.subject)
+            .with_html(&message.html);
+
+        if let Some(text) = &message.text {
+            email = email.with_text(text);
+        }
+        for attachment in &message.attachments {
+            email = email.with_attachment(
+                CreateAttachment::from_content(attachment.content.clone())
+                    .with_filename(&attachment.filename)
+                    .with_content_type(&attachment.content_type),
+            );
+        }
+
+        self.client.emails.send(email).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use async_trait::async_trait;
+use lettre::message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use super::{EmailMessage, EmailResult, EmailTransport};
+
+// Sends through an SMTP server: a company relay, or MailHog and the like locally
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    // See lettre's `AsyncSmtpTransport::from_url` for the URL format: credentials in the
+    // userinfo, `smtps://` for implicit TLS and `?tls=required` for STARTTLS
+    pub fn from_url(url: &str) -> EmailResult<Self> {
+        Ok(Self { mailer: AsyncSmtpTransport::<Tokio1Executor>::from_url(url)?.build() })
+    }
+}
+
+#[async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, message: &EmailMessage) -> EmailResult<()> {
+        let mut builder = Message::builder()
+            .from(message.from.parse::<Mailbox>()?)
+            .subject(&message.subject);
+        for to in &message.to {
+            builder = builder.to(to.parse()?);
+        }
+
+        let body = match &message.text {
+            Some(text) => MultiPart::alternative_plain_html(text.clone(), message.html.clone()),
+            None => MultiPart::alternative().singlepart(SinglePart::html(message.html.clone())),
+        };
+        let email = if message.attachments.is_empty() {
+            builder.multipart(body)?
+        } else {
+            let mut mixed = MultiPart::mixed().multipart(body);
+            for attachment in &message.attachments {
+                let content_type = ContentType::parse(&attachment.content_type)?;
+                mixed = mixed.singlepart(Attachment::new(attachment.filename.clone()).body(attachment.content.clone(), content_type));
+            }
+            builder.multipart(mixed)?
+        };
+        self.mailer.send(email).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use split_bills_core::ValidationError;
+use uuid::Uuid;
+
+use crate::models::ErrorResponse;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-ID";
+
+tokio::task_local! {
+    // Set by the request_id middleware for the duration of each request
+    static REQUEST_ID: String;
+}
+
+// Everything a handler can fail with. Responses are JSON (see ErrorResponse) and only
+// carry text that is safe to show: storage errors, provider payloads and anything that
+// could contain a key are logged together with the request id and never sent back.
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest(String),
+    Validation(ValidationError),
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict(String),
+    // The Idempotency-Key was already used for a different request
+    IdempotencyKeyReused,
+    PayloadTooLarge(String),
+    // Too many requests in a short time; seconds until the client may try again
+    RateLimited { retry_after: u64 },
+    // The daily quota is used up; seconds until it resets at midnight UTC
+    QuotaExceeded { retry_after: u64 },
+    // A feature this server has no credentials for, e.g. "AI" or "Email"
+    NotConfigured(&'static str),
+    Storage(sqlx::Error),
+    // Bad stored data, a template that failed to render and the like
+    Internal(String),
+    Ai(String),
+    Email(String),
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) | AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::RateLimited { .. } | AppError::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::NotConfigured(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Storage(e) if storage_unavailable(e) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Storage(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Ai(_) | AppError::Email(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Validation(_) => "validation_failed",
+            AppError::Unauthorized => "unauthorized",
+            AppError::Forbidden => "forbidden",
+            AppError::NotFound => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::PayloadTooLarge(_) => "payload_too_large",
+            AppError::RateLimited { .. } => "rate_limited",
+            AppError::QuotaExceeded { .. } => "quota_exceeded",
+            AppError::IdempotencyKeyReused => "idempotency_key_reused",
+            AppError::NotConfigured(_) => "not_configured",
+            AppError::Storage(e) if storage_unavailable(e) => "storage_unavailable",
+            AppError::Storage(_) => "storage_error",
+            AppError::Internal(_) => "internal_error",
+            AppError::Ai(_) => "ai_provider_error",
+            AppError::Email(_) => "email_provider_error",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            AppError::BadRequest(message) | AppError::Conflict(message) | AppError::PayloadTooLarge(message) => {
+                message.clone()
+            }
+            AppError::Validation(e) => e.to_string(),
+            AppError::Unauthorized => "Sign in to do this".to_string(),
+            AppError::Forbidden => "Missing or wrong credentials for this resource".to_string(),
+            AppError::NotFound => "Not found".to_string(),
+            AppError::RateLimited { .. } => "Too many requests, try again shortly".to_string(),
+            AppError::QuotaExceeded { .. } => "The daily limit for this feature has been reached".to_string(),
+            AppError::IdempotencyKeyReused => {
+                "This Idempotency-Key was already used for a different request".to_string()
+            }
+            AppError::NotConfigured(feature) => format!("{} is not configured on this server", feature),
+            AppError::Storage(e) if storage_unavailable(e) => "Storage is temporarily unavailable".to_string(),
+            AppError::Storage(_) | AppError::Internal(_) => "Internal server error".to_string(),
+            AppError::Ai(_) => "The AI provider could not process the request".to_string(),
+            AppError::Email(_) => "The email provider could not send the message".to_string(),
+        }
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            AppError::Validation(ValidationError::EmptyName { person_id })
+            | AppError::Validation(ValidationError::EmptyPaidBy { person_id }) => {
+                Some(serde_json::json!({ "person_id": person_id }))
+            }
+            AppError::Validation(ValidationError::InvalidNumber { field, person_id }) => {
+                Some(serde_json::json!({ "field": field, "person_id": person_id }))
+            }
+            AppError::RateLimited { retry_after } | AppError::QuotaExceeded { retry_after } => {
+                Some(serde_json::json!({ "retry_after": retry_after }))
+            }
+            _ => None,
+        }
+    }
+
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            AppError::RateLimited { retry_after } | AppError::QuotaExceeded { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::Storage(e) => write!(f, "storage error: {}", e),
+            AppError::Internal(e) => write!(f, "internal error: {}", e),
+            AppError::Ai(e) => write!(f, "AI provider error: {}", e),
+            AppError::Email(e) => write!(f, "email provider error: {}", e),
+            _ => f.write_str(&self.message()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        AppError::Storage(e)
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<askama::Error> for AppError {
+    fn from(e: askama::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<ValidationError> for AppError {
+    fn from(e: ValidationError) -> Self {
+        AppError::Validation(e)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let request_id = current_request_id();
+        let status = self.status();
+        if status.is_server_error() {
+            tracing::error!(request_id = %request_id, "{}", self);
+        }
+
+        let body = ErrorResponse {
+            code: self.code().to_string(),
+            message: self.message(),
+            details: self.details(),
+            request_id,
+        };
+        let mut response = (status, Json(body)).into_response();
+        if let Some(seconds) = self.retry_after() {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
+    }
+}
+
+// A pool that timed out or went away is worth retrying; anything else is a bug or bad data
+fn storage_unavailable(e: &sqlx::Error) -> bool {
+    matches!(e, sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::Io(_))
+}
+
+// The id of the request being handled. Outside the middleware (admin commands, tests)
+// every error gets a fresh one.
+pub fn current_request_id() -> String {
+    REQUEST_ID
+        .try_with(|id| id.clone())
+        .unwrap_or_else(|_| Uuid::new_v4().to_string())
+}
+
+// Give every request an id, reusing the client's X-Request-ID when it sent a sane one,
+// so errors in the logs can be matched to what the user saw. The id is echoed back.
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let id = request.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .filter(|id| valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
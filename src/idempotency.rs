@@ -0,0 +1,263 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::error::AppError;
+use crate::models::{AppState, DbIdempotencyRecord};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+// How long a key is remembered
+pub const IDEMPOTENCY_KEY_HOURS: i64 = 24;
+
+// A request still marked in progress after this long died with the server; its key is
+// given to the next retry
+const IN_PROGRESS_MINUTES: i64 = 5;
+
+// Same as axum's default body limit, which the handlers behind this enforce anyway
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+// Headers that decide what a request is allowed to do, so a key can't be replayed by
+// someone holding different credentials
+const CREDENTIAL_HEADERS: [&str; 3] = ["X-Edit-Secret", "X-Owner-Token", "Authorization"];
+
+// Make mutating requests safe to retry. A request sent with an Idempotency-Key runs once;
+// retries with the same key and the same request get the stored response back, marked
+// with Idempotent-Replayed, and a different request under a used key is rejected.
+// Server errors and 429s aren't stored, so retrying after one runs the request again.
+//
+// Keys belong to the client's credentials, or its address when it sends none, so two
+// clients picking the same key don't get in each other's way. Stored responses are
+// encrypted with a key derived from the Idempotency-Key, which only the client has,
+// because some carry secrets (a new session's edit secret, an access token).
+pub async fn middleware(State(state): State<AppState>, request: Request, next: Next) -> Result<Response, AppError> {
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return Ok(next.run(request).await);
+    }
+    let Some(client_key) = idempotency_key(request.headers())? else {
+        return Ok(next.run(request).await);
+    };
+    let ip = state.limiter.client_ip(&request);
+    let key = record_key(request.headers(), &ip, &client_key);
+    let cipher = body_cipher(&key, &client_key);
+
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|_| AppError::PayloadTooLarge("Request body is too large".to_string()))?;
+    let request_hash = request_hash(&parts.method, &parts.uri, &parts.headers, &body);
+
+    let record = DbIdempotencyRecord {
+        key: key.clone(),
+        request_hash,
+        created_at: Utc::now(),
+        status: None,
+        content_type: None,
+        body: None,
+    };
+    if let Claim::Stored(status, content_type, body) = claim(&state, &record).await? {
+        let body = decrypt(&cipher, &body)
+            .ok_or_else(|| AppError::Internal("failed to decrypt a stored response".to_string()))?;
+        return Ok(replay(status, content_type, body));
+    }
+
+    // Server errors and rate limits say nothing about the request itself, so they
+    // aren't stored and a retry runs again
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if response.status().is_server_error() || response.status() == StatusCode::TOO_MANY_REQUESTS {
+        state.store.delete_idempotency_record(&key).await?;
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            state.store.delete_idempotency_record(&key).await?;
+            return Err(AppError::Internal(format!("failed to read response body: {}", e)));
+        }
+    };
+    let content_type = parts.headers.get(header::CONTENT_TYPE).and_then(|h| h.to_str().ok());
+    state.store
+        .complete_idempotency_record(&key, parts.status.as_u16() as i64, content_type, &encrypt(&cipher, &body))
+        .await?;
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+enum Claim {
+    // The key is ours; run the request
+    Run,
+    // The response the first request got: status, content type and body
+    Stored(i64, Option<String>, String),
+}
+
+// Insert the record, or work out what to do about the one already there
+async fn claim(state: &AppState, record: &DbIdempotencyRecord) -> Result<Claim, AppError> {
+    if state.store.insert_idempotency_record(record).await? {
+        return Ok(Claim::Run);
+    }
+
+    if let Some(existing) = state.store.get_idempotency_record(&record.key).await? {
+        let now = Utc::now();
+        let expired = existing.created_at < now - Duration::hours(IDEMPOTENCY_KEY_HOURS);
+        let abandoned = existing.status.is_none() && existing.created_at < now - Duration::minutes(IN_PROGRESS_MINUTES);
+        if !expired && !abandoned {
+            if existing.request_hash != record.request_hash {
+                return Err(AppError::IdempotencyKeyReused);
+            }
+            return match (existing.status, existing.body) {
+                (Some(status), Some(body)) => Ok(Claim::Stored(status, existing.content_type, body)),
+                _ => Err(in_progress()),
+            };
+        }
+        state.store.delete_idempotency_record(&record.key).await?;
+    }
+
+    // Gone by now, or too old to count; a concurrent retry may still beat us to it
+    if state.store.insert_idempotency_record(record).await? {
+        Ok(Claim::Run)
+    } else {
+        Err(in_progress())
+    }
+}
+
+fn in_progress() -> AppError {
+    AppError::Conflict("A request with this Idempotency-Key is still in progress".to_string())
+}
+
+fn replay(status: i64, content_type: Option<String>, body: Vec<u8>) -> Response {
+    let status = u16::try_from(status)
+        .ok()
+        .and_then(|s| StatusCode::from_u16(s).ok())
+        .unwrap_or(StatusCode::OK);
+    let mut response = (status, body).into_response();
+    let headers = response.headers_mut();
+    match content_type.and_then(|c| HeaderValue::from_str(&c).ok()) {
+        Some(value) => {
+            headers.insert(header::CONTENT_TYPE, value);
+        }
+        None => {
+            headers.remove(header::CONTENT_TYPE);
+        }
+    }
+    headers.insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<String>, AppError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+    match value.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= 255 => Ok(Some(key.to_string())),
+        _ => Err(AppError::BadRequest("Idempotency-Key must be 1 to 255 visible ASCII characters".to_string())),
+    }
+}
+
+// What the record is stored under: the key, scoped to whoever sent it
+pub fn record_key(headers: &HeaderMap, ip: &str, key: &str) -> String {
+    let mut hasher = Sha256::new();
+    let mut field = |bytes: &[u8]| {
+        hasher.update((bytes.len() as u64).to_be_bytes());
+        hasher.update(bytes);
+    };
+
+    field(b"record");
+    let credentials: Vec<&[u8]> = CREDENTIAL_HEADERS
+        .iter()
+        .map(|name| headers.get(*name).map(|h| h.as_bytes()).unwrap_or_default())
+        .collect();
+    if credentials.iter().any(|c| !c.is_empty()) {
+        credentials.iter().for_each(|c| field(c));
+    } else {
+        field(ip.as_bytes());
+    }
+    field(key.as_bytes());
+
+    format!("{:x}", hasher.finalize())
+}
+
+// The record key is stored, so the cipher key also needs the client's own key to derive
+fn body_cipher(record_key: &str, key: &str) -> Aes256Gcm {
+    let mut hasher = Sha256::new();
+    for bytes in [b"body".as_slice(), record_key.as_bytes(), key.as_bytes()] {
+        hasher.update((bytes.len() as u64).to_be_bytes());
+        hasher.update(bytes);
+    }
+    Aes256Gcm::new(&hasher.finalize())
+}
+
+// Base64 of the nonce followed by the ciphertext
+fn encrypt(cipher: &Aes256Gcm, body: &[u8]) -> String {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let mut out = nonce.to_vec();
+    out.extend(cipher.encrypt(&nonce, body).expect("encrypting in memory cannot fail"));
+    STANDARD.encode(out)
+}
+
+fn decrypt(cipher: &Aes256Gcm, stored: &str) -> Option<Vec<u8>> {
+    let bytes = STANDARD.decode(stored).ok()?;
+    if bytes.len() < 12 {
+        return None;
+    }
+    let (nonce, ciphertext) = bytes.split_at(12);
+    cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+}
+
+fn request_hash(method: &Method, uri: &axum::http::Uri, headers: &HeaderMap, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    let mut field = |bytes: &[u8]| {
+        hasher.update((bytes.len() as u64).to_be_bytes());
+        hasher.update(bytes);
+    };
+
+    field(method.as_str().as_bytes());
+    field(uri.path_and_query().map(|p| p.as_str()).unwrap_or("/").as_bytes());
+    for name in CREDENTIAL_HEADERS {
+        field(headers.get(name).map(|h| h.as_bytes()).unwrap_or_default());
+    }
+    // A retried form upload gets a new random boundary, which says nothing about its contents
+    match multipart_boundary(headers) {
+        Some(boundary) => field(&without(body, boundary.as_bytes())),
+        None => field(body),
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+fn multipart_boundary(headers: &HeaderMap) -> Option<String> {
+    let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+    if !content_type.starts_with("multipart/") {
+        return None;
+    }
+    content_type
+        .split(';')
+        .filter_map(|param| param.trim().strip_prefix("boundary="))
+        .map(|boundary| boundary.trim_matches('"').to_string())
+        .find(|boundary| !boundary.is_empty())
+}
+
+fn without(haystack: &[u8], needle: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(haystack.len());
+    let mut rest = haystack;
+    while !rest.is_empty() {
+        if rest.starts_with(needle) {
+            rest = &rest[needle.len()..];
+        } else {
+            out.push(rest[0]);
+            rest = &rest[1..];
+        }
+    }
+    out
+}
@@ -0,0 +1,195 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Deserialize;
+use std::fmt;
+use uuid::Uuid;
+
+use crate::models::*;
+use crate::store::SessionStore;
+
+// Sessions from the old `sessions.json` file, keyed by session id
+#[derive(Deserialize)]
+struct LegacySession {
+    people: Vec<Person>,
+    edit_secret: String,
+    created_at: DateTime<Utc>,
+    #[serde(default)]
+    fund_amount: f64,
+    #[serde(default)]
+    tip_percentage: f64,
+    #[serde(default = "default_include_sponsor")]
+    include_sponsor: bool,
+}
+
+// One entry of the `splitBillsHistory` array that script.js kept in localStorage
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryEntry {
+    timestamp: i64,
+    people: Vec<Person>,
+    #[serde(default)]
+    fund_amount: f64,
+    #[serde(default)]
+    tip_percentage: f64,
+    #[serde(default)]
+    source: Option<String>,
+}
+
+pub struct ImportedSession {
+    pub id: String,
+    pub edit_secret: String,
+    pub people: usize,
+}
+
+#[derive(Default)]
+pub struct ImportReport {
+    pub imported: Vec<ImportedSession>,
+    // Record label and the reason it was skipped
+    pub skipped: Vec<(String, String)>,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Imported {} session(s), skipped {}", self.imported.len(), self.skipped.len())?;
+        for session in &self.imported {
+            writeln!(
+                f,
+                "  imported {} ({} people): {}/?session={}&secret={}",
+                session.id,
+                session.people,
+                crate::base_url(),
+                session.id,
+                session.edit_secret
+            )?;
+        }
+        for (label, reason) in &self.skipped {
+            writeln!(f, "  skipped {}: {}", label, reason)?;
+        }
+        Ok(())
+    }
+}
+
+// Import either format, told apart by shape: `sessions.json` is an object keyed by
+// session id, the history export is an array. Legacy sessions keep their id and edit
+// secret so old share links keep working; history entries never had either, so they
+// get new ones. Imported sessions count as accessed now, otherwise the next cleanup
+// would delete anything older than the expiry window straight away.
+pub async fn import_json(store: &dyn SessionStore, contents: &str, now: DateTime<Utc>) -> Result<ImportReport, String> {
+    let value: serde_json::Value = serde_json::from_str(contents)
+        .map_err(|e| format!("not valid JSON: {}", e))?;
+
+    match value {
+        serde_json::Value::Object(sessions) => Ok(import_legacy_sessions(store, sessions, now).await),
+        serde_json::Value::Array(entries) => Ok(import_history(store, entries, now).await),
+        _ => Err("expected a sessions.json object or a history array".to_string()),
+    }
+}
+
+async fn import_legacy_sessions(
+    store: &dyn SessionStore,
+    sessions: serde_json::Map<String, serde_json::Value>,
+    now: DateTime<Utc>,
+) -> ImportReport {
+    let mut report = ImportReport::default();
+
+    for (id, value) in sessions {
+        let legacy: LegacySession = match serde_json::from_value(value) {
+            Ok(legacy) => legacy,
+            Err(e) => {
+                report.skipped.push((id, format!("unreadable record: {}", e)));
+                continue;
+            }
+        };
+
+        match store.get_session(&id).await {
+            Ok(Some(_)) => {
+                report.skipped.push((id, "already exists".to_string()));
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                report.skipped.push((id, format!("database error: {}", e)));
+                continue;
+            }
+        }
+
+        let session = DbSession {
+            fund_amount: legacy.fund_amount,
+            tip_percentage: legacy.tip_percentage,
+            include_sponsor: legacy.include_sponsor,
+            ..new_session(id, legacy.edit_secret, &legacy.people, legacy.created_at, now)
+        };
+        insert(store, session, &legacy.people, &mut report).await;
+    }
+
+    report
+}
+
+async fn import_history(store: &dyn SessionStore, entries: Vec<serde_json::Value>, now: DateTime<Utc>) -> ImportReport {
+    let mut report = ImportReport::default();
+
+    for (index, value) in entries.into_iter().enumerate() {
+        let label = format!("history entry {}", index + 1);
+        let entry: HistoryEntry = match serde_json::from_value(value) {
+            Ok(entry) => entry,
+            Err(e) => {
+                report.skipped.push((label, format!("unreadable record: {}", e)));
+                continue;
+            }
+        };
+
+        if entry.source.as_deref() == Some("server") {
+            report.skipped.push((label, "already archived on the server".to_string()));
+            continue;
+        }
+        if entry.people.is_empty() {
+            report.skipped.push((label, "no people".to_string()));
+            continue;
+        }
+
+        let created_at = Utc.timestamp_millis_opt(entry.timestamp).single().unwrap_or(now);
+        let session = DbSession {
+            fund_amount: entry.fund_amount,
+            tip_percentage: entry.tip_percentage,
+            ..new_session(Uuid::new_v4().to_string(), Uuid::new_v4().to_string(), &entry.people, created_at, now)
+        };
+        insert(store, session, &entry.people, &mut report).await;
+    }
+
+    report
+}
+
+fn new_session(id: String, edit_secret: String, people: &[Person], created_at: DateTime<Utc>, now: DateTime<Utc>) -> DbSession {
+    DbSession {
+        id,
+        edit_secret,
+        people: serde_json::to_string(people).unwrap_or_default(),
+        created_at,
+        last_accessed_at: now,
+        fund_amount: 0.0,
+        tip_percentage: 0.0,
+        include_sponsor: default_include_sponsor(),
+        expiry_days: None,
+        pinned: false,
+        notification_emails: "[]".to_string(),
+        expiry_warning_sent_at: None,
+        extend_token: None,
+        currency: None,
+        owner_user_id: None,
+    }
+}
+
+async fn insert(store: &dyn SessionStore, session: DbSession, people: &[Person], report: &mut ImportReport) {
+    if let Err(e) = store.insert_session(&session).await {
+        report.skipped.push((session.id, format!("database error: {}", e)));
+        return;
+    }
+    if let Err(e) = crate::sync_participant_links(store, &session.id, people).await {
+        tracing::error!("Failed to create participant links for imported session {}: {}", session.id, e);
+    }
+
+    report.imported.push(ImportedSession {
+        id: session.id,
+        edit_secret: session.edit_secret,
+        people: people.len(),
+    });
+}
@@ -1,33 +1,60 @@
+use askama::Template;
 use axum::{
     extract::{State, Multipart},
     response::IntoResponse,
-    routing::{get, post},
+    routing::{get, post, put},
     Json, Router,
 };
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tower_http::services::ServeDir;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use uuid::Uuid;
-use chrono::Utc;
-use sqlx::{SqlitePool, sqlite::SqlitePoolOptions};
+use chrono::{DateTime, Utc};
+use split_bills_core::{build_ledger, member_balances, net_group_balances, plan_transfers, settle_balances, validate_request};
 
 mod models;
 use models::*;
 
+mod admin;
 mod ai;
 use ai::{AiProvider, OpenAiProvider};
 
+mod calc;
+
 mod email;
+mod error;
+use error::AppError;
+mod idempotency;
 mod image_utils;
+mod import;
+mod openapi;
+mod pdf;
+mod rate_limit;
+mod report;
+
+mod store;
+use store::SessionStore;
+
+mod v2;
+mod versioning;
 
 #[cfg(test)]
 mod tests;
 
 
 
-const SESSION_EXPIRY_DAYS: i64 = 7;
+const DEFAULT_SESSION_EXPIRY_DAYS: i64 = 7;
+const DEFAULT_EXPIRY_WARNING_DAYS: i64 = 2;
+// Ten years; far beyond that the expiry date no longer fits in a timestamp
+const MAX_SESSION_EXPIRY_DAYS: i64 = 3650;
+const MAX_NOTIFICATION_EMAILS: usize = 10;
+const MAX_SYNC_OPERATIONS: usize = 500;
+const LOGIN_TOKEN_MINUTES: i64 = 15;
+const ACCESS_TOKEN_DAYS: i64 = 30;
+const MAX_REMINDER_DELAY_DAYS: i64 = 365;
+const MAX_REMINDER_REPEAT_DAYS: i64 = 90;
 
 #[tokio::main]
 async fn main() {
@@ -39,527 +66,2691 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let db_url = "sqlite:sessions.db?mode=rwc";
-    let pool = SqlitePoolOptions::new()
-        .connect(db_url)
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // The calculator works offline, without touching the database
+    if args.first().map(String::as_str) == Some("calculate") {
+        if let Err(e) = calc::run(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let database_url = std::env::var("DATABASE_URL")
+        .unwrap_or_else(|_| store::DEFAULT_DATABASE_URL.to_string());
+    let store = store::connect(&database_url)
         .await
         .expect("Failed to connect to database");
 
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS sessions (
-            id TEXT PRIMARY KEY,
-            edit_secret TEXT NOT NULL,
-            people TEXT NOT NULL,
-            created_at DATETIME NOT NULL,
-            last_accessed_at DATETIME NOT NULL
-        )
-        "#
-    )
-    .execute(&pool)
-    .await
-    .expect("Failed to create table");
+    let session_expiry_days: i64 = std::env::var("SESSION_EXPIRY_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(DEFAULT_SESSION_EXPIRY_DAYS);
+
+    let expiry_warning_days = expiry_warning_days();
 
-    // Migration: Add fund_amount column if it doesn't exist
-    // We ignore the error because it will fail if the column already exists
-    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN fund_amount REAL DEFAULT 0.0")
-        .execute(&pool)
-        .await;
+    // Any other arguments run an admin command (see admin.rs) instead of the server
+    if !args.is_empty() {
+        if let Err(e) = admin::run(store.as_ref(), &args, session_expiry_days).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN tip_percentage REAL DEFAULT 0.0")
-        .execute(&pool)
-        .await;
+    let email = email::EmailService::from_env()
+        .expect("Invalid email configuration")
+        .map(Arc::new);
 
-    let cleanup_pool = pool.clone();
+    let cleanup_store = store.clone();
+    let cleanup_email = email.clone();
     tokio::spawn(async move {
         loop {
             tokio::time::sleep(tokio::time::Duration::from_secs(3600)).await; // Check every hour
-            cleanup_expired_sessions(&cleanup_pool).await;
+            // Warnings can only go out when email is configured; otherwise expire as before
+            if let Some(email_service) = &cleanup_email {
+                send_expiry_warnings(cleanup_store.as_ref(), email_service, session_expiry_days, expiry_warning_days).await;
+                send_payment_reminders(cleanup_store.as_ref(), email_service, Utc::now()).await;
+            }
+            let warning_days = cleanup_email.is_some().then_some(expiry_warning_days);
+            cleanup_expired_sessions(cleanup_store.as_ref(), session_expiry_days, warning_days).await;
         }
     });
 
     let state = AppState {
-        pool,
-        processed_requests: Arc::new(Mutex::new(HashMap::new())),
+        store,
+        session_expiry_days,
+        limiter: Arc::new(rate_limit::RateLimiter::from_env()),
+        email,
     };
 
-    let app = Router::new()
-        .route("/", get(index))
-        .route("/api/calculate", post(calculate_split))
-        .route("/api/sessions", post(create_session))
-        .route("/api/sessions/:id", get(get_session).put(update_session))
-        .route("/api/ai/text", post(process_ai_text))
-        .route("/api/ai/split", post(process_ai_split_text))
-        .route("/api/ai/image", post(process_ai_image))
-        .route("/api/email", post(send_email_handler))
-        .nest_service("/static", ServeDir::new("static"))
-        .with_state(state);
-
     let listener = tokio::net::TcpListener::bind("0.0.0.0:7777")
         .await
         .unwrap();
     
     tracing::info!("Server running on http://0.0.0.0:7777");
     
-    axum::serve(listener, app).await.unwrap();
+    // The peer address is what the rate limits count per IP
+    axum::serve(listener, app(state).into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .unwrap();
 }
 
-async fn cleanup_expired_sessions(pool: &SqlitePool) {
-    let threshold = Utc::now() - chrono::Duration::days(SESSION_EXPIRY_DAYS);
-    let result = sqlx::query("DELETE FROM sessions WHERE last_accessed_at < ?")
-        .bind(threshold)
-        .execute(pool)
-        .await;
-    
-    match result {
-        Ok(r) => {
-            if r.rows_affected() > 0 {
-                tracing::info!("Cleaned up {} expired sessions", r.rows_affected());
+fn app(state: AppState) -> Router {
+    Router::new()
+        .route("/", get(index))
+        .nest("/api/v1", api_v1(&state))
+        // v2 only overrides what changed; the rest falls through to v1
+        .nest("/api/v2", v2::routes().fallback_service(api_v1(&state).with_state(state.clone())))
+        .nest(
+            "/api",
+            api_v1(&state).layer(axum::middleware::from_fn_with_state(
+                versioning::UNVERSIONED_API,
+                versioning::deprecation_headers,
+            )),
+        )
+        .route("/api/openapi.json", get(openapi::openapi_json))
+        .route("/api/docs", get(openapi::api_docs))
+        .nest_service("/static", ServeDir::new("static"))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), idempotency::middleware))
+        .layer(axum::middleware::from_fn(error::request_id))
+        .with_state(state)
+}
+
+// The API as of v1, also served unversioned under /api for older clients
+fn api_v1(state: &AppState) -> Router<AppState> {
+    // These call paid APIs with the server's keys
+    let ai = Router::new()
+        .route("/ai/text", post(process_ai_text))
+        .route("/ai/split", post(process_ai_split_text))
+        .route("/ai/image", post(process_ai_image))
+        .route_layer(axum::middleware::from_fn_with_state(
+            (state.clone(), rate_limit::Feature::Ai),
+            rate_limit::limit,
+        ));
+    let email = Router::new()
+        .route("/sessions/:id/email", post(send_email_handler))
+        .route("/auth/login", post(request_login))
+        .route_layer(axum::middleware::from_fn_with_state(
+            (state.clone(), rate_limit::Feature::Email),
+            rate_limit::limit,
+        ));
+
+    Router::new()
+        .route("/calculate", post(calculate_split))
+        .route("/sessions", post(create_session))
+        .route("/sessions/:id", get(get_session).put(update_session).delete(delete_session))
+        .route("/sessions/:id/sync", post(sync_session))
+        .route("/sessions/:id/expiry", put(update_session_expiry))
+        .route("/sessions/:id/notifications", get(get_session_notifications).put(update_session_notifications))
+        .route("/sessions/:id/extend", get(extend_session))
+        .route("/sessions/:id/report", get(download_report))
+        .route("/sessions/:id/reminders", get(get_reminders).put(update_reminders).delete(delete_reminders))
+        .route("/reminders/:token/paid", get(reminder_paid_page).post(mark_reminder_paid))
+        .route("/sessions/:id/archive", post(archive_session))
+        .route("/archives", get(list_archives))
+        .route("/archives/:id", get(get_archive).delete(delete_archive))
+        .route("/archives/:id/restore", post(restore_archive))
+        .route("/archives/:id/clone", post(clone_archive))
+        .route("/templates", get(list_templates).post(create_template))
+        .route("/groups", post(create_group))
+        .route("/groups/:id", get(get_group).put(update_group))
+        .route("/groups/:id/sessions", post(add_group_session))
+        .route("/groups/:id/sessions/:session_id", axum::routing::delete(remove_group_session))
+        .route("/groups/:id/ledger", get(get_group_ledger))
+        .route("/auth/verify", post(verify_login))
+        .route("/auth/logout", post(logout))
+        .route("/me", get(get_me))
+        .route("/me/sessions", get(list_my_sessions).post(claim_session))
+        .route("/me/groups", get(list_my_groups).post(claim_group))
+        .route("/templates/:id", get(get_template).put(update_template).delete(delete_template))
+        .route("/sessions/:id/participants", get(list_participant_links).put(update_participant_emails))
+        .route("/participants/:token", get(get_participant_view))
+        .route("/participants/:token/claim", post(claim_participant))
+        .merge(ai)
+        .merge(email)
+}
+
+fn expiry_warning_days() -> i64 {
+    std::env::var("SESSION_EXPIRY_WARNING_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|days| (0..=MAX_SESSION_EXPIRY_DAYS).contains(days))
+        .unwrap_or(DEFAULT_EXPIRY_WARNING_DAYS)
+}
+
+// `warning_days` is None when no warnings can be sent
+async fn cleanup_expired_sessions(store: &dyn SessionStore, default_expiry_days: i64, warning_days: Option<i64>) {
+    let now = Utc::now();
+    match store.delete_expired_sessions(default_expiry_days, warning_days, now).await {
+        Ok(deleted) => {
+            if deleted > 0 {
+                tracing::info!("Cleaned up {} expired sessions", deleted);
             }
         }
         Err(e) => tracing::error!("Failed to cleanup sessions: {}", e),
     }
+
+    if let Err(e) = store.delete_expired_tokens(now).await {
+        tracing::error!("Failed to cleanup login tokens: {}", e);
+    }
+
+    let cutoff = now - chrono::Duration::hours(idempotency::IDEMPOTENCY_KEY_HOURS);
+    if let Err(e) = store.delete_idempotency_records_before(cutoff).await {
+        tracing::error!("Failed to cleanup idempotency keys: {}", e);
+    }
+
+    // A week of usage is kept for the `usage` admin command
+    if let Err(e) = store.delete_daily_usage_before(now.date_naive() - chrono::Duration::days(7)).await {
+        tracing::error!("Failed to cleanup daily usage: {}", e);
+    }
+}
+
+async fn send_expiry_warnings(
+    store: &dyn SessionStore,
+    email_service: &email::EmailService,
+    default_expiry_days: i64,
+    warning_days: i64,
+) {
+    // Not yet warned since the last access, and within `warning_days` of expiring
+    let sessions = match store.sessions_due_for_warning(default_expiry_days, warning_days, Utc::now()).await {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            tracing::error!("Failed to load sessions for expiry warnings: {}", e);
+            return;
+        }
+    };
+
+    if sessions.is_empty() {
+        return;
+    }
+
+    let base_url = base_url();
+
+    for session in sessions {
+        let emails: Vec<String> = serde_json::from_str(&session.notification_emails).unwrap_or_default();
+        let people: Vec<Person> = serde_json::from_str(&session.people).unwrap_or_default();
+        let Some(expires_at) = session_expires_at(&session, default_expiry_days) else {
+            continue;
+        };
+        // Sessions are kept warning_days after the warning, but no longer than warning_days
+        // past their expiry (see delete_expired_sessions), so a late warning shows the later date
+        let now = Utc::now();
+        let warning = chrono::Duration::days(warning_days);
+        let deleted_at = expires_at.max((now + warning).min(expires_at + warning));
+        if deleted_at <= now {
+            continue;
+        }
+
+        let mut names: Vec<&str> = people.iter().map(|p| p.name.as_str()).collect();
+        names.sort();
+        names.dedup();
+        let total_spent: f64 = people.iter().map(|p| p.amount_spent * p.quantity as f64 + p.tip).sum();
+
+        let extend_token = Uuid::new_v4().to_string();
+        let template = ExpiryWarningTemplate {
+            num_participants: names.len(),
+            total_spent: format!("{:.2}", total_spent),
+            expires_at: deleted_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+            view_url: format!("{}/?session={}", base_url, session.id),
+            extend_url: format!("{}/api/v1/sessions/{}/extend?token={}", base_url, session.id, extend_token),
+        };
+        let html = match template.render() {
+            Ok(html) => html,
+            Err(e) => {
+                tracing::error!("Failed to render expiry warning for session {}: {}", session.id, e);
+                continue;
+            }
+        };
+
+        if let Err(e) = email_service.send_email(emails, "Your split is about to expire", &html, Default::default()).await {
+            tracing::error!("Failed to send expiry warning for session {}: {}", session.id, e);
+            continue;
+        }
+
+        match store.record_expiry_warning(&session.id, &extend_token, now).await {
+            Ok(_) => tracing::info!("Sent expiry warning for session {}", session.id),
+            Err(e) => tracing::error!("Failed to record expiry warning for session {}: {}", session.id, e),
+        }
+    }
+}
+
+// Remind everyone who still owes money in sessions with a reminder schedule, from
+// first_after_days after the session was created and then every repeat_days, until the
+// debt is marked paid. The people they pay get a note of their own so they can mark it
+// paid too; the reminder itself links to the debtor's personal page, so it isn't copied.
+// Marking paid covers the debt as it was reminded: once edits change what the debtor owes
+// or to whom, they are reminded again straight away.
+async fn send_payment_reminders(store: &dyn SessionStore, email_service: &email::EmailService, now: DateTime<Utc>) {
+    let schedules = match store.reminder_schedules().await {
+        Ok(schedules) => schedules,
+        Err(e) => {
+            tracing::error!("Failed to load reminder schedules: {}", e);
+            return;
+        }
+    };
+
+    for schedule in schedules {
+        if let Err(e) = send_session_reminders(store, email_service, &schedule, now).await {
+            tracing::error!("Failed to send payment reminders for session {}: {}", schedule.session_id, e);
+        }
+    }
+}
+
+async fn send_session_reminders(
+    store: &dyn SessionStore,
+    email_service: &email::EmailService,
+    schedule: &DbReminderSchedule,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let Some(session) = store.get_session(&schedule.session_id).await? else {
+        return Ok(());
+    };
+    if now < session.created_at + chrono::Duration::days(schedule.first_after_days) {
+        return Ok(());
+    }
+
+    let people: Vec<Person> = serde_json::from_str(&session.people)?;
+    sync_participant_links(store, &session.id, &people).await?;
+    let links = store.participant_links_for_session(&session.id).await?;
+    let reminders = store.payment_reminders_for_session(&session.id).await?;
+
+    let result = split_bills_core::calculate_split(CalculateRequest {
+        people,
+        include_sponsor: session.include_sponsor,
+        restrict_sponsor_to_spent: Some(true),
+        fund_amount: session.fund_amount,
+        tip_percentage: session.tip_percentage,
+    });
+    let transfers = plan_transfers(&result.settlements);
+    let currency = session.currency.as_deref();
+    let base_url = base_url();
+
+    for settlement in result.settlements.iter().filter(|s| s.balance < -0.01) {
+        let Some(link) = links.iter().find(|link| link.name == settlement.name) else {
+            continue;
+        };
+        let Some(address) = &link.email else {
+            continue;
+        };
+        let existing = reminders.iter().find(|r| r.name == settlement.name);
+        let owed: Vec<_> = transfers.iter().filter(|t| t.from == settlement.name).cloned().collect();
+        let due = match existing {
+            None => true,
+            Some(reminder) if reminder.paid_at.is_some() => !same_debt(reminder, -settlement.balance, &owed),
+            Some(reminder) => {
+                schedule.repeat_days > 0 && reminder.last_sent_at + chrono::Duration::days(schedule.repeat_days) <= now
+            }
+        };
+        if !due {
+            continue;
+        }
+
+        let token = existing.map_or_else(|| Uuid::new_v4().to_string(), |r| r.token.clone());
+        let paid_url = format!("{}/api/v1/reminders/{}/paid", base_url, token);
+        let t = email::EmailStrings::for_language(Language::from_code(link.language.as_deref()));
+        let html = PaymentReminderTemplate {
+            t,
+            name: settlement.name.clone(),
+            amount: email::format_money(-settlement.balance, currency),
+            transfers: owed
+                .iter()
+                .map(|transfer| SettlementEmailTransfer {
+                    pays: true,
+                    other: transfer.to.clone(),
+                    amount: email::format_money(transfer.amount, currency),
+                })
+                .collect(),
+            view_url: format!("{}/?me={}", base_url, link.token),
+            paid_url: paid_url.clone(),
+        }.render()?;
+
+        if let Err(e) = email_service.send_email(vec![address.clone()], t.reminder_subject, &html, Default::default()).await {
+            tracing::error!("Failed to send payment reminder to {} in session {}: {}", settlement.name, session.id, e);
+            continue;
+        }
+
+        for transfer in &owed {
+            let Some(creditor) = links.iter().find(|l| l.name == transfer.to) else {
+                continue;
+            };
+            let Some(creditor_address) = creditor.email.as_ref().filter(|e| *e != address) else {
+                continue;
+            };
+            let t = email::EmailStrings::for_language(Language::from_code(creditor.language.as_deref()));
+            let html = PaymentReminderCreditorTemplate {
+                t,
+                name: creditor.name.clone(),
+                debtor: settlement.name.clone(),
+                amount: email::format_money(transfer.amount, currency),
+                paid_url: paid_url.clone(),
+            }.render()?;
+            if let Err(e) = email_service.send_email(vec![creditor_address.clone()], t.reminder_creditor_subject, &html, Default::default()).await {
+                tracing::error!("Failed to tell {} about the payment reminder in session {}: {}", creditor.name, session.id, e);
+            }
+        }
+
+        store.record_payment_reminder(&DbPaymentReminder {
+            token,
+            session_id: session.id.clone(),
+            name: settlement.name.clone(),
+            amount: -settlement.balance,
+            transfers: serde_json::to_string(&owed)?,
+            reminders_sent: existing.map_or(0, |r| r.reminders_sent) + 1,
+            last_sent_at: now,
+            paid_at: None,
+        }).await?;
+        tracing::info!("Sent payment reminder to {} in session {}", settlement.name, session.id);
+    }
+    Ok(())
+}
+
+// Whether a reminder covers what the debtor owes now, to the cent and to the same people
+fn same_debt(reminder: &DbPaymentReminder, amount: f64, owed: &[Transfer]) -> bool {
+    let reminded: Vec<Transfer> = serde_json::from_str(&reminder.transfers).unwrap_or_default();
+    (reminder.amount - amount).abs() < 0.01
+        && reminded.len() == owed.len()
+        && reminded.iter().zip(owed).all(|(a, b)| a.to == b.to && (a.amount - b.amount).abs() < 0.01)
+}
+
+fn base_url() -> String {
+    std::env::var("BASE_URL")
+        .unwrap_or_else(|_| "https://billsplitter.ddoffy.org".to_string())
 }
 
 async fn index() -> impl IntoResponse {
-    let base_url = std::env::var("BASE_URL")
-        .unwrap_or_else(|_| "https://billsplitter.ddoffy.org".to_string());
-    IndexTemplate { base_url }
+    IndexTemplate { base_url: base_url() }
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/sessions",
+    tag = "sessions",
+    summary = "Create a shared session",
+    params(CreateSessionQuery),
+    request_body = Option<CreateSessionRequest>,
+    security((), ("bearer" = [])),
+    responses(
+        (status = 200, body = CreateSessionResponse),
+        (status = 400, description = "Neither a body nor a template, or expiry_days is too long", body = ErrorResponse),
+        (status = 404, description = "No such template", body = ErrorResponse),
+    )
+)]
 async fn create_session(
     State(state): State<AppState>,
-    Json(request): Json<CreateSessionRequest>,
-) -> Json<CreateSessionResponse> {
+    axum::extract::Query(query): axum::extract::Query<CreateSessionQuery>,
+    headers: axum::http::HeaderMap,
+    request: Option<Json<CreateSessionRequest>>,
+) -> Result<Json<CreateSessionResponse>, AppError> {
+    // Signed-in users own what they create; anonymous sessions work as before
+    let user = current_user(state.store.as_ref(), &headers).await?;
+    let request = match (query.template, request) {
+        // The template supplies the options; people in the body are added to its lines
+        (Some(template_id), request) => {
+            let template = fetch_template(state.store.as_ref(), &template_id).await?;
+            let template: SessionTemplate = serde_json::from_str(&template.body)?;
+            let extra = request.map(|Json(r)| r.people).unwrap_or_default();
+            instantiate_template(&template, extra, Utc::now().timestamp_millis() as u64)
+        }
+        (None, Some(Json(request))) => request,
+        (None, None) => return Err(AppError::BadRequest("Send the session in the body or pick a template".to_string())),
+    };
+    if matches!(request.expiry_days, Some(days) if days > MAX_SESSION_EXPIRY_DAYS) {
+        return Err(AppError::BadRequest(format!("expiry_days must be at most {}", MAX_SESSION_EXPIRY_DAYS)));
+    }
+
     let id = Uuid::new_v4().to_string();
     let edit_secret = Uuid::new_v4().to_string();
-    let now = Utc::now();
-    let people_json = serde_json::to_string(&request.people).unwrap_or_default();
-    
-    sqlx::query(
-        "INSERT INTO sessions (id, edit_secret, people, created_at, last_accessed_at, fund_amount, tip_percentage) VALUES (?, ?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&id)
-    .bind(&edit_secret)
-    .bind(&people_json)
-    .bind(now)
-    .bind(now)
-    .bind(request.fund_amount)
-    .bind(request.tip_percentage)
-    .execute(&state.pool)
-    .await
-    .unwrap();
-    
-    Json(CreateSessionResponse {
+
+    insert_session(state.store.as_ref(), &id, &edit_secret, &request, user.as_ref().map(|u| u.id.as_str()))
+        .await?;
+
+    Ok(Json(CreateSessionResponse {
         id,
         edit_secret,
-    })
+    }))
+}
+
+async fn insert_session(
+    store: &dyn SessionStore,
+    id: &str,
+    edit_secret: &str,
+    request: &CreateSessionRequest,
+    owner_user_id: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let people_json = serde_json::to_string(&request.people).unwrap_or_default();
+
+    store.insert_session(&DbSession {
+        id: id.to_string(),
+        edit_secret: edit_secret.to_string(),
+        people: people_json,
+        created_at: now,
+        last_accessed_at: now,
+        fund_amount: request.fund_amount,
+        tip_percentage: request.tip_percentage,
+        include_sponsor: request.include_sponsor,
+        expiry_days: request.expiry_days.filter(|days| *days > 0),
+        pinned: request.pinned,
+        notification_emails: "[]".to_string(),
+        expiry_warning_sent_at: None,
+        extend_token: None,
+        currency: request.currency.clone(),
+        owner_user_id: owner_user_id.map(|id| id.to_string()),
+    }).await?;
+
+    if let Err(e) = sync_participant_links(store, id, &request.people).await {
+        tracing::error!("Failed to create participant links: {}", e);
+    }
+    Ok(())
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/sessions/{id}",
+    tag = "sessions",
+    summary = "Load a session",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = GetSessionResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
 async fn get_session(
     State(state): State<AppState>,
     axum::extract::Path(id): axum::extract::Path<String>,
-) -> Result<Json<GetSessionResponse>, axum::http::StatusCode> {
+) -> Result<Json<GetSessionResponse>, AppError> {
     let now = Utc::now();
     
-    let update_result = sqlx::query("UPDATE sessions SET last_accessed_at = ? WHERE id = ?")
-        .bind(now)
-        .bind(&id)
-        .execute(&state.pool)
-        .await;
+    state.store.touch_session(&id, now).await?;
 
-    if let Err(_) = update_result {
-        return Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
-    }
-
-    let row: Option<DbSession> = sqlx::query_as("SELECT * FROM sessions WHERE id = ?")
-        .bind(&id)
-        .fetch_optional(&state.pool)
-        .await
-        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let row = state.store.get_session(&id).await?;
 
     if let Some(session) = row {
-        let people: Vec<Person> = serde_json::from_str(&session.people)
-            .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-            
-        Ok(Json(GetSessionResponse {
-            people,
-            fund_amount: session.fund_amount,
-            tip_percentage: session.tip_percentage,
-        }))
+        Ok(Json(session_response(session, state.session_expiry_days)?))
     } else {
-        Err(axum::http::StatusCode::NOT_FOUND)
+        Err(AppError::NotFound)
     }
 }
 
+// Also used by the admin commands, so their JSON matches the API
+fn session_response(session: DbSession, default_expiry_days: i64) -> Result<GetSessionResponse, serde_json::Error> {
+    let people: Vec<Person> = serde_json::from_str(&session.people)?;
+    Ok(GetSessionResponse {
+        people,
+        fund_amount: session.fund_amount,
+        tip_percentage: session.tip_percentage,
+        include_sponsor: session.include_sponsor,
+        pinned: session.pinned,
+        expires_at: session_expires_at(&session, default_expiry_days),
+        currency: session.currency,
+    })
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/sessions/{id}",
+    tag = "sessions",
+    summary = "Replace the contents of a session",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = UpdateSessionRequest,
+    security(("edit_secret" = [])),
+    responses(
+        (status = 200, description = "Saved", body = serde_json::Value, example = json!({"success": true})),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
 async fn update_session(
     State(state): State<AppState>,
     axum::extract::Path(id): axum::extract::Path<String>,
     headers: axum::http::HeaderMap,
     Json(request): Json<UpdateSessionRequest>,
-) -> Result<Json<serde_json::Value>, axum::http::StatusCode> {
+) -> Result<Json<serde_json::Value>, AppError> {
     let secret_header = headers.get("X-Edit-Secret")
         .and_then(|h| h.to_str().ok());
         
     if let Some(secret) = secret_header {
-        let row: Option<(String,)> = sqlx::query_as("SELECT edit_secret FROM sessions WHERE id = ?")
-            .bind(&id)
-            .fetch_optional(&state.pool)
-            .await
-            .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        let row = state.store.get_session(&id).await?;
             
-        if let Some((stored_secret,)) = row {
-            if stored_secret == secret {
-                let people_json = serde_json::to_string(&request.people)
-                    .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-                let now = Utc::now();
+        if let Some(mut session) = row {
+            if session.edit_secret == secret {
+                session.people = serde_json::to_string(&request.people)?;
+                session.fund_amount = request.fund_amount;
+                session.tip_percentage = request.tip_percentage;
+                session.include_sponsor = request.include_sponsor;
+                // Older clients don't send a currency; keep the stored one
+                if request.currency.is_some() {
+                    session.currency = request.currency.clone();
+                }
+                session.last_accessed_at = Utc::now();
                 
-                sqlx::query("UPDATE sessions SET people = ?, fund_amount = ?, tip_percentage = ?, last_accessed_at = ? WHERE id = ?")
-                    .bind(people_json)
-                    .bind(request.fund_amount)
-                    .bind(request.tip_percentage)
-                    .bind(now)
-                    .bind(&id)
-                    .execute(&state.pool)
-                    .await
-                    .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+                state.store.update_session_contents(&session).await?;
+
+                sync_participant_links(state.store.as_ref(), &id, &request.people).await?;
+
+                let balances = member_balances(CalculateRequest {
+                    people: request.people,
+                    include_sponsor: request.include_sponsor,
+                    restrict_sponsor_to_spent: Some(true),
+                    fund_amount: request.fund_amount,
+                    tip_percentage: request.tip_percentage,
+                });
+                refresh_group_snapshots(state.store.as_ref(), &id, &balances).await?;
                     
                 return Ok(Json(serde_json::json!({"success": true})));
             } else {
-                return Err(axum::http::StatusCode::FORBIDDEN);
+                return Err(AppError::Forbidden);
             }
         } else {
-            return Err(axum::http::StatusCode::NOT_FOUND);
+            return Err(AppError::NotFound);
         }
     }
-    Err(axum::http::StatusCode::FORBIDDEN)
+    Err(AppError::Forbidden)
 }
 
-async fn calculate_split(Json(request): Json<CalculateRequest>) -> Json<CalculateResponse> {
-    Json(calculate_split_internal(request))
-}
+// Apply a batch of edits a client made while offline. Replayed operations are
+// ignored, and for each expense or participant the change with the latest client
+// timestamp wins, whatever order the batches arrive in.
+#[utoipa::path(
+    post,
+    path = "/api/v1/sessions/{id}/sync",
+    tag = "sessions",
+    summary = "Merge a batch of offline edits",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = SyncRequest,
+    security(("edit_secret" = [])),
+    responses(
+        (status = 200, body = SyncResponse),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+        (status = 413, description = "Too many operations", body = ErrorResponse),
+    )
+)]
+async fn sync_session(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+    Json(request): Json<SyncRequest>,
+) -> Result<Json<SyncResponse>, AppError> {
+    if request.operations.len() > MAX_SYNC_OPERATIONS {
+        return Err(AppError::PayloadTooLarge(format!("At most {} operations per sync", MAX_SYNC_OPERATIONS)));
+    }
 
-fn calculate_split_internal(request: CalculateRequest) -> CalculateResponse {
-    let people = request.people;
-    let include_sponsor = request.include_sponsor;
-    let _restrict_sponsor = request.restrict_sponsor_to_spent.unwrap_or(true);
-    let fund_amount = request.fund_amount;
-    let tip_percentage = request.tip_percentage;
+    let mut session = authorize_session(state.store.as_ref(), &id, &headers).await?;
+    let mut people: Vec<Person> = serde_json::from_str(&session.people)?;
+    let history = state.store.sync_operations(&id).await?;
+
+    let now = Utc::now();
+    let outcome = apply_sync_operations(&id, &mut people, &history, request.operations, now);
 
-    // Group people by name to handle multiple entries for the same person
-    let mut grouped_people: HashMap<String, PersonSummary> = HashMap::new();
+    if !outcome.records.is_empty() {
+        session.people = serde_json::to_string(&people)?;
+        session.last_accessed_at = now;
+        state.store.update_session_contents(&session).await?;
+        state.store.insert_sync_operations(&outcome.records).await?;
 
-    for person in &people {
-        let entry = grouped_people.entry(person.name.clone()).or_insert(PersonSummary {
-            name: person.name.clone(),
-            amount_spent: 0.0,
-            tip: 0.0,
-            sponsor_amount: 0.0,
-            is_sponsor: false,
-            is_receiver: false,
-            will_receive_from_others: 0.0,
-            owes_to_others: 0.0,
-            delegated_self: 0.0,
+        sync_participant_links(state.store.as_ref(), &id, &people).await?;
+        let balances = member_balances(CalculateRequest {
+            people,
+            include_sponsor: session.include_sponsor,
+            restrict_sponsor_to_spent: Some(true),
+            fund_amount: session.fund_amount,
+            tip_percentage: session.tip_percentage,
         });
+        refresh_group_snapshots(state.store.as_ref(), &id, &balances).await?;
+    }
+
+    Ok(Json(SyncResponse {
+        session: session_response(session, state.session_expiry_days)?,
+        applied: outcome.applied,
+        rejected: outcome.rejected,
+    }))
+}
+
+struct SyncOutcome {
+    applied: Vec<String>,
+    rejected: Vec<RejectedOperation>,
+    // Newly applied operations, to remember for later syncs
+    records: Vec<DbSyncOperation>,
+}
+
+// Operations run in (client_timestamp, op_id) order. One that is older than the last
+// applied change to the same target is rejected, so every device ends up with the
+// same result no matter which batch reaches the server first.
+fn apply_sync_operations(
+    session_id: &str,
+    people: &mut Vec<Person>,
+    history: &[DbSyncOperation],
+    mut operations: Vec<SyncOperation>,
+    now: DateTime<Utc>,
+) -> SyncOutcome {
+    let mut latest: HashMap<String, (i64, String)> = HashMap::new();
+    for op in history {
+        let version = (op.client_timestamp, op.op_id.clone());
+        let entry = latest.entry(op.target.clone()).or_insert_with(|| version.clone());
+        if version > *entry {
+            *entry = version;
+        }
+    }
+    let mut seen: std::collections::HashSet<String> = history.iter().map(|op| op.op_id.clone()).collect();
+
+    operations.sort_by(|a, b| (a.client_timestamp, &a.op_id).cmp(&(b.client_timestamp, &b.op_id)));
 
-        // Handle sponsor status
-        entry.sponsor_amount += person.sponsor_amount;
-        if person.is_sponsor {
-            entry.is_sponsor = true;
+    let mut outcome = SyncOutcome { applied: Vec::new(), rejected: Vec::new(), records: Vec::new() };
+    for op in operations {
+        if seen.contains(&op.op_id) {
+            if !outcome.applied.contains(&op.op_id) {
+                outcome.applied.push(op.op_id);
+            }
+            continue;
         }
-        if person.is_receiver {
-            entry.is_receiver = true;
+
+        let target = sync_target(&op.change);
+        let version = (op.client_timestamp, op.op_id.clone());
+        if latest.get(&target).is_some_and(|newest| *newest > version) {
+            outcome.rejected.push(RejectedOperation {
+                op_id: op.op_id,
+                reason: "superseded by a newer change".to_string(),
+            });
+            continue;
         }
 
-        // Track expenses with "paid_by" set
-        if let Some(ref payer_name) = person.paid_by {
-            let total_expense = (person.amount_spent * person.quantity as f64) + person.tip;
-            
-            if payer_name == &person.name {
-                // Self-payment: This is a private expense, exclude from shared pool
-                entry.delegated_self += total_expense;
-                // Also add to amount_spent since they're paying for it
-                entry.amount_spent += person.amount_spent * person.quantity as f64;
-                entry.tip += person.tip;
-            } else {
-                // Someone else will reimburse this person for this expense
-                // The current person (who actually paid) will receive reimbursement
-                entry.will_receive_from_others += total_expense;
-                // DON'T add to amount_spent - it's offset by the reimbursement
-                
-                // The designated payer owes this amount
-                let payer_entry = grouped_people.entry(payer_name.clone()).or_insert(PersonSummary {
-                    name: payer_name.clone(),
+        match apply_sync_change(people, op.change) {
+            Ok(()) => {
+                latest.insert(target.clone(), version);
+                seen.insert(op.op_id.clone());
+                outcome.records.push(DbSyncOperation {
+                    session_id: session_id.to_string(),
+                    op_id: op.op_id.clone(),
+                    target,
+                    client_timestamp: op.client_timestamp,
+                    applied_at: now,
+                });
+                outcome.applied.push(op.op_id);
+            }
+            Err(reason) => outcome.rejected.push(RejectedOperation { op_id: op.op_id, reason }),
+        }
+    }
+    outcome
+}
+
+fn sync_target(change: &SyncChange) -> String {
+    match change {
+        SyncChange::AddExpense { expense } | SyncChange::EditExpense { expense } => format!("expense:{}", expense.id),
+        SyncChange::DeleteExpense { expense_id } => format!("expense:{}", expense_id),
+        SyncChange::AddParticipant { name }
+        | SyncChange::EditParticipant { name, .. }
+        | SyncChange::DeleteParticipant { name } => format!("participant:{}", name.trim()),
+    }
+}
+
+fn apply_sync_change(people: &mut Vec<Person>, change: SyncChange) -> Result<(), String> {
+    match change {
+        // Writing a line that was deleted brings it back, so a newer edit beats an older delete
+        SyncChange::AddExpense { expense } | SyncChange::EditExpense { expense } => {
+            validate_request(&CalculateRequest {
+                people: vec![expense.clone()],
+                include_sponsor: true,
+                restrict_sponsor_to_spent: None,
+                fund_amount: 0.0,
+                tip_percentage: 0.0,
+            })
+            .map_err(|e| e.to_string())?;
+
+            match people.iter_mut().find(|p| p.id == expense.id) {
+                Some(existing) => *existing = expense,
+                None => people.push(expense),
+            }
+        }
+        // Deleting something already gone is fine: the end result is the same
+        SyncChange::DeleteExpense { expense_id } => people.retain(|p| p.id != expense_id),
+        SyncChange::AddParticipant { name } => {
+            let name = name.trim().to_string();
+            if name.is_empty() {
+                return Err("participant name is empty".to_string());
+            }
+            if !people.iter().any(|p| p.name == name) {
+                let id = people.iter().map(|p| p.id).max().unwrap_or(0) + 1;
+                people.push(Person {
+                    id,
+                    name,
+                    description: String::new(),
                     amount_spent: 0.0,
+                    quantity: 1,
                     tip: 0.0,
-                    sponsor_amount: 0.0,
                     is_sponsor: false,
+                    sponsor_amount: 0.0,
                     is_receiver: false,
-                    will_receive_from_others: 0.0,
-                    owes_to_others: 0.0,
-                    delegated_self: 0.0,
+                    paid_by: None,
                 });
-                payer_entry.owes_to_others += total_expense;
             }
-        } else {
-            // No paid_by: Normal shared expense
-            entry.amount_spent += person.amount_spent * person.quantity as f64;
-            entry.tip += person.tip;
+        }
+        // Renames every line of the participant and every line they pay for
+        SyncChange::EditParticipant { name, new_name } => {
+            let (name, new_name) = (name.trim(), new_name.trim());
+            if new_name.is_empty() {
+                return Err("participant name is empty".to_string());
+            }
+            if !people.iter().any(|p| p.name == name) {
+                return Err(format!("no participant named {}", name));
+            }
+            for person in people.iter_mut() {
+                if person.name == name {
+                    person.name = new_name.to_string();
+                }
+                if person.paid_by.as_deref() == Some(name) {
+                    person.paid_by = Some(new_name.to_string());
+                }
+            }
+        }
+        // Lines they were paying for go back to the shared pool
+        SyncChange::DeleteParticipant { name } => {
+            let name = name.trim();
+            people.retain(|p| p.name != name);
+            for person in people.iter_mut() {
+                if person.paid_by.as_deref() == Some(name) {
+                    person.paid_by = None;
+                }
+            }
         }
     }
+    Ok(())
+}
 
-    // Convert map to vector for processing
-    let unique_people: Vec<PersonSummary> = grouped_people.into_values().collect();
-
-    let tip_multiplier = 1.0 + (tip_percentage / 100.0);
+#[utoipa::path(
+    delete,
+    path = "/api/v1/sessions/{id}",
+    tag = "sessions",
+    summary = "Delete a session",
+    params(("id" = String, Path, description = "Session id")),
+    security(("edit_secret" = [])),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
+async fn delete_session(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+) -> Result<axum::http::StatusCode, AppError> {
+    authorize_session(state.store.as_ref(), &id, &headers).await?;
 
-    // Calculate totals with tip included (as if it's a tax)
-    let total_spent_base: f64 = unique_people.iter().map(|p| p.amount_spent).sum();
-    let total_explicit_tip: f64 = unique_people.iter().map(|p| p.tip).sum();
-    
-    // Total spent with tip = (Base * Global Tax) + Explicit Tips
-    // Note: We assume explicit tips are NOT taxed by the global percentage
-    let total_spent_with_tip = (total_spent_base * tip_multiplier) + total_explicit_tip;
-    
-    // Calculate all delegated expenses (only private expenses paid_by self)
-    // Reimbursement expenses (will_receive_from_others) are already excluded from amount_spent
-    let all_delegated_expenses: f64 = unique_people.iter().map(|p| p.delegated_self).sum();
-    
-    // Sponsorship is a fixed amount, not affected by tip/tax
-    let total_sponsored: f64 = unique_people.iter().map(|p| p.sponsor_amount).sum();
-    
-    // Handle sponsorship logic based on restriction setting
-    // Always restrict sponsorship to total spent to ensure no one profits (negative share)
-    let (effective_total_sponsored, sponsorship_ratio) = if total_sponsored > total_spent_with_tip {
-        // Restrict enabled: Cap sponsorship at total spent (including tip)
-        // Scale down sponsor contributions
-        let ratio = if total_sponsored > 0.0 { total_spent_with_tip / total_sponsored } else { 0.0 };
-        (total_spent_with_tip, ratio)
-    } else {
-        // Sponsorship <= spent: Use actual sponsorship amount
-        (total_sponsored, 1.0)
-    };
-    
-    // The amount that needs to be shared among participants
-    // Subtract all delegated expenses (including self-payment) because those are private transactions
-    // Fund amount is flat cash, so it's subtracted from the total needed
-    let amount_to_share = (total_spent_with_tip - effective_total_sponsored - fund_amount - all_delegated_expenses).max(0.0);
+    state.store.delete_session(&id).await?;
 
-    let participants: Vec<&PersonSummary> = if include_sponsor {
-        unique_people.iter().collect()
-    } else {
-        unique_people.iter().filter(|p| !p.is_sponsor).collect()
-    };
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
 
-    let num_participants = participants.len();
-    let per_person_share = if num_participants > 0 {
-        amount_to_share / num_participants as f64
-    } else {
-        0.0
-    };
+#[utoipa::path(
+    put,
+    path = "/api/v1/sessions/{id}/expiry",
+    tag = "sessions",
+    summary = "Change when a session expires",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = SessionExpiryRequest,
+    security(("edit_secret" = [])),
+    responses(
+        (status = 200, body = SessionExpiryResponse),
+        (status = 400, description = "expiry_days is not between 1 and 3650", body = ErrorResponse),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
+async fn update_session_expiry(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+    Json(request): Json<SessionExpiryRequest>,
+) -> Result<Json<SessionExpiryResponse>, AppError> {
+    if matches!(request.expiry_days, Some(days) if !(1..=MAX_SESSION_EXPIRY_DAYS).contains(&days)) {
+        return Err(AppError::BadRequest(format!("expiry_days must be between 1 and {}", MAX_SESSION_EXPIRY_DAYS)));
+    }
 
-    let mut settlements: Vec<Settlement> = unique_people
-        .iter()
-        .map(|person| {
-            // Calculate tip paid by this person
-            // = Explicit Tip + (Amount Spent * Global Tax Rate)
-            let global_tip_part = if tip_percentage > 0.0 {
-                person.amount_spent * (tip_percentage / 100.0)
-            } else {
-                0.0
-            };
-            
-            let tip_paid = person.tip + global_tip_part;
+    let mut session = authorize_session(state.store.as_ref(), &id, &headers).await?;
+    session.expiry_days = request.expiry_days;
+    session.pinned = request.pinned;
+    session.last_accessed_at = Utc::now();
 
-            // Calculate how much this person should pay (cost)
-            let sponsor_cost = if person.is_sponsor {
-                // Sponsorship is flat amount
-                person.sponsor_amount * sponsorship_ratio
-            } else {
-                0.0
-            };
-            
-            // If they are a participant in the split, add the shared amount
-            let is_participant = if include_sponsor {
-                true
-            } else {
-                !person.is_sponsor
-            };
+    state.store.update_session_expiry(&session).await?;
 
-            let share_cost = if is_participant {
-                per_person_share
-            } else {
-                0.0
-            };
+    Ok(Json(SessionExpiryResponse {
+        expiry_days: session.expiry_days,
+        pinned: session.pinned,
+        expires_at: session_expires_at(&session, state.session_expiry_days),
+    }))
+}
 
-            // What they should pay: sponsor_cost + share_cost + delegated_self + owes_to_others
-            // delegated_self is their private expense
-            // owes_to_others is reimbursements they owe to others
-            let total_cost = sponsor_cost + share_cost + person.delegated_self + person.owes_to_others;
-
-            // Balance calculation:
-            // What they paid: amount_spent + tip_paid
-            // Plus what they will receive back: will_receive_from_others
-            // Minus what they should pay: total_cost (share + private + reimbursements owed)
-            // Balance = (amount_spent + tip_paid + will_receive_from_others) - total_cost
-            // Positive balance = they should receive money
-            // Negative balance = they should pay money
-            let balance = (person.amount_spent + tip_paid + person.will_receive_from_others) - total_cost;
-
-            let settlement_type = if balance > 0.01 {
-                "receive".to_string()
-            } else if balance < -0.01 {
-                "pay".to_string()
-            } else {
-                "settled".to_string()
-            };
+#[utoipa::path(
+    get,
+    path = "/api/v1/sessions/{id}/notifications",
+    tag = "sessions",
+    summary = "List who is warned before expiry",
+    params(("id" = String, Path, description = "Session id")),
+    security(("edit_secret" = [])),
+    responses(
+        (status = 200, body = SessionNotificationsResponse),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
+async fn get_session_notifications(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<SessionNotificationsResponse>, AppError> {
+    let session = authorize_session(state.store.as_ref(), &id, &headers).await?;
+    let emails = serde_json::from_str(&session.notification_emails).unwrap_or_default();
+    Ok(Json(SessionNotificationsResponse { emails }))
+}
 
-            Settlement {
-                name: person.name.clone(),
-                amount_spent: person.amount_spent,
-                tip_paid,
-                sponsor_cost,
-                share_cost,
-                balance,
-                settlement_type,
-                is_receiver: person.is_receiver,
-            }
-        })
+#[utoipa::path(
+    put,
+    path = "/api/v1/sessions/{id}/notifications",
+    tag = "sessions",
+    summary = "Set who is warned before expiry",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = SessionNotificationsRequest,
+    security(("edit_secret" = [])),
+    responses(
+        (status = 200, body = SessionNotificationsResponse),
+        (status = 400, description = "Invalid or too many addresses", body = ErrorResponse),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
+async fn update_session_notifications(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+    Json(request): Json<SessionNotificationsRequest>,
+) -> Result<Json<SessionNotificationsResponse>, AppError> {
+    let mut emails: Vec<String> = request.emails
+        .iter()
+        .map(|e| e.trim().to_lowercase())
+        .filter(|e| !e.is_empty())
         .collect();
-    
-    // Sort settlements: Payers (negative balance) first, then Receivers (positive balance)
-    settlements.sort_by(|a, b| {
-        a.balance.partial_cmp(&b.balance)
-            .unwrap_or(std::cmp::Ordering::Equal)
-            .then_with(|| a.name.cmp(&b.name))
-    });
+    emails.sort();
+    emails.dedup();
 
-    CalculateResponse {
-        total_spent: total_spent_base + total_explicit_tip,
-        total_sponsored: effective_total_sponsored,
-        fund_amount,
-        total_tip: total_spent_with_tip - (total_spent_base + total_explicit_tip),
-        amount_to_share,
-        num_participants,
-        per_person_share,
-        settlements,
+    if emails.len() > MAX_NOTIFICATION_EMAILS {
+        return Err(AppError::BadRequest(format!("At most {} notification emails", MAX_NOTIFICATION_EMAILS)));
     }
+    if emails.iter().any(|e| !e.contains('@')) {
+        return Err(AppError::BadRequest("Invalid email address".to_string()));
+    }
+
+    authorize_session(state.store.as_ref(), &id, &headers).await?;
+
+    let emails_json = serde_json::to_string(&emails)?;
+
+    state.store.update_session_notifications(&id, &emails_json).await?;
+
+    Ok(Json(SessionNotificationsResponse { emails }))
 }
 
-async fn process_ai_text(
+// Target of the link in expiry warning emails
+#[utoipa::path(
+    get,
+    path = "/api/v1/sessions/{id}/extend",
+    tag = "sessions",
+    summary = "Extend a session from an expiry warning email",
+    params(("id" = String, Path, description = "Session id"), ExtendSessionQuery),
+    responses(
+        (status = 303, description = "Redirects to the session"),
+        (status = 404, description = "Unknown session or token", body = ErrorResponse),
+    )
+)]
+async fn extend_session(
     State(state): State<AppState>,
-    headers: axum::http::HeaderMap,
-    Json(request): Json<AiTextRequest>
-) -> impl IntoResponse {
-    let request_id = headers.get("X-Request-ID").and_then(|h| h.to_str().ok().map(|s| s.to_string()));
-    if let Err(e) = check_request_id(&state, request_id).await {
-        return e.into_response();
-    }
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::Query(query): axum::extract::Query<ExtendSessionQuery>,
+) -> Result<axum::response::Redirect, AppError> {
+    let extended = state.store.extend_session(&id, &query.token, Utc::now()).await?;
 
-    let api_key = std::env::var("OPENAI_API_KEY").unwrap_or_default();
-    if api_key.is_empty() {
-        return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "OPENAI_API_KEY not set").into_response();
-    }
-    let provider = OpenAiProvider::new(api_key);
-    match provider.process_text(&request.text).await {
-        Ok(data) => Json(data).into_response(),
-        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    if !extended {
+        return Err(AppError::NotFound);
     }
+
+    Ok(axum::response::Redirect::to(&format!("/?session={}", id)))
 }
 
-async fn process_ai_split_text(
+#[utoipa::path(
+    get,
+    path = "/api/v1/sessions/{id}/reminders",
+    tag = "reminders",
+    summary = "The session's payment reminder schedule and who has been reminded",
+    params(("id" = String, Path, description = "Session id")),
+    security(("edit_secret" = [])),
+    responses(
+        (status = 200, body = RemindersResponse),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
+async fn get_reminders(
     State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
     headers: axum::http::HeaderMap,
-    Json(request): Json<AiTextRequest>
-) -> impl IntoResponse {
-    let request_id = headers.get("X-Request-ID").and_then(|h| h.to_str().ok().map(|s| s.to_string()));
-    if let Err(e) = check_request_id(&state, request_id).await {
-        return e.into_response();
-    }
+) -> Result<Json<RemindersResponse>, AppError> {
+    authorize_session(state.store.as_ref(), &id, &headers).await?;
 
-    let api_key = std::env::var("OPENAI_API_KEY").unwrap_or_default();
-    if api_key.is_empty() {
-        return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "OPENAI_API_KEY not set").into_response();
-    }
-    let provider = OpenAiProvider::new(api_key);
-    match provider.process_split_text(&request.text).await {
-        Ok(data) => Json(data).into_response(),
-        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
-    }
+    let schedule = state.store.get_reminder_schedule(&id).await?;
+    let reminders = state.store.payment_reminders_for_session(&id).await?;
+
+    Ok(Json(RemindersResponse {
+        schedule: schedule.map(|s| ReminderSchedule { first_after_days: s.first_after_days, repeat_days: s.repeat_days }),
+        reminders: reminders
+            .into_iter()
+            .map(|r| PaymentReminderStatus {
+                name: r.name,
+                amount: r.amount,
+                reminders_sent: r.reminders_sent,
+                last_sent_at: r.last_sent_at,
+                paid_at: r.paid_at,
+            })
+            .collect(),
+    }))
 }
 
-async fn process_ai_image(
+#[utoipa::path(
+    put,
+    path = "/api/v1/sessions/{id}/reminders",
+    tag = "reminders",
+    summary = "Remind participants who owe money to pay",
+    description = "Everyone with a negative balance and a recorded email address (see PUT \
+        /api/v1/sessions/{id}/participants) is emailed first_after_days after the session was created, \
+        then every repeat_days until they or the person they pay mark the debt paid from the email.",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = ReminderSchedule,
+    security(("edit_secret" = [])),
+    responses(
+        (status = 200, body = RemindersResponse),
+        (status = 400, description = "Days out of range", body = ErrorResponse),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
+async fn update_reminders(
     State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
     headers: axum::http::HeaderMap,
-    mut multipart: Multipart
-) -> impl IntoResponse {
-    let request_id = headers.get("X-Request-ID").and_then(|h| h.to_str().ok().map(|s| s.to_string()));
-    if let Err(e) = check_request_id(&state, request_id).await {
-        return e.into_response();
-    }
+    Json(request): Json<ReminderSchedule>,
+) -> Result<Json<RemindersResponse>, AppError> {
+    authorize_session(state.store.as_ref(), &id, &headers).await?;
 
-    let api_key = std::env::var("OPENAI_API_KEY").unwrap_or_default();
-    if api_key.is_empty() {
-        return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "OPENAI_API_KEY not set").into_response();
+    if !(0..=MAX_REMINDER_DELAY_DAYS).contains(&request.first_after_days) {
+        return Err(AppError::BadRequest(format!("first_after_days must be between 0 and {}", MAX_REMINDER_DELAY_DAYS)));
     }
-    
+    if !(0..=MAX_REMINDER_REPEAT_DAYS).contains(&request.repeat_days) {
+        return Err(AppError::BadRequest(format!("repeat_days must be between 0 and {}", MAX_REMINDER_REPEAT_DAYS)));
+    }
+
+    state.store.set_reminder_schedule(&DbReminderSchedule {
+        session_id: id.clone(),
+        first_after_days: request.first_after_days,
+        repeat_days: request.repeat_days,
+        created_at: Utc::now(),
+    }).await?;
+
+    get_reminders(State(state), axum::extract::Path(id), headers).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/sessions/{id}/reminders",
+    tag = "reminders",
+    summary = "Stop payment reminders",
+    params(("id" = String, Path, description = "Session id")),
+    security(("edit_secret" = [])),
+    responses(
+        (status = 204, description = "Stopped"),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
+async fn delete_reminders(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+) -> Result<axum::http::StatusCode, AppError> {
+    authorize_session(state.store.as_ref(), &id, &headers).await?;
+
+    state.store.delete_reminder_schedule(&id).await?;
+
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
+
+// Target of the link in payment reminder emails, which go to the debtor and the people they
+// pay. Link scanners and prefetchers follow it too, so it only asks; the page's button posts.
+#[utoipa::path(
+    get,
+    path = "/api/v1/reminders/{token}/paid",
+    tag = "reminders",
+    summary = "Ask whether a debt from a reminder email has been paid",
+    params(("token" = String, Path, description = "Token from the reminder email")),
+    responses(
+        (status = 200, description = "A page with a button that marks the debt paid", content_type = "text/html"),
+        (status = 404, description = "Unknown token", body = ErrorResponse),
+    )
+)]
+async fn reminder_paid_page(
+    State(state): State<AppState>,
+    axum::extract::Path(token): axum::extract::Path<String>,
+) -> Result<ReminderPaidTemplate, AppError> {
+    let reminder = state.store.get_payment_reminder(&token)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let paid = reminder.paid_at.is_some();
+    reminder_paid_template(state.store.as_ref(), reminder, paid).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/reminders/{token}/paid",
+    tag = "reminders",
+    summary = "Mark a debt paid from a reminder email",
+    params(("token" = String, Path, description = "Token from the reminder email")),
+    responses(
+        (status = 200, description = "A page saying the reminders have stopped", content_type = "text/html"),
+        (status = 404, description = "Unknown token", body = ErrorResponse),
+    )
+)]
+async fn mark_reminder_paid(
+    State(state): State<AppState>,
+    axum::extract::Path(token): axum::extract::Path<String>,
+) -> Result<ReminderPaidTemplate, AppError> {
+    let reminder = state.store.get_payment_reminder(&token)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    // Submitting again is harmless
+    state.store.mark_payment_paid(&token, Utc::now()).await?;
+
+    reminder_paid_template(state.store.as_ref(), reminder, true).await
+}
+
+// In the debtor's language. Both sides can open the page, so it doesn't link to the
+// debtor's personal page.
+async fn reminder_paid_template(
+    store: &dyn SessionStore,
+    reminder: DbPaymentReminder,
+    paid: bool,
+) -> Result<ReminderPaidTemplate, AppError> {
+    let currency = store.get_session(&reminder.session_id).await?.and_then(|session| session.currency);
+    let language = store.participant_links_for_session(&reminder.session_id)
+        .await?
+        .into_iter()
+        .find(|link| link.name == reminder.name)
+        .and_then(|link| link.language);
+    Ok(ReminderPaidTemplate {
+        t: email::EmailStrings::for_language(Language::from_code(language.as_deref())),
+        name: reminder.name,
+        amount: email::format_money(reminder.amount, currency.as_deref()),
+        paid,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/sessions/{id}/report",
+    tag = "sessions",
+    summary = "Download a settlement report",
+    description = "Every expense line, the adjustments (fund, tip, sponsorships, private expenses and \
+        reimbursements), the per-person settlement table and the transfers, as CSV or PDF.",
+    params(("id" = String, Path, description = "Session id"), report::ReportQuery),
+    responses(
+        (status = 200, description = "The report, as an attachment", content((String = "text/csv"), (Vec<u8> = "application/pdf"))),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
+async fn download_report(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::Query(query): axum::extract::Query<report::ReportQuery>,
+) -> Result<axum::response::Response, AppError> {
+    let now = Utc::now();
+    state.store.touch_session(&id, now).await?;
+    let session = state.store.get_session(&id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let report = report::SettlementReport::from_session(&session, now)?;
+    let body = report.render(query.format).map_err(AppError::Internal)?;
+    let disposition = format!("attachment; filename=\"{}\"", report.filename(query.format));
+    Ok((
+        [
+            (axum::http::header::CONTENT_TYPE, query.format.content_type().to_string()),
+            (axum::http::header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    ).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/sessions/{id}/archive",
+    tag = "archives",
+    summary = "Archive a session with its calculation",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = Option<ArchiveSessionRequest>,
+    security(("edit_secret" = [], "owner_token" = [])),
+    responses(
+        (status = 200, body = ArchiveResponse),
+        (status = 400, description = "Neither an owner token nor a group", body = ErrorResponse),
+        (status = 403, description = "Missing or wrong X-Edit-Secret or group_edit_secret", body = ErrorResponse),
+        (status = 404, description = "No such session or group", body = ErrorResponse),
+    )
+)]
+async fn archive_session(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+    request: Option<Json<ArchiveSessionRequest>>,
+) -> Result<Json<ArchiveResponse>, AppError> {
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    let owner_token = headers.get("X-Owner-Token")
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.to_string());
+
+    // Without an owner or a group nobody could ever list this archive again
+    if owner_token.is_none() && request.group_id.is_none() {
+        return Err(AppError::BadRequest("Send an X-Owner-Token header or a group_id".to_string()));
+    }
+
+    let session = authorize_session(state.store.as_ref(), &id, &headers).await?;
+    if let Some(group_id) = &request.group_id {
+        check_group_secret(state.store.as_ref(), group_id, request.group_edit_secret.as_deref()).await?;
+    }
+    let people: Vec<Person> = serde_json::from_str(&session.people)?;
+
+    let calculation = split_bills_core::calculate_split(CalculateRequest {
+        people: people.clone(),
+        include_sponsor: session.include_sponsor,
+        restrict_sponsor_to_spent: Some(true),
+        fund_amount: session.fund_amount,
+        tip_percentage: session.tip_percentage,
+    });
+
+    let archive = DbArchive {
+        id: Uuid::new_v4().to_string(),
+        session_id: session.id,
+        edit_secret: session.edit_secret,
+        owner_token,
+        group_id: request.group_id,
+        title: request.title.unwrap_or_default(),
+        people: session.people,
+        fund_amount: session.fund_amount,
+        tip_percentage: session.tip_percentage,
+        include_sponsor: session.include_sponsor,
+        calculation: serde_json::to_string(&calculation)?,
+        archived_at: Utc::now(),
+        currency: session.currency,
+    };
+
+    state.store.insert_archive(&archive).await?;
+
+    archive_response(archive).map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/archives",
+    tag = "archives",
+    summary = "List archives by owner or group",
+    description = "A group's archives need the group's X-Edit-Secret.",
+    params(ListArchivesQuery),
+    security(("owner_token" = []), ("edit_secret" = [])),
+    responses(
+        (status = 200, body = Vec<ArchiveResponse>),
+        (status = 400, description = "Neither an owner token nor a group", body = ErrorResponse),
+        (status = 403, description = "Missing or wrong X-Edit-Secret for the group", body = ErrorResponse),
+        (status = 404, description = "No such group", body = ErrorResponse),
+    )
+)]
+async fn list_archives(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<ListArchivesQuery>,
+) -> Result<Json<Vec<ArchiveResponse>>, AppError> {
+    let owner_token = headers.get("X-Owner-Token").and_then(|h| h.to_str().ok());
+
+    let archives = match (query.group_id, owner_token) {
+        (Some(group_id), _) => {
+            authorize_group(state.store.as_ref(), &group_id, &headers).await?;
+            state.store.archives_for_group(&group_id).await
+        }
+        (None, Some(owner_token)) => state.store.archives_for_owner(owner_token).await,
+        (None, None) => return Err(AppError::BadRequest("Send an X-Owner-Token header or a group_id".to_string())),
+    }?;
+
+    archives
+        .into_iter()
+        .map(archive_response)
+        .collect::<Result<Vec<_>, _>>()
+        .map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/archives/{id}",
+    tag = "archives",
+    summary = "Load an archive",
+    params(("id" = String, Path, description = "Archive id")),
+    responses(
+        (status = 200, body = ArchiveResponse),
+        (status = 404, description = "No such archive", body = ErrorResponse),
+    )
+)]
+async fn get_archive(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<ArchiveResponse>, AppError> {
+    let archive = fetch_archive(state.store.as_ref(), &id).await?;
+    archive_response(archive).map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/archives/{id}",
+    tag = "archives",
+    summary = "Delete an archive",
+    params(("id" = String, Path, description = "Archive id")),
+    security(("owner_token" = []), ("edit_secret" = [])),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 403, description = "Not the owner", body = ErrorResponse),
+        (status = 404, description = "No such archive", body = ErrorResponse),
+    )
+)]
+async fn delete_archive(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+) -> Result<axum::http::StatusCode, AppError> {
+    let archive = fetch_archive(state.store.as_ref(), &id).await?;
+    if !owns_archive(&archive, &headers) {
+        return Err(AppError::Forbidden);
+    }
+
+    state.store.delete_archive(&id).await?;
+
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
+
+// Bring an expired session back under its original id and secret, so old share links work again
+#[utoipa::path(
+    post,
+    path = "/api/v1/archives/{id}/restore",
+    tag = "archives",
+    summary = "Restore an archive under its original session id",
+    params(("id" = String, Path, description = "Archive id")),
+    security(("owner_token" = []), ("edit_secret" = [])),
+    responses(
+        (status = 200, body = CreateSessionResponse),
+        (status = 403, description = "Not the owner", body = ErrorResponse),
+        (status = 404, description = "No such archive", body = ErrorResponse),
+        (status = 409, description = "The session still exists", body = ErrorResponse),
+    )
+)]
+async fn restore_archive(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<CreateSessionResponse>, AppError> {
+    let user = current_user(state.store.as_ref(), &headers).await?;
+    let archive = fetch_archive(state.store.as_ref(), &id).await?;
+    if !owns_archive(&archive, &headers) {
+        return Err(AppError::Forbidden);
+    }
+
+    let existing = state.store.get_session(&archive.session_id).await?;
+
+    if existing.is_some() {
+        return Err(AppError::Conflict("The session still exists".to_string()));
+    }
+
+    insert_session(state.store.as_ref(), &archive.session_id, &archive.edit_secret, &archive_session_request(&archive)?, user.as_ref().map(|u| u.id.as_str()))
+        .await?;
+
+    Ok(Json(CreateSessionResponse {
+        id: archive.session_id,
+        edit_secret: archive.edit_secret,
+    }))
+}
+
+// Start a fresh session from a copy of an archive
+#[utoipa::path(
+    post,
+    path = "/api/v1/archives/{id}/clone",
+    tag = "archives",
+    summary = "Start a new session from an archive",
+    params(("id" = String, Path, description = "Archive id")),
+    security((), ("bearer" = [])),
+    responses(
+        (status = 200, body = CreateSessionResponse),
+        (status = 404, description = "No such archive", body = ErrorResponse),
+    )
+)]
+async fn clone_archive(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<CreateSessionResponse>, AppError> {
+    let user = current_user(state.store.as_ref(), &headers).await?;
+    let archive = fetch_archive(state.store.as_ref(), &id).await?;
+    let session_id = Uuid::new_v4().to_string();
+    let edit_secret = Uuid::new_v4().to_string();
+
+    insert_session(state.store.as_ref(), &session_id, &edit_secret, &archive_session_request(&archive)?, user.as_ref().map(|u| u.id.as_str()))
+        .await?;
+
+    Ok(Json(CreateSessionResponse {
+        id: session_id,
+        edit_secret,
+    }))
+}
+
+async fn fetch_archive(store: &dyn SessionStore, id: &str) -> Result<DbArchive, AppError> {
+    store.get_archive(id)
+        .await?
+        .ok_or(AppError::NotFound)
+}
+
+// The archiving browser (X-Owner-Token) or anyone holding the session's edit secret
+fn owns_archive(archive: &DbArchive, headers: &axum::http::HeaderMap) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|h| h.to_str().ok());
+
+    let owner_matches = matches!(
+        (archive.owner_token.as_deref(), header("X-Owner-Token")),
+        (Some(stored), Some(given)) if stored == given
+    );
+    owner_matches || header("X-Edit-Secret") == Some(archive.edit_secret.as_str())
+}
+
+fn archive_session_request(archive: &DbArchive) -> Result<CreateSessionRequest, AppError> {
+    Ok(CreateSessionRequest {
+        people: serde_json::from_str(&archive.people)?,
+        fund_amount: archive.fund_amount,
+        tip_percentage: archive.tip_percentage,
+        include_sponsor: archive.include_sponsor,
+        expiry_days: None,
+        pinned: false,
+        currency: archive.currency.clone(),
+    })
+}
+
+fn archive_response(archive: DbArchive) -> Result<ArchiveResponse, AppError> {
+    Ok(ArchiveResponse {
+        people: serde_json::from_str(&archive.people)?,
+        calculation: serde_json::from_str(&archive.calculation)?,
+        id: archive.id,
+        session_id: archive.session_id,
+        group_id: archive.group_id,
+        title: archive.title,
+        archived_at: archive.archived_at,
+        fund_amount: archive.fund_amount,
+        tip_percentage: archive.tip_percentage,
+        include_sponsor: archive.include_sponsor,
+        currency: archive.currency,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/templates",
+    tag = "templates",
+    summary = "List your templates",
+    security(("owner_token" = [])),
+    responses(
+        (status = 200, body = Vec<TemplateResponse>),
+        (status = 400, description = "Missing X-Owner-Token", body = ErrorResponse),
+    )
+)]
+async fn list_templates(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<TemplateResponse>>, AppError> {
+    let owner_token = headers.get("X-Owner-Token")
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| AppError::BadRequest("Missing X-Owner-Token header".to_string()))?;
+
+    let templates = state.store.templates_for_owner(owner_token).await?;
+
+    templates
+        .into_iter()
+        .map(template_response)
+        .collect::<Result<Vec<_>, _>>()
+        .map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/templates",
+    tag = "templates",
+    summary = "Save a template",
+    request_body = SessionTemplate,
+    security(("owner_token" = [])),
+    responses(
+        (status = 200, body = TemplateResponse),
+        (status = 400, description = "Missing X-Owner-Token or name", body = ErrorResponse),
+    )
+)]
+async fn create_template(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    Json(template): Json<SessionTemplate>,
+) -> Result<Json<TemplateResponse>, AppError> {
+    let owner_token = headers.get("X-Owner-Token")
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| AppError::BadRequest("Missing X-Owner-Token header".to_string()))?;
+
+    if template.name.trim().is_empty() {
+        return Err(AppError::BadRequest("Template name is required".to_string()));
+    }
+
+    let now = Utc::now();
+    let row = DbTemplate {
+        id: Uuid::new_v4().to_string(),
+        owner_token: owner_token.to_string(),
+        name: template.name.clone(),
+        body: serde_json::to_string(&template)?,
+        created_at: now,
+        updated_at: now,
+    };
+
+    state.store.insert_template(&row).await?;
+
+    template_response(row).map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/templates/{id}",
+    tag = "templates",
+    summary = "Load a template",
+    params(("id" = String, Path, description = "Template id")),
+    responses(
+        (status = 200, body = TemplateResponse),
+        (status = 404, description = "No such template", body = ErrorResponse),
+    )
+)]
+async fn get_template(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<TemplateResponse>, AppError> {
+    let template = fetch_template(state.store.as_ref(), &id).await?;
+    template_response(template).map(Json)
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/templates/{id}",
+    tag = "templates",
+    summary = "Replace a template",
+    params(("id" = String, Path, description = "Template id")),
+    request_body = SessionTemplate,
+    security(("owner_token" = [])),
+    responses(
+        (status = 200, body = TemplateResponse),
+        (status = 400, description = "Missing name", body = ErrorResponse),
+        (status = 403, description = "Not the owner", body = ErrorResponse),
+        (status = 404, description = "No such template", body = ErrorResponse),
+    )
+)]
+async fn update_template(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+    Json(template): Json<SessionTemplate>,
+) -> Result<Json<TemplateResponse>, AppError> {
+    if template.name.trim().is_empty() {
+        return Err(AppError::BadRequest("Template name is required".to_string()));
+    }
+
+    let mut row = fetch_template(state.store.as_ref(), &id).await?;
+    if !owns_template(&row, &headers) {
+        return Err(AppError::Forbidden);
+    }
+
+    row.name = template.name.clone();
+    row.body = serde_json::to_string(&template)?;
+    row.updated_at = Utc::now();
+
+    state.store.update_template(&row).await?;
+
+    template_response(row).map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/templates/{id}",
+    tag = "templates",
+    summary = "Delete a template",
+    params(("id" = String, Path, description = "Template id")),
+    security(("owner_token" = [])),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 403, description = "Not the owner", body = ErrorResponse),
+        (status = 404, description = "No such template", body = ErrorResponse),
+    )
+)]
+async fn delete_template(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+) -> Result<axum::http::StatusCode, AppError> {
+    let row = fetch_template(state.store.as_ref(), &id).await?;
+    if !owns_template(&row, &headers) {
+        return Err(AppError::Forbidden);
+    }
+
+    state.store.delete_template(&id).await?;
+
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
+
+async fn fetch_template(store: &dyn SessionStore, id: &str) -> Result<DbTemplate, AppError> {
+    store.get_template(id)
+        .await?
+        .ok_or(AppError::NotFound)
+}
+
+fn owns_template(template: &DbTemplate, headers: &axum::http::HeaderMap) -> bool {
+    headers.get("X-Owner-Token").and_then(|h| h.to_str().ok()) == Some(template.owner_token.as_str())
+}
+
+fn template_response(row: DbTemplate) -> Result<TemplateResponse, AppError> {
+    Ok(TemplateResponse {
+        template: serde_json::from_str(&row.body)?,
+        id: row.id,
+        created_at: row.created_at,
+        updated_at: row.updated_at,
+    })
+}
+
+// Build the contents of a new session from a template. `first_id` seeds the
+// expense ids, which the frontend otherwise takes from Date.now().
+fn instantiate_template(template: &SessionTemplate, extra: Vec<Person>, first_id: u64) -> CreateSessionRequest {
+    let mut people: Vec<Person> = template.expenses.iter().cloned().chain(extra).collect();
+
+    // Roster members without any line still need one to show up as participants
+    for name in &template.roster {
+        if !people.iter().any(|p| &p.name == name) {
+            people.push(Person {
+                id: 0,
+                name: name.clone(),
+                description: String::new(),
+                amount_spent: 0.0,
+                quantity: 1,
+                tip: 0.0,
+                is_sponsor: false,
+                sponsor_amount: 0.0,
+                is_receiver: false,
+                paid_by: None,
+            });
+        }
+    }
+
+    for (i, person) in people.iter_mut().enumerate() {
+        person.id = first_id + i as u64;
+        person.is_receiver = template.receiver.as_deref() == Some(person.name.as_str());
+    }
+
+    CreateSessionRequest {
+        people,
+        fund_amount: template.fund_amount,
+        tip_percentage: template.tip_percentage,
+        include_sponsor: template.include_sponsor,
+        expiry_days: None,
+        pinned: false,
+        currency: template.currency.clone(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/groups",
+    tag = "groups",
+    summary = "Create a group",
+    request_body = GroupRequest,
+    security((), ("bearer" = [])),
+    responses(
+        (status = 200, body = CreateGroupResponse),
+        (status = 400, description = "Missing name", body = ErrorResponse),
+    )
+)]
+async fn create_group(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    Json(request): Json<GroupRequest>,
+) -> Result<Json<CreateGroupResponse>, AppError> {
+    if request.name.trim().is_empty() {
+        return Err(AppError::BadRequest("Group name is required".to_string()));
+    }
+
+    let user = current_user(state.store.as_ref(), &headers).await?;
+
+    let group = DbGroup {
+        id: Uuid::new_v4().to_string(),
+        edit_secret: Uuid::new_v4().to_string(),
+        name: request.name.trim().to_string(),
+        members: serde_json::to_string(&normalize_members(request.members))?,
+        created_at: Utc::now(),
+        owner_user_id: user.map(|u| u.id),
+    };
+
+    state.store.insert_group(&group).await?;
+
+    Ok(Json(CreateGroupResponse { id: group.id, edit_secret: group.edit_secret }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/groups/{id}",
+    tag = "groups",
+    summary = "Load a group and its sessions",
+    params(("id" = String, Path, description = "Group id")),
+    responses(
+        (status = 200, body = GroupResponse),
+        (status = 404, description = "No such group", body = ErrorResponse),
+    )
+)]
+async fn get_group(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<GroupResponse>, AppError> {
+    let group = state.store.get_group(&id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    group_response(state.store.as_ref(), group).await.map(Json)
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/groups/{id}",
+    tag = "groups",
+    summary = "Rename a group or change its members",
+    params(("id" = String, Path, description = "Group id")),
+    request_body = GroupRequest,
+    security(("edit_secret" = [])),
+    responses(
+        (status = 200, body = GroupResponse),
+        (status = 400, description = "Missing name", body = ErrorResponse),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such group", body = ErrorResponse),
+    )
+)]
+async fn update_group(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+    Json(request): Json<GroupRequest>,
+) -> Result<Json<GroupResponse>, AppError> {
+    if request.name.trim().is_empty() {
+        return Err(AppError::BadRequest("Group name is required".to_string()));
+    }
+
+    let mut group = authorize_group(state.store.as_ref(), &id, &headers).await?;
+    group.name = request.name.trim().to_string();
+    group.members = serde_json::to_string(&normalize_members(request.members))?;
+
+    state.store.update_group(&group).await?;
+
+    group_response(state.store.as_ref(), group).await.map(Json)
+}
+
+// Needs both the group's secret (header) and the session's secret (body)
+#[utoipa::path(
+    post,
+    path = "/api/v1/groups/{id}/sessions",
+    tag = "groups",
+    summary = "Add a session to a group",
+    params(("id" = String, Path, description = "Group id")),
+    request_body = AddGroupSessionRequest,
+    security(("edit_secret" = [])),
+    responses(
+        (status = 200, body = GroupResponse),
+        (status = 403, description = "Wrong group or session secret", body = ErrorResponse),
+        (status = 404, description = "No such group or session", body = ErrorResponse),
+    )
+)]
+async fn add_group_session(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+    Json(request): Json<AddGroupSessionRequest>,
+) -> Result<Json<GroupResponse>, AppError> {
+    let group = authorize_group(state.store.as_ref(), &id, &headers).await?;
+
+    let session = state.store.get_session(&request.session_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    if session.edit_secret != request.session_edit_secret {
+        return Err(AppError::Forbidden);
+    }
+
+    let balances = member_balances(CalculateRequest {
+        people: serde_json::from_str(&session.people)?,
+        include_sponsor: session.include_sponsor,
+        restrict_sponsor_to_spent: Some(true),
+        fund_amount: session.fund_amount,
+        tip_percentage: session.tip_percentage,
+    });
+    let balances_json = serde_json::to_string(&balances)?;
+    let now = Utc::now();
+
+    state.store.upsert_group_session(&DbGroupSession {
+        group_id: id,
+        session_id: session.id,
+        balances: balances_json,
+        added_at: now,
+        updated_at: now,
+    })
+        .await?;
+
+    group_response(state.store.as_ref(), group).await.map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/groups/{id}/sessions/{session_id}",
+    tag = "groups",
+    summary = "Remove a session from a group",
+    params(("id" = String, Path, description = "Group id"), ("session_id" = String, Path, description = "Session id")),
+    security(("edit_secret" = [])),
+    responses(
+        (status = 204, description = "Removed"),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such group or session in it", body = ErrorResponse),
+    )
+)]
+async fn remove_group_session(
+    State(state): State<AppState>,
+    axum::extract::Path((id, session_id)): axum::extract::Path<(String, String)>,
+    headers: axum::http::HeaderMap,
+) -> Result<axum::http::StatusCode, AppError> {
+    authorize_group(state.store.as_ref(), &id, &headers).await?;
+
+    let removed = state.store.delete_group_session(&id, &session_id).await?;
+
+    if !removed {
+        return Err(AppError::NotFound);
+    }
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
+
+// Net balances across every session in the group, plus the transfers that settle them
+#[utoipa::path(
+    get,
+    path = "/api/v1/groups/{id}/ledger",
+    tag = "groups",
+    summary = "Net balances and transfers across a group",
+    params(("id" = String, Path, description = "Group id")),
+    responses(
+        (status = 200, body = GroupLedgerResponse),
+        (status = 404, description = "No such group", body = ErrorResponse),
+    )
+)]
+async fn get_group_ledger(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<GroupLedgerResponse>, AppError> {
+    let group = state.store.get_group(&id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let members: Vec<String> = serde_json::from_str(&group.members).unwrap_or_default();
+    let snapshots = state.store.group_sessions(&id).await?;
+
+    let per_session: Vec<Vec<MemberBalance>> = snapshots
+        .iter()
+        .map(|s| serde_json::from_str(&s.balances))
+        .collect::<Result<_, _>>()?;
+
+    let balances = net_group_balances(&members, &per_session);
+    let transfers = settle_balances(
+        &balances.iter().map(|b| (b.name.clone(), b.balance)).collect::<Vec<_>>(),
+    );
+
+    Ok(Json(GroupLedgerResponse {
+        balances,
+        transfers,
+        num_sessions: snapshots.len(),
+    }))
+}
+
+async fn authorize_group(
+    store: &dyn SessionStore,
+    id: &str,
+    headers: &axum::http::HeaderMap,
+) -> Result<DbGroup, AppError> {
+    let secret = headers.get("X-Edit-Secret").and_then(|h| h.to_str().ok());
+    check_group_secret(store, id, secret).await
+}
+
+async fn check_group_secret(store: &dyn SessionStore, id: &str, secret: Option<&str>) -> Result<DbGroup, AppError> {
+    let secret = secret.ok_or(AppError::Forbidden)?;
+
+    let group = store.get_group(id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    if group.edit_secret != secret {
+        return Err(AppError::Forbidden);
+    }
+    Ok(group)
+}
+
+async fn group_response(store: &dyn SessionStore, group: DbGroup) -> Result<GroupResponse, AppError> {
+    let snapshots = store.group_sessions(&group.id).await?;
+
+    let active = store.active_group_session_ids(&group.id).await?;
+
+    let sessions = snapshots
+        .into_iter()
+        .map(|s| {
+            Ok(GroupSessionSummary {
+                active: active.contains(&s.session_id),
+                balances: serde_json::from_str(&s.balances)?,
+                session_id: s.session_id,
+                added_at: s.added_at,
+                updated_at: s.updated_at,
+            })
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    Ok(GroupResponse {
+        members: serde_json::from_str(&group.members).unwrap_or_default(),
+        id: group.id,
+        name: group.name,
+        created_at: group.created_at,
+        sessions,
+    })
+}
+
+// Keep every group's copy of this session's balances current
+async fn refresh_group_snapshots(
+    store: &dyn SessionStore,
+    session_id: &str,
+    balances: &[MemberBalance],
+) -> Result<(), sqlx::Error> {
+    let balances_json = serde_json::to_string(balances).unwrap_or_else(|_| "[]".to_string());
+    store.refresh_group_snapshots(session_id, &balances_json, Utc::now()).await
+}
+
+fn normalize_members(members: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for member in members {
+        let member = member.trim().to_string();
+        if !member.is_empty() && !normalized.contains(&member) {
+            normalized.push(member);
+        }
+    }
+    normalized
+}
+
+// Email a single-use sign-in link. Always answers 202 so the endpoint
+// can't be used to find out which addresses have accounts.
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/login",
+    tag = "auth",
+    summary = "Email a sign-in link",
+    request_body = LoginRequest,
+    responses(
+        (status = 202, description = "Sent, whether or not the address has an account"),
+        (status = 400, description = "Invalid email address", body = ErrorResponse),
+        (status = 429, description = "Rate limit or daily quota reached; see Retry-After", body = ErrorResponse),
+        (status = 503, description = "Email is not configured", body = ErrorResponse),
+    )
+)]
+async fn request_login(
+    State(state): State<AppState>,
+    Json(request): Json<LoginRequest>,
+) -> Result<axum::http::StatusCode, AppError> {
+    let email = request.email.trim().to_lowercase();
+    if !email.contains('@') {
+        return Err(AppError::BadRequest("Invalid email address".to_string()));
+    }
+
+    let email_service = state.email.as_ref().ok_or(AppError::NotConfigured("Email"))?;
+
+    let token = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    state.store.insert_login_token(&DbLoginToken {
+        token: token.clone(),
+        email: email.clone(),
+        created_at: now,
+        expires_at: now + chrono::Duration::minutes(LOGIN_TOKEN_MINUTES),
+        used_at: None,
+    }).await?;
+
+    let template = MagicLinkTemplate {
+        login_url: format!("{}/?login={}", base_url(), token),
+        valid_minutes: LOGIN_TOKEN_MINUTES,
+    };
+    let html = template.render()?;
+
+    email_service.send_email(vec![email], "Sign in to Split Bills", &html, Default::default())
+        .await
+        .map_err(|e| AppError::Email(e.to_string()))?;
+    Ok(axum::http::StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/verify",
+    tag = "auth",
+    summary = "Exchange a sign-in link token for an access token",
+    request_body = VerifyLoginRequest,
+    responses(
+        (status = 200, body = VerifyLoginResponse),
+        (status = 401, description = "Unknown, used or expired token", body = ErrorResponse),
+    )
+)]
+async fn verify_login(
+    State(state): State<AppState>,
+    Json(request): Json<VerifyLoginRequest>,
+) -> Result<Json<VerifyLoginResponse>, AppError> {
+    let now = Utc::now();
+
+    let login = state.store.consume_login_token(&request.token, now)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+
+    let user = state.store.find_or_create_user(&login.email, now).await?;
+
+    let access_token = Uuid::new_v4().to_string();
+    let expires_at = now + chrono::Duration::days(ACCESS_TOKEN_DAYS);
+    state.store.insert_access_token(&access_token, &user.id, now, expires_at).await?;
+
+    Ok(Json(VerifyLoginResponse {
+        access_token,
+        expires_at,
+        user: UserResponse {
+            id: user.id,
+            email: user.email,
+            created_at: user.created_at,
+        },
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/logout",
+    tag = "auth",
+    summary = "Revoke the access token",
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Signed out"),
+    )
+)]
+async fn logout(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+) -> Result<axum::http::StatusCode, AppError> {
+    if let Some(token) = bearer_token(&headers) {
+        state.store.delete_access_token(token).await?;
+    }
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me",
+    tag = "auth",
+    summary = "The signed-in user",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, body = UserResponse),
+        (status = 401, description = "Not signed in", body = ErrorResponse),
+    )
+)]
+async fn get_me(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<UserResponse>, AppError> {
+    let user = require_user(state.store.as_ref(), &headers).await?;
+    Ok(Json(UserResponse {
+        id: user.id,
+        email: user.email,
+        created_at: user.created_at,
+    }))
+}
+
+// Take ownership of an anonymously created session by presenting its edit secret
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/sessions",
+    tag = "auth",
+    summary = "Take ownership of a session",
+    request_body = ClaimSessionRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Claimed"),
+        (status = 401, description = "Not signed in", body = ErrorResponse),
+        (status = 403, description = "Wrong edit secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
+async fn claim_session(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    Json(request): Json<ClaimSessionRequest>,
+) -> Result<axum::http::StatusCode, AppError> {
+    let user = require_user(state.store.as_ref(), &headers).await?;
+
+    let claimed = state.store.set_session_owner(&request.session_id, &request.edit_secret, &user.id)
+        .await?;
+
+    if !claimed {
+        return Err(AppError::Forbidden);
+    }
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/groups",
+    tag = "auth",
+    summary = "Take ownership of a group",
+    request_body = ClaimGroupRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Claimed"),
+        (status = 401, description = "Not signed in", body = ErrorResponse),
+        (status = 403, description = "Wrong edit secret", body = ErrorResponse),
+        (status = 404, description = "No such group", body = ErrorResponse),
+    )
+)]
+async fn claim_group(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    Json(request): Json<ClaimGroupRequest>,
+) -> Result<axum::http::StatusCode, AppError> {
+    let user = require_user(state.store.as_ref(), &headers).await?;
+
+    let claimed = state.store.set_group_owner(&request.group_id, &request.edit_secret, &user.id)
+        .await?;
+
+    if !claimed {
+        return Err(AppError::Forbidden);
+    }
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/sessions",
+    tag = "auth",
+    summary = "Sessions you own or take part in",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, body = Vec<UserSessionSummary>),
+        (status = 401, description = "Not signed in", body = ErrorResponse),
+    )
+)]
+async fn list_my_sessions(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<UserSessionSummary>>, AppError> {
+    let user = require_user(state.store.as_ref(), &headers).await?;
+
+    let owned = state.store.sessions_owned_by(&user.id).await?;
+
+    let joined = state.store.participant_links_for_user(&user.id).await?;
+
+    let mut summaries: Vec<UserSessionSummary> = owned
+        .iter()
+        .map(|session| UserSessionSummary {
+            id: session.id.clone(),
+            role: "owner".to_string(),
+            edit_secret: Some(session.edit_secret.clone()),
+            participant_name: joined.iter().find(|link| link.session_id == session.id).map(|link| link.name.clone()),
+            created_at: session.created_at,
+            last_accessed_at: session.last_accessed_at,
+            expires_at: session_expires_at(session, state.session_expiry_days),
+        })
+        .collect();
+
+    for link in &joined {
+        if owned.iter().any(|s| s.id == link.session_id) {
+            continue;
+        }
+        let session = state.store.get_session(&link.session_id).await?;
+
+        if let Some(session) = session {
+            summaries.push(UserSessionSummary {
+                expires_at: session_expires_at(&session, state.session_expiry_days),
+                id: session.id,
+                role: "participant".to_string(),
+                edit_secret: None,
+                participant_name: Some(link.name.clone()),
+                created_at: session.created_at,
+                last_accessed_at: session.last_accessed_at,
+            });
+        }
+    }
+
+    Ok(Json(summaries))
+}
+
+// Groups the user owns, plus groups containing a session they took part in
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/groups",
+    tag = "auth",
+    summary = "Groups you own or belong to",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, body = Vec<UserGroupSummary>),
+        (status = 401, description = "Not signed in", body = ErrorResponse),
+    )
+)]
+async fn list_my_groups(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<UserGroupSummary>>, AppError> {
+    let user = require_user(state.store.as_ref(), &headers).await?;
+
+    let groups = state.store.groups_for_user(&user.id).await?;
+
+    Ok(Json(
+        groups
+            .into_iter()
+            .map(|group| {
+                let is_owner = group.owner_user_id.as_deref() == Some(user.id.as_str());
+                UserGroupSummary {
+                    id: group.id,
+                    name: group.name,
+                    role: if is_owner { "owner" } else { "participant" }.to_string(),
+                    edit_secret: is_owner.then_some(group.edit_secret),
+                }
+            })
+            .collect(),
+    ))
+}
+
+fn bearer_token(headers: &axum::http::HeaderMap) -> Option<&str> {
+    headers.get(axum::http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+}
+
+// The signed-in user, if any. An invalid or expired token is treated as signed out
+// so anonymous requests behave exactly as they did before accounts existed.
+async fn current_user(
+    store: &dyn SessionStore,
+    headers: &axum::http::HeaderMap,
+) -> Result<Option<DbUser>, AppError> {
+    let Some(token) = bearer_token(headers) else {
+        return Ok(None);
+    };
+
+    Ok(store.user_for_access_token(token, Utc::now()).await?)
+}
+
+async fn require_user(
+    store: &dyn SessionStore,
+    headers: &axum::http::HeaderMap,
+) -> Result<DbUser, AppError> {
+    current_user(store, headers)
+        .await?
+        .ok_or(AppError::Unauthorized)
+}
+
+// Load a session and check the X-Edit-Secret header against it
+async fn authorize_session(
+    store: &dyn SessionStore,
+    id: &str,
+    headers: &axum::http::HeaderMap,
+) -> Result<DbSession, AppError> {
+    let secret = headers.get("X-Edit-Secret")
+        .and_then(|h| h.to_str().ok())
+        .ok_or(AppError::Forbidden)?;
+
+    let session = store.get_session(id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    if session.edit_secret != secret {
+        return Err(AppError::Forbidden);
+    }
+    Ok(session)
+}
+
+// When cleanup will delete the session if nobody touches it again; None for pinned sessions
+fn session_expires_at(session: &DbSession, default_expiry_days: i64) -> Option<DateTime<Utc>> {
+    if session.pinned {
+        return None;
+    }
+    // Out-of-range rows from before expiry_days was capped count as never expiring
+    // rather than panicking
+    let days = session.expiry_days.unwrap_or(default_expiry_days);
+    chrono::TimeDelta::try_days(days).and_then(|days| session.last_accessed_at.checked_add_signed(days))
+}
+
+// Make sure every unique name in the session has a personal link token.
+// Tokens are never rotated, so links already handed out keep working.
+async fn sync_participant_links(
+    store: &dyn SessionStore,
+    session_id: &str,
+    people: &[Person],
+) -> Result<(), sqlx::Error> {
+    let mut names: Vec<&str> = people.iter().map(|p| p.name.as_str()).collect();
+    names.sort();
+    names.dedup();
+
+    store.ensure_participant_links(session_id, &names, Utc::now()).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/sessions/{id}/participants",
+    tag = "participants",
+    summary = "Personal links for everyone in a session",
+    params(("id" = String, Path, description = "Session id")),
+    security(("edit_secret" = [])),
+    responses(
+        (status = 200, body = Vec<ParticipantLink>),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
+async fn list_participant_links(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<ParticipantLink>>, AppError> {
+    let session = authorize_session(state.store.as_ref(), &id, &headers).await?;
+
+    let people: Vec<Person> = serde_json::from_str(&session.people)?;
+
+    // Sessions created before personal links existed have no tokens yet
+    sync_participant_links(state.store.as_ref(), &id, &people).await?;
+
+    let links = state.store.participant_links_for_session(&id).await?;
+
+    Ok(Json(
+        links
+            .into_iter()
+            .filter(|link| people.iter().any(|p| p.name == link.name))
+            .map(|link| ParticipantLink {
+                name: link.name,
+                token: link.token,
+                claimed: link.claimed_at.is_some(),
+                email: link.email,
+                language: Language::from_code(link.language.as_deref()),
+            })
+            .collect(),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/sessions/{id}/participants",
+    tag = "participants",
+    summary = "Record participants' email addresses and languages",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = ParticipantSettingsRequest,
+    security(("edit_secret" = [])),
+    responses(
+        (status = 200, body = Vec<ParticipantLink>),
+        (status = 400, description = "Unknown participant or invalid address", body = ErrorResponse),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+    )
+)]
+async fn update_participant_emails(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+    Json(request): Json<ParticipantSettingsRequest>,
+) -> Result<Json<Vec<ParticipantLink>>, AppError> {
+    let session = authorize_session(state.store.as_ref(), &id, &headers).await?;
+    let people: Vec<Person> = serde_json::from_str(&session.people)?;
+
+    let unknown = request.emails.keys()
+        .chain(request.languages.keys())
+        .find(|name| !people.iter().any(|p| &p.name == *name));
+    if let Some(name) = unknown {
+        return Err(AppError::BadRequest(format!("{} is not a participant in this session", name)));
+    }
+
+    let mut emails = Vec::new();
+    for (name, email) in request.emails {
+        let email = email.map(|e| e.trim().to_lowercase()).filter(|e| !e.is_empty());
+        if email.as_ref().is_some_and(|e| !e.contains('@')) {
+            return Err(AppError::BadRequest("Invalid email address".to_string()));
+        }
+        emails.push((name, email));
+    }
+
+    sync_participant_links(state.store.as_ref(), &id, &people).await?;
+    for (name, email) in &emails {
+        state.store.set_participant_link_email(&id, name, email.as_deref()).await?;
+    }
+    for (name, language) in &request.languages {
+        state.store.set_participant_link_language(&id, name, language.code()).await?;
+    }
+
+    list_participant_links(State(state), axum::extract::Path(id), headers).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/participants/{token}",
+    tag = "participants",
+    summary = "One participant's share, ledger and transfers",
+    params(("token" = String, Path, description = "Personal link token")),
+    responses(
+        (status = 200, body = ParticipantViewResponse),
+        (status = 404, description = "Unknown token or participant", body = ErrorResponse),
+    )
+)]
+async fn get_participant_view(
+    State(state): State<AppState>,
+    axum::extract::Path(token): axum::extract::Path<String>,
+) -> Result<Json<ParticipantViewResponse>, AppError> {
+    let link = state.store.get_participant_link(&token)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    state.store.touch_session(&link.session_id, Utc::now()).await?;
+
+    let session = state.store.get_session(&link.session_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let people: Vec<Person> = serde_json::from_str(&session.people)?;
+
+    let expenses: Vec<Person> = people
+        .iter()
+        .filter(|p| p.name == link.name || p.paid_by.as_deref() == Some(link.name.as_str()))
+        .cloned()
+        .collect();
+
+    let result = split_bills_core::calculate_split(CalculateRequest {
+        people,
+        include_sponsor: session.include_sponsor,
+        restrict_sponsor_to_spent: Some(true),
+        fund_amount: session.fund_amount,
+        tip_percentage: session.tip_percentage,
+    });
+
+    // The participant may have been removed from the session after the link was issued
+    let settlement = result.settlements
+        .iter()
+        .find(|s| s.name == link.name)
+        .ok_or(AppError::NotFound)?;
+
+    let transfers = plan_transfers(&result.settlements)
+        .into_iter()
+        .filter(|t| t.from == link.name || t.to == link.name)
+        .collect();
+
+    Ok(Json(ParticipantViewResponse {
+        session_id: link.session_id,
+        name: link.name,
+        balance: settlement.balance,
+        settlement_type: settlement.settlement_type.clone(),
+        is_receiver: settlement.is_receiver,
+        ledger: build_ledger(settlement),
+        transfers,
+        expenses,
+        claimed_at: link.claimed_at,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/participants/{token}/claim",
+    tag = "participants",
+    summary = "Claim a personal link",
+    params(("token" = String, Path, description = "Personal link token")),
+    security((), ("bearer" = [])),
+    responses(
+        (status = 200, body = ClaimParticipantResponse),
+        (status = 404, description = "Unknown token", body = ErrorResponse),
+    )
+)]
+async fn claim_participant(
+    State(state): State<AppState>,
+    axum::extract::Path(token): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<ClaimParticipantResponse>, AppError> {
+    let user = current_user(state.store.as_ref(), &headers).await?;
+
+    let link = state.store.get_participant_link(&token)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    // A signed-in user claiming the link takes part in the session from their account
+    if let Some(user) = &user {
+        state.store.set_participant_link_user(&token, &user.id).await?;
+    }
+
+    // Claiming again from another device is allowed; keep the first claim time
+    let claimed_at = match link.claimed_at {
+        Some(claimed_at) => claimed_at,
+        None => {
+            let now = Utc::now();
+            state.store.mark_participant_link_claimed(&token, now).await?;
+            now
+        }
+    };
+
+    Ok(Json(ClaimParticipantResponse {
+        session_id: link.session_id,
+        name: link.name,
+        token: link.token,
+        claimed_at,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/calculate",
+    tag = "calculate",
+    summary = "Split a bill",
+    request_body = CalculateRequest,
+    responses(
+        (status = 200, body = CalculateResponse),
+        (status = 400, description = "The request failed validation", body = ErrorResponse),
+    )
+)]
+async fn calculate_split(
+    Json(request): Json<CalculateRequest>,
+) -> Result<Json<CalculateResponse>, AppError> {
+    validate_request(&request)?;
+    Ok(Json(split_bills_core::calculate_split(request)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/ai/text",
+    tag = "ai",
+    summary = "Extract a receipt from text",
+    request_body = AiTextRequest,
+    responses(
+        (status = 200, body = ai::ReceiptData),
+        (status = 429, description = "Rate limit or daily quota reached; see Retry-After", body = ErrorResponse),
+        (status = 502, description = "The AI provider failed", body = ErrorResponse),
+        (status = 503, description = "AI is not configured", body = ErrorResponse),
+    )
+)]
+async fn process_ai_text(
+    Json(request): Json<AiTextRequest>
+) -> Result<Json<ai::ReceiptData>, AppError> {
+    let provider = ai_provider()?;
+    provider.process_text(&request.text).await.map(Json).map_err(AppError::Ai)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/ai/split",
+    tag = "ai",
+    summary = "Turn a description of who paid what into expenses",
+    request_body = AiTextRequest,
+    responses(
+        (status = 200, body = ai::AiSplitResponse),
+        (status = 429, description = "Rate limit or daily quota reached; see Retry-After", body = ErrorResponse),
+        (status = 502, description = "The AI provider failed", body = ErrorResponse),
+        (status = 503, description = "AI is not configured", body = ErrorResponse),
+    )
+)]
+async fn process_ai_split_text(
+    Json(request): Json<AiTextRequest>
+) -> Result<Json<ai::AiSplitResponse>, AppError> {
+    let provider = ai_provider()?;
+    provider.process_split_text(&request.text).await.map(Json).map_err(AppError::Ai)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/ai/image",
+    tag = "ai",
+    summary = "Extract a receipt from a photo",
+    request_body(content_type = "multipart/form-data", description = "An `image` field with the photo"),
+    responses(
+        (status = 200, body = ai::ReceiptData),
+        (status = 400, description = "No image field", body = ErrorResponse),
+        (status = 429, description = "Rate limit or daily quota reached; see Retry-After", body = ErrorResponse),
+        (status = 502, description = "The AI provider failed", body = ErrorResponse),
+        (status = 503, description = "AI is not configured", body = ErrorResponse),
+    )
+)]
+async fn process_ai_image(
+    mut multipart: Multipart
+) -> Result<Json<ai::ReceiptData>, AppError> {
+    let provider = ai_provider()?;
+
     while let Ok(Some(field)) = multipart.next_field().await {
         let name = field.name().unwrap_or_default().to_string();
         if name == "image" {
             let content_type = field.content_type().unwrap_or("application/octet-stream").to_string();
-            
-            match field.bytes().await {
-                Ok(data) => {
-                    // Optimize image if needed
-                    let (optimized_data, optimized_content_type) = match image_utils::optimize_image(&data, &content_type) {
-                        Ok(result) => result,
-                        Err(e) => {
-                            tracing::warn!("Image optimization failed: {}, using original", e);
-                            (data.to_vec(), content_type)
-                        }
-                    };
-
-                    let provider = OpenAiProvider::new(api_key);
-                    match provider.process_image(&optimized_data, &optimized_content_type).await {
-                        Ok(data) => return Json(data).into_response(),
-                        Err(e) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
-                    }
+            let data = field.bytes()
+                .await
+                .map_err(|e| AppError::BadRequest(format!("Failed to read image data: {}", e)))?;
+
+            // Optimize image if needed
+            let (optimized_data, optimized_content_type) = match image_utils::optimize_image(&data, &content_type) {
+                Ok(result) => result,
+                Err(e) => {
+                    tracing::warn!("Image optimization failed: {}, using original", e);
+                    (data.to_vec(), content_type)
                 }
-                Err(e) => return (axum::http::StatusCode::BAD_REQUEST, format!("Failed to read image data: {}", e)).into_response(),
-            }
+            };
+
+            return provider.process_image(&optimized_data, &optimized_content_type)
+                .await
+                .map(Json)
+                .map_err(AppError::Ai);
         }
     }
-    (axum::http::StatusCode::BAD_REQUEST, "No image field found").into_response()
+    Err(AppError::BadRequest("No image field found".to_string()))
 }
 
-async fn send_email_handler(Json(payload): Json<SendEmailRequest>) -> impl IntoResponse {
-    if std::env::var("RESEND_API_KEY").is_err() {
-         return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "RESEND_API_KEY not configured").into_response();
+// The provider's credentials never leave the server; without them AI features are off
+fn ai_provider() -> Result<OpenAiProvider, AppError> {
+    let api_key = std::env::var("OPENAI_API_KEY").unwrap_or_default();
+    let model = std::env::var("OPENAI_API_MODEL").unwrap_or_default();
+    if api_key.is_empty() || model.is_empty() {
+        return Err(AppError::NotConfigured("AI"));
     }
+    Ok(OpenAiProvider::new(api_key, model))
+}
 
-    let email_service = email::EmailService::new();
-    match email_service.send_email(payload.to, &payload.subject, &payload.html_content, payload.cc, payload.bcc).await {
-        Ok(_) => axum::http::StatusCode::OK.into_response(),
-        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+#[utoipa::path(
+    post,
+    path = "/api/v1/sessions/{id}/email",
+    tag = "email",
+    summary = "Email the session's results to its participants",
+    description = "Each recipient gets their own email, rendered by the server from the session's settlement \
+        in the participant's language. Recipients must be addresses recorded on the session's participants \
+        (see PUT /api/v1/sessions/{id}/participants).",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = SendEmailRequest,
+    security(("edit_secret" = [])),
+    responses(
+        (status = 200, description = "Sent"),
+        (status = 400, description = "No recipients, or an address that isn't a participant's", body = ErrorResponse),
+        (status = 403, description = "Missing or wrong X-Edit-Secret", body = ErrorResponse),
+        (status = 404, description = "No such session", body = ErrorResponse),
+        (status = 429, description = "Rate limit or daily quota reached; see Retry-After", body = ErrorResponse),
+        (status = 502, description = "The email provider failed", body = ErrorResponse),
+        (status = 503, description = "Email is not configured", body = ErrorResponse),
+    )
+)]
+async fn send_email_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+    Json(payload): Json<SendEmailRequest>,
+) -> Result<axum::http::StatusCode, AppError> {
+    let session = authorize_session(state.store.as_ref(), &id, &headers).await?;
+
+    let mut to: Vec<String> = payload.to.iter().map(|e| e.trim().to_lowercase()).filter(|e| !e.is_empty()).collect();
+    to.sort();
+    to.dedup();
+    if to.is_empty() {
+        return Err(AppError::BadRequest("Add at least one recipient".to_string()));
     }
-}
 
-async fn check_request_id(
-    state: &AppState,
-    request_id: Option<String>,
-) -> Result<(), (axum::http::StatusCode, String)> {
-    if let Some(id) = request_id {
-        let mut cache = state.processed_requests.lock().await;
-        
-        // Cleanup expired entries (older than 5 minutes)
-        let now = Utc::now();
-        cache.retain(|_, timestamp| *timestamp > now - chrono::Duration::minutes(5));
-        
-        if cache.contains_key(&id) {
-            return Err((axum::http::StatusCode::CONFLICT, "Duplicate request".to_string()));
+    // Only people in the split can be emailed, so the endpoint can't be used to reach strangers
+    let people: Vec<Person> = serde_json::from_str(&session.people)?;
+    sync_participant_links(state.store.as_ref(), &id, &people).await?;
+    let links: Vec<DbParticipantLink> = state.store.participant_links_for_session(&id)
+        .await?
+        .into_iter()
+        .filter(|link| people.iter().any(|p| p.name == link.name))
+        .collect();
+    if let Some(stranger) = to.iter().find(|e| !links.iter().any(|link| link.email.as_ref() == Some(*e))) {
+        return Err(AppError::BadRequest(format!("{} is not the address of a participant in this session", stranger)));
+    }
+
+    let email_service = state.email.as_ref().ok_or(AppError::NotConfigured("Email"))?;
+
+    let result = split_bills_core::calculate_split(CalculateRequest {
+        people,
+        include_sponsor: session.include_sponsor,
+        restrict_sponsor_to_spent: Some(true),
+        fund_amount: session.fund_amount,
+        tip_percentage: session.tip_percentage,
+    });
+
+    let mut attachments = Vec::new();
+    if !payload.attachments.is_empty() {
+        let report = report::SettlementReport::from_session(&session, Utc::now())?;
+        let mut formats = payload.attachments.clone();
+        formats.sort();
+        formats.dedup();
+        for format in formats {
+            attachments.push(report.attachment(format).map_err(AppError::Internal)?);
         }
-        
-        cache.insert(id, now);
     }
-    Ok(())
+
+    // Everyone gets their own email, in their own language, linking to their personal page
+    let recipients = links.iter().filter(|link| link.email.as_ref().is_some_and(|e| to.contains(e)));
+    for link in recipients {
+        let language = Language::from_code(link.language.as_deref());
+        let view_url = format!("{}/?me={}", base_url(), link.token);
+        let Some(message) = email::settlement_email(&result, &link.name, session.currency.as_deref(), language, view_url) else {
+            continue;
+        };
+        let html = SettlementEmailHtml { email: &message }.render()?;
+        let text = SettlementEmailText { email: &message }.render()?;
+        let address = link.email.clone().unwrap_or_default();
+        let options = email::EmailOptions { text: Some(text), attachments: attachments.clone() };
+        email_service.send_email(vec![address], message.t.subject, &html, options)
+            .await
+            .map_err(|e| AppError::Email(e.to_string()))?;
+    }
+    Ok(axum::http::StatusCode::OK)
 }
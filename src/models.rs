@@ -1,10 +1,20 @@
 use askama::Template;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, SqlitePool};
-use std::collections::HashMap;
+use sqlx::FromRow;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::email::{EmailService, EmailStrings};
+use crate::rate_limit::RateLimiter;
+use crate::report::ReportFormat;
+use crate::store::SessionStore;
+
+// The calculation types live in the core crate; re-exported so handlers keep using models::*
+pub use split_bills_core::{
+    default_include_sponsor, default_quantity, CalculateRequest, CalculateResponse, LedgerEntry, MemberBalance, Person, Settlement,
+    Transfer,
+};
 
 // Template structs
 #[derive(Template)]
@@ -13,31 +23,138 @@ pub struct IndexTemplate {
     pub base_url: String,
 }
 
-// Helper functions
-pub fn default_quantity() -> u32 {
-    1
+#[derive(Template)]
+#[template(path = "expiry_warning.html")]
+pub struct ExpiryWarningTemplate {
+    pub num_participants: usize,
+    pub total_spent: String,
+    pub expires_at: String,
+    pub view_url: String,
+    pub extend_url: String,
 }
 
-// Core data models
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Person {
-    pub id: u64,
+// One participant's results email. The HTML and plain-text templates below render the
+// same data, in the participant's language.
+pub struct SettlementEmail {
+    pub t: &'static EmailStrings,
     pub name: String,
-    pub description: String,
-    pub amount_spent: f64,
-    #[serde(default = "default_quantity")]
-    pub quantity: u32,
-    #[serde(default)]
-    pub tip: f64,
-    pub is_sponsor: bool,
-    pub sponsor_amount: f64,
-    #[serde(default)]
-    pub is_receiver: bool,
-    #[serde(default)]
-    pub paid_by: Option<String>,
+    // "pay", "receive" or "settled"
+    pub settlement_type: String,
+    // The absolute balance
+    pub amount: String,
+    pub transfers: Vec<SettlementEmailTransfer>,
+    pub ledger: Vec<SettlementEmailLedgerLine>,
+    pub balance: String,
+    pub total_spent: String,
+    pub total_sponsored: String,
+    // Only shown when non-zero
+    pub total_tip: Option<String>,
+    pub fund_amount: Option<String>,
+    pub amount_to_share: String,
+    pub num_participants: usize,
+    pub per_person_share: String,
+    pub rows: Vec<SettlementEmailRow>,
+    // The participant's personal link
+    pub view_url: String,
+}
+
+pub struct SettlementEmailTransfer {
+    // Whether the participant pays `other` or is paid by them
+    pub pays: bool,
+    pub other: String,
+    pub amount: String,
+}
+
+pub struct SettlementEmailLedgerLine {
+    pub label: String,
+    pub amount: String,
+}
+
+pub struct SettlementEmailRow {
+    pub name: String,
+    pub amount_spent: String,
+    pub tip_paid: String,
+    pub sponsor_cost: String,
+    pub share_cost: String,
+    pub balance: String,
+    // "pay", "receive" or "settled"
+    pub settlement_type: String,
+    // The absolute balance, for the "Pay x" / "Receive x" column
+    pub amount: String,
+}
+
+impl SettlementEmailRow {
+    pub fn color(&self) -> &'static str {
+        match self.settlement_type.as_str() {
+            "pay" => "#fed7d7",
+            "receive" => "#c6f6d5",
+            _ => "#ffffff",
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "settlement_email.html")]
+pub struct SettlementEmailHtml<'a> {
+    pub email: &'a SettlementEmail,
+}
+
+#[derive(Template)]
+#[template(path = "settlement_email.txt")]
+pub struct SettlementEmailText<'a> {
+    pub email: &'a SettlementEmail,
+}
+
+// Sent on a session's reminder schedule to each participant who still owes money
+#[derive(Template)]
+#[template(path = "payment_reminder.html")]
+pub struct PaymentReminderTemplate {
+    pub t: &'static EmailStrings,
+    pub name: String,
+    pub amount: String,
+    // Who to pay and how much
+    pub transfers: Vec<SettlementEmailTransfer>,
+    pub view_url: String,
+    pub paid_url: String,
+}
+
+// What the people a reminded debtor pays get instead of a copy of the reminder, which
+// links to the debtor's personal page
+#[derive(Template)]
+#[template(path = "payment_reminder_creditor.html")]
+pub struct PaymentReminderCreditorTemplate {
+    pub t: &'static EmailStrings,
+    pub name: String,
+    pub debtor: String,
+    // What the debtor owes this person
+    pub amount: String,
+    pub paid_url: String,
+}
+
+// Behind the mark-paid link in payment reminders. The link only shows this page, so mail
+// scanners that follow it don't mark anything; the button posts back to the same URL.
+#[derive(Template)]
+#[template(path = "reminder_paid.html")]
+pub struct ReminderPaidTemplate {
+    pub t: &'static EmailStrings,
+    pub name: String,
+    pub amount: String,
+    pub paid: bool,
+}
+
+// Interactive docs for /api/openapi.json
+#[derive(Template)]
+#[template(path = "api_docs.html")]
+pub struct ApiDocsTemplate;
+
+#[derive(Template)]
+#[template(path = "magic_link.html")]
+pub struct MagicLinkTemplate {
+    pub login_url: String,
+    pub valid_minutes: i64,
 }
 
-#[derive(Debug, FromRow)]
+#[derive(Debug, Clone, FromRow)]
 #[allow(dead_code)]
 pub struct DbSession {
     pub id: String,
@@ -49,106 +166,624 @@ pub struct DbSession {
     pub fund_amount: f64,
     #[sqlx(default)]
     pub tip_percentage: f64,
+    #[sqlx(default)]
+    pub include_sponsor: bool,
+    #[sqlx(default)]
+    pub expiry_days: Option<i64>,
+    #[sqlx(default)]
+    pub pinned: bool,
+    #[sqlx(default)]
+    pub notification_emails: String,
+    #[sqlx(default)]
+    pub expiry_warning_sent_at: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    pub extend_token: Option<String>,
+    #[sqlx(default)]
+    pub currency: Option<String>,
+    #[sqlx(default)]
+    pub owner_user_id: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+#[allow(dead_code)]
+pub struct DbParticipantLink {
+    pub token: String,
+    pub session_id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub claimed_at: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    pub user_id: Option<String>,
+    #[sqlx(default)]
+    pub email: Option<String>,
+    // None for links made before languages could be set; see Language
+    #[sqlx(default)]
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct DbArchive {
+    pub id: String,
+    pub session_id: String,
+    pub edit_secret: String,
+    pub owner_token: Option<String>,
+    pub group_id: Option<String>,
+    pub title: String,
+    pub people: String,
+    pub fund_amount: f64,
+    pub tip_percentage: f64,
+    pub include_sponsor: bool,
+    pub calculation: String,
+    pub archived_at: DateTime<Utc>,
+    #[sqlx(default)]
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+#[allow(dead_code)]
+pub struct DbTemplate {
+    pub id: String,
+    pub owner_token: String,
+    pub name: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+#[allow(dead_code)]
+pub struct DbGroup {
+    pub id: String,
+    pub edit_secret: String,
+    pub name: String,
+    pub members: String,
+    pub created_at: DateTime<Utc>,
+    #[sqlx(default)]
+    pub owner_user_id: Option<String>,
+}
+
+// A session's balances as last seen, so the group ledger survives session expiry
+#[derive(Debug, Clone, FromRow)]
+#[allow(dead_code)]
+pub struct DbGroupSession {
+    pub group_id: String,
+    pub session_id: String,
+    pub balances: String,
+    pub added_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// An operation applied through the sync endpoint, kept so replays are ignored and
+// older changes to the same target lose
+#[derive(Debug, Clone, FromRow)]
+pub struct DbSyncOperation {
+    pub session_id: String,
+    pub op_id: String,
+    // What the operation changed, e.g. `expense:42` or `participant:Alice`
+    pub target: String,
+    // Milliseconds since the epoch, as sent by the client
+    pub client_timestamp: i64,
+    pub applied_at: DateTime<Utc>,
+}
+
+// A mutating request sent with an Idempotency-Key, and the response it got so a retry
+// can be answered with it. Until the handler finishes, status and body are None.
+#[derive(Debug, Clone, FromRow)]
+pub struct DbIdempotencyRecord {
+    // sha256 of the client's credentials or address and its key, hex encoded
+    pub key: String,
+    // sha256 of the method, path, credentials and body, hex encoded
+    pub request_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub status: Option<i64>,
+    pub content_type: Option<String>,
+    // Encrypted with the client's key, as responses can carry secrets (see idempotency.rs)
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct DbUser {
+    pub id: String,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+#[allow(dead_code)]
+pub struct DbLoginToken {
+    pub token: String,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+// How many rate-limited requests a subject (e.g. `ai:ip:203.0.113.7`) made on a UTC day
+#[derive(Debug, Clone, FromRow)]
+#[allow(dead_code)]
+pub struct DbDailyUsage {
+    pub day: NaiveDate,
+    pub subject: String,
+    pub count: i64,
+}
+
+// An IP address or session id that the per-client rate limits and quotas don't apply to
+#[derive(Debug, Clone, FromRow)]
+pub struct DbRateLimitExemption {
+    pub subject: String,
+    pub note: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// When a session's debtors are reminded to pay: first_after_days after the session was
+// created, then every repeat_days (0 sends just the one)
+#[derive(Debug, Clone, FromRow)]
+pub struct DbReminderSchedule {
+    pub session_id: String,
+    pub first_after_days: i64,
+    pub repeat_days: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+// One debtor's reminders. The token is the mark-as-paid link in their emails and stays
+// the same across reminders.
+#[derive(Debug, Clone, FromRow)]
+pub struct DbPaymentReminder {
+    pub token: String,
+    pub session_id: String,
+    pub name: String,
+    // Owed when the last reminder went out
+    pub amount: f64,
+    // JSON array of the transfers that reminder asked for
+    #[sqlx(default)]
+    pub transfers: String,
+    pub reminders_sent: i64,
+    pub last_sent_at: DateTime<Utc>,
+    pub paid_at: Option<DateTime<Utc>>,
 }
 
 // API request/response structs
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CreateSessionRequest {
     pub people: Vec<Person>,
     #[serde(default)]
     pub fund_amount: f64,
     #[serde(default)]
     pub tip_percentage: f64,
+    #[serde(default = "default_include_sponsor")]
+    pub include_sponsor: bool,
+    #[serde(default)]
+    pub expiry_days: Option<i64>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CreateSessionQuery {
+    #[serde(default)]
+    pub template: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct CreateSessionResponse {
     pub id: String,
     pub edit_secret: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct GetSessionResponse {
     pub people: Vec<Person>,
     pub fund_amount: f64,
     pub tip_percentage: f64,
+    pub include_sponsor: bool,
+    pub pinned: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub currency: Option<String>,
+}
+
+// Offline edits from a client, replayed with POST /api/v1/sessions/:id/sync
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SyncRequest {
+    pub operations: Vec<SyncOperation>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct SyncOperation {
+    // Client-generated, unique per operation; resending it is a no-op
+    pub op_id: String,
+    // Milliseconds since the epoch when the change was made, i.e. Date.now()
+    pub client_timestamp: i64,
+    #[serde(flatten)]
+    pub change: SyncChange,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SyncChange {
+    // Add and edit both write the whole line, keyed by its id
+    AddExpense { expense: Person },
+    EditExpense { expense: Person },
+    DeleteExpense { expense_id: u64 },
+    AddParticipant { name: String },
+    EditParticipant { name: String, new_name: String },
+    DeleteParticipant { name: String },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SyncResponse {
+    pub session: GetSessionResponse,
+    // Including operations that were already applied by an earlier sync
+    pub applied: Vec<String>,
+    pub rejected: Vec<RejectedOperation>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RejectedOperation {
+    pub op_id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct UpdateSessionRequest {
     pub people: Vec<Person>,
     #[serde(default)]
     pub fund_amount: f64,
     #[serde(default)]
     pub tip_percentage: f64,
+    #[serde(default = "default_include_sponsor")]
+    pub include_sponsor: bool,
+    // None keeps the session's current currency
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SessionExpiryRequest {
+    // None falls back to the server-wide default
+    #[serde(default)]
+    pub expiry_days: Option<i64>,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionExpiryResponse {
+    pub expiry_days: Option<i64>,
+    pub pinned: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SessionNotificationsRequest {
+    pub emails: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionNotificationsResponse {
+    pub emails: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct ReminderSchedule {
+    // Days after the session was created before the first reminder
+    pub first_after_days: i64,
+    // Days between reminders after that; 0 sends only the first
+    pub repeat_days: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PaymentReminderStatus {
+    pub name: String,
+    pub amount: f64,
+    pub reminders_sent: i64,
+    pub last_sent_at: DateTime<Utc>,
+    pub paid_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RemindersResponse {
+    // None when reminders are off
+    pub schedule: Option<ReminderSchedule>,
+    // Everyone reminded so far
+    pub reminders: Vec<PaymentReminderStatus>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExtendSessionQuery {
+    pub token: String,
+}
+
+// Server-side archives
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct ArchiveSessionRequest {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub group_id: Option<String>,
+    // Required with group_id, as X-Edit-Secret carries the session's secret
+    #[serde(default)]
+    pub group_edit_secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListArchivesQuery {
+    #[serde(default)]
+    pub group_id: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CalculateRequest {
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ArchiveResponse {
+    pub id: String,
+    pub session_id: String,
+    pub group_id: Option<String>,
+    pub title: String,
+    pub archived_at: DateTime<Utc>,
     pub people: Vec<Person>,
+    pub fund_amount: f64,
+    pub tip_percentage: f64,
     pub include_sponsor: bool,
-    pub restrict_sponsor_to_spent: Option<bool>,
+    pub currency: Option<String>,
+    pub calculation: CalculateResponse,
+}
+
+// Persistent groups
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct GroupRequest {
+    pub name: String,
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreateGroupResponse {
+    pub id: String,
+    pub edit_secret: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AddGroupSessionRequest {
+    pub session_id: String,
+    pub session_edit_secret: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GroupSessionSummary {
+    pub session_id: String,
+    // False once the session itself has expired; its balances still count
+    pub active: bool,
+    pub added_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub balances: Vec<MemberBalance>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GroupResponse {
+    pub id: String,
+    pub name: String,
+    pub members: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub sessions: Vec<GroupSessionSummary>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GroupLedgerResponse {
+    pub balances: Vec<MemberBalance>,
+    pub transfers: Vec<Transfer>,
+    pub num_sessions: usize,
+}
+
+// User accounts
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LoginRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct VerifyLoginRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserResponse {
+    pub id: String,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VerifyLoginResponse {
+    pub access_token: String,
+    pub expires_at: DateTime<Utc>,
+    pub user: UserResponse,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ClaimSessionRequest {
+    pub session_id: String,
+    pub edit_secret: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ClaimGroupRequest {
+    pub group_id: String,
+    pub edit_secret: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserSessionSummary {
+    pub id: String,
+    // "owner" or "participant"
+    pub role: String,
+    // Only returned to the owner, so lost edit links can be recovered
+    pub edit_secret: Option<String>,
+    pub participant_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_accessed_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserGroupSummary {
+    pub id: String,
+    pub name: String,
+    pub role: String,
+    pub edit_secret: Option<String>,
+}
+
+// Reusable session templates
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SessionTemplate {
+    pub name: String,
+    // Everyone who should show up in a new session, even without expense lines
+    #[serde(default)]
+    pub roster: Vec<String>,
     #[serde(default)]
     pub fund_amount: f64,
     #[serde(default)]
     pub tip_percentage: f64,
+    #[serde(default = "default_include_sponsor")]
+    pub include_sponsor: bool,
+    #[serde(default)]
+    pub receiver: Option<String>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    // Recurring lines copied into every new session, e.g. court rental
+    #[serde(default)]
+    pub expenses: Vec<Person>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct CalculateResponse {
-    pub total_spent: f64,
-    pub total_sponsored: f64,
-    pub fund_amount: f64,
-    pub total_tip: f64,
-    pub amount_to_share: f64,
-    pub num_participants: usize,
-    pub per_person_share: f64,
-    pub settlements: Vec<Settlement>,
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TemplateResponse {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub template: SessionTemplate,
+}
+
+// Per-participant personal links
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ParticipantLink {
+    pub name: String,
+    pub token: String,
+    pub claimed: bool,
+    // Where results for this participant may be emailed
+    pub email: Option<String>,
+    // What language their emails are written in
+    pub language: Language,
+}
+
+// Participant name -> email address (null clears it) and participant name -> language.
+// Names left out are unchanged.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ParticipantSettingsRequest {
+    #[serde(default)]
+    pub emails: std::collections::BTreeMap<String, Option<String>>,
+    #[serde(default)]
+    pub languages: std::collections::BTreeMap<String, Language>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    Vi,
+}
+
+impl Language {
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::Vi => "vi",
+        }
+    }
+
+    // Unknown or missing codes fall back to English
+    pub fn from_code(code: Option<&str>) -> Language {
+        match code {
+            Some("vi") => Language::Vi,
+            _ => Language::En,
+        }
+    }
 }
 
-// Calculation structs
-#[derive(Debug, Serialize)]
-pub struct Settlement {
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ParticipantViewResponse {
+    pub session_id: String,
     pub name: String,
-    pub amount_spent: f64,
-    pub tip_paid: f64,
-    pub sponsor_cost: f64,
-    pub share_cost: f64,
     pub balance: f64,
     pub settlement_type: String,
     pub is_receiver: bool,
+    pub ledger: Vec<LedgerEntry>,
+    pub transfers: Vec<Transfer>,
+    pub expenses: Vec<Person>,
+    pub claimed_at: Option<DateTime<Utc>>,
 }
 
-pub struct PersonSummary {
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ClaimParticipantResponse {
+    pub session_id: String,
     pub name: String,
-    pub amount_spent: f64,
-    pub tip: f64,
-    pub sponsor_amount: f64,
-    pub is_sponsor: bool,
-    pub is_receiver: bool,
-    pub will_receive_from_others: f64,  // Amount they will receive as reimbursement
-    pub owes_to_others: f64, // Amount they owe to reimburse others
-    pub delegated_self: f64, // Amount they marked as paid_by themselves (private expense)
+    pub token: String,
+    pub claimed_at: DateTime<Utc>,
+}
+
+// API v2 shapes; see v2.rs
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CalculateResponseV2 {
+    pub summary: CalculateSummary,
+    pub settlements: Vec<Settlement>,
+    pub transfers: Vec<Transfer>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CalculateSummary {
+    pub total_spent: f64,
+    pub total_sponsored: f64,
+    pub fund_amount: f64,
+    pub total_tip: f64,
+    pub amount_to_share: f64,
+    pub num_participants: usize,
+    pub per_person_share: f64,
+}
+
+// Body of every error response; see error.rs
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    pub details: Option<serde_json::Value>,
+    pub request_id: String,
 }
 
 // Service request structs
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct AiTextRequest {
     pub text: String,
 }
 
-#[derive(Deserialize)]
+// Emails the session's settlement, rendered by the server. Only addresses recorded on
+// the session's participants are accepted.
+#[derive(Deserialize, ToSchema)]
 pub struct SendEmailRequest {
     pub to: Vec<String>,
-    pub subject: String,
-    pub html_content: String,
-    pub cc: Option<Vec<String>>,
-    pub bcc: Option<Vec<String>>,
+    // Settlement reports to attach, e.g. ["csv", "pdf"]
+    #[serde(default)]
+    pub attachments: Vec<ReportFormat>,
 }
 
 // Application state
 #[derive(Clone)]
 pub struct AppState {
-    pub pool: SqlitePool,
-    pub processed_requests: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
+    pub store: Arc<dyn SessionStore>,
+    pub session_expiry_days: i64,
+    pub limiter: Arc<RateLimiter>,
+    // None when no email transport is configured
+    pub email: Option<Arc<EmailService>>,
 }
@@ -0,0 +1,123 @@
+use axum::response::IntoResponse;
+use axum::Json;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::models::ApiDocsTemplate;
+
+// The API description, generated from the handlers' #[utoipa::path] attributes and the
+// request/response types. openapi.json in the repository root is a snapshot of it;
+// test_openapi_snapshot fails when the two differ.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Split Bills API",
+        description = "Split shared bills, keep sessions in sync and settle up. Errors are JSON `ErrorResponse` bodies.\n\n\
+            Every /api/v1 path also answers unversioned under /api, with Deprecation and Sunset headers. \
+            /api/v2 serves the v2 paths listed here and v1 for everything else.\n\n\
+            Mutating requests accept an `Idempotency-Key` header: retries with the same key and body get the \
+            first response back (with `Idempotent-Replayed: true`) for 24 hours, a different body under a used \
+            key gets 422.",
+    ),
+    paths(
+        crate::calculate_split,
+        crate::create_session,
+        crate::get_session,
+        crate::update_session,
+        crate::delete_session,
+        crate::sync_session,
+        crate::update_session_expiry,
+        crate::get_session_notifications,
+        crate::update_session_notifications,
+        crate::extend_session,
+        crate::download_report,
+        crate::get_reminders,
+        crate::update_reminders,
+        crate::delete_reminders,
+        crate::reminder_paid_page,
+        crate::mark_reminder_paid,
+        crate::list_participant_links,
+        crate::update_participant_emails,
+        crate::archive_session,
+        crate::list_archives,
+        crate::get_archive,
+        crate::delete_archive,
+        crate::restore_archive,
+        crate::clone_archive,
+        crate::list_templates,
+        crate::create_template,
+        crate::get_template,
+        crate::update_template,
+        crate::delete_template,
+        crate::create_group,
+        crate::get_group,
+        crate::update_group,
+        crate::add_group_session,
+        crate::remove_group_session,
+        crate::get_group_ledger,
+        crate::request_login,
+        crate::verify_login,
+        crate::logout,
+        crate::get_me,
+        crate::list_my_sessions,
+        crate::claim_session,
+        crate::list_my_groups,
+        crate::claim_group,
+        crate::get_participant_view,
+        crate::claim_participant,
+        crate::process_ai_text,
+        crate::process_ai_split_text,
+        crate::process_ai_image,
+        crate::send_email_handler,
+        crate::v2::calculate_split,
+    ),
+    modifiers(&Credentials),
+    tags(
+        (name = "calculate", description = "Stateless bill splitting"),
+        (name = "sessions", description = "Shared sessions, edited with their X-Edit-Secret"),
+        (name = "participants", description = "Personal links for each participant"),
+        (name = "archives", description = "Snapshots of sessions that outlive expiry"),
+        (name = "templates", description = "Reusable starting points for sessions"),
+        (name = "groups", description = "Running balances across several sessions"),
+        (name = "auth", description = "Optional accounts with email sign-in"),
+        (name = "ai", description = "Receipt and expense extraction"),
+        (name = "email", description = "Emailing results"),
+        (name = "reminders", description = "Scheduled emails to participants who still owe money"),
+    )
+)]
+pub struct ApiDoc;
+
+// The three ways to prove access, referenced by the paths' `security(...)`
+struct Credentials;
+
+impl Modify for Credentials {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "edit_secret",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "X-Edit-Secret",
+                "Returned when a session or group is created",
+            ))),
+        );
+        components.add_security_scheme(
+            "owner_token",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "X-Owner-Token",
+                "Any secret the client picks to group its archives and templates",
+            ))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+pub async fn api_docs() -> impl IntoResponse {
+    ApiDocsTemplate
+}
@@ -0,0 +1,134 @@
+use unicode_normalization::UnicodeNormalization;
+
+// A4 in points
+const PAGE_WIDTH: f64 = 595.0;
+const PAGE_HEIGHT: f64 = 842.0;
+const MARGIN: f64 = 50.0;
+
+// Courier is 600/1000 of the font size wide, so text can be laid out in columns
+pub const MONO_SIZE: f64 = 9.0;
+pub const MONO_COLUMNS: usize = ((PAGE_WIDTH - 2.0 * MARGIN) / (MONO_SIZE * 0.6)) as usize;
+
+#[derive(Clone, Copy)]
+pub enum Font {
+    Heading,
+    Mono,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Heading => "F1",
+            Font::Mono => "F2",
+        }
+    }
+}
+
+// Just enough PDF for text reports: lines of text top to bottom, flowing onto new pages.
+// It uses the standard Helvetica-Bold and Courier fonts, which every viewer has, so
+// nothing is embedded; they only cover Latin-1, so other letters lose their accents
+// (see `encode`).
+pub struct PdfDocument {
+    pages: Vec<String>,
+    y: f64,
+}
+
+impl PdfDocument {
+    pub fn new() -> Self {
+        PdfDocument { pages: vec![String::new()], y: PAGE_HEIGHT - MARGIN }
+    }
+
+    pub fn line(&mut self, font: Font, size: f64, text: &str) {
+        let leading = size * 1.4;
+        if self.y - leading < MARGIN {
+            self.pages.push(String::new());
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+        self.y -= leading;
+        let page = self.pages.last_mut().expect("a document always has a page");
+        page.push_str(&format!(
+            "BT /{} {} Tf {} {:.2} Td ({}) Tj ET\n",
+            font.resource(),
+            size,
+            MARGIN,
+            self.y,
+            escape(&encode(text)),
+        ));
+    }
+
+    pub fn space(&mut self, points: f64) {
+        self.y -= points;
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        let mut objects: Vec<Vec<u8>> = Vec::new();
+        let page_ids: Vec<usize> = (0..self.pages.len()).map(|i| 5 + 2 * i).collect();
+        let kids: Vec<String> = page_ids.iter().map(|id| format!("{} 0 R", id)).collect();
+
+        objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+        objects.push(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), page_ids.len()).into_bytes());
+        objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_vec());
+        objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>".to_vec());
+        for (page, id) in self.pages.iter().zip(&page_ids) {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH, PAGE_HEIGHT, id + 1
+            ).into_bytes());
+            let mut stream = format!("<< /Length {} >>\nstream\n", page.len()).into_bytes();
+            stream.extend_from_slice(page.as_bytes());
+            stream.extend_from_slice(b"endstream");
+            objects.push(stream);
+        }
+
+        let mut out = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            out.extend_from_slice(object);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = out.len();
+        out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        out.extend_from_slice(
+            format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).as_bytes(),
+        );
+        out
+    }
+}
+
+// Latin-1, which WinAnsiEncoding matches for printable characters. Anything else is
+// written without its accents ("Nguyễn" becomes "Nguyen"), or as "?".
+fn encode(text: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            ' '..='~' | '\u{a0}'..='\u{ff}' => out.push(c as u8),
+            'đ' => out.push(b'd'),
+            'Đ' => out.push(b'D'),
+            _ => match c.nfd().next() {
+                Some(base) if base.is_ascii_graphic() => out.push(base as u8),
+                _ => out.push(b'?'),
+            },
+        }
+    }
+    out
+}
+
+fn escape(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'(' | b')' | b'\\' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    out
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, RawPathParams, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use chrono::{NaiveTime, Utc};
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+use crate::models::AppState;
+
+// Idle buckets are dropped once there are this many, so made-up session ids can't grow the map forever
+const MAX_BUCKETS: usize = 10_000;
+
+// The endpoints that spend money upstream, each with its own limits and counters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Ai,
+    Email,
+}
+
+impl Feature {
+    fn name(self) -> &'static str {
+        match self {
+            Feature::Ai => "ai",
+            Feature::Email => "email",
+        }
+    }
+}
+
+// Requests per minute for each token bucket, which also holds up to a minute's worth as
+// a burst, and requests per UTC day. 0 turns a limit off.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub per_ip: u32,
+    pub per_session: u32,
+    pub global: u32,
+    pub daily_per_ip: u32,
+    pub daily: u32,
+}
+
+impl Limits {
+    // AI requests aren't made on a session, so only the per-IP and global limits apply
+    pub const AI: Limits = Limits { per_ip: 6, per_session: 0, global: 60, daily_per_ip: 100, daily: 2000 };
+    pub const EMAIL: Limits = Limits { per_ip: 3, per_session: 5, global: 30, daily_per_ip: 30, daily: 500 };
+
+    // e.g. AI_LIMIT_PER_IP, AI_LIMIT_PER_SESSION, AI_LIMIT_GLOBAL, AI_DAILY_QUOTA_PER_IP
+    // and AI_DAILY_QUOTA, falling back to the defaults
+    pub fn from_env(prefix: &str, defaults: Limits) -> Limits {
+        let var = |name: &str, default: u32| {
+            std::env::var(format!("{}_{}", prefix, name))
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        Limits {
+            per_ip: var("LIMIT_PER_IP", defaults.per_ip),
+            per_session: var("LIMIT_PER_SESSION", defaults.per_session),
+            global: var("LIMIT_GLOBAL", defaults.global),
+            daily_per_ip: var("DAILY_QUOTA_PER_IP", defaults.daily_per_ip),
+            daily: var("DAILY_QUOTA", defaults.daily),
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// Token buckets live in process memory, so each instance enforces its own share of the
+// per-minute limits. The daily quotas are counted in the database and hold across instances.
+pub struct RateLimiter {
+    ai: Limits,
+    email: Limits,
+    // Take the client's address from X-Forwarded-For, for servers behind a reverse proxy
+    trust_forwarded_for: bool,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(Limits::AI, Limits::EMAIL, false)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(ai: Limits, email: Limits, trust_forwarded_for: bool) -> Self {
+        RateLimiter { ai, email, trust_forwarded_for, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn from_env() -> Self {
+        RateLimiter::new(
+            Limits::from_env("AI", Limits::AI),
+            Limits::from_env("EMAIL", Limits::EMAIL),
+            std::env::var("TRUST_FORWARDED_FOR").is_ok_and(|v| v == "1" || v == "true"),
+        )
+    }
+
+    pub fn client_ip(&self, request: &Request) -> String {
+        client_ip(request, self.trust_forwarded_for)
+    }
+
+    fn limits(&self, feature: Feature) -> Limits {
+        match feature {
+            Feature::Ai => self.ai,
+            Feature::Email => self.email,
+        }
+    }
+
+    // Take a token from every bucket or from none of them. Err carries how long until
+    // the emptiest bucket has a token again.
+    async fn take(&self, buckets: &[(String, u32)]) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut map = self.buckets.lock().await;
+        if map.len() > MAX_BUCKETS {
+            map.retain(|_, bucket| now.duration_since(bucket.updated) < Duration::from_secs(60));
+        }
+
+        let mut wait = Duration::ZERO;
+        for (key, per_minute) in buckets {
+            let capacity = *per_minute as f64;
+            let bucket = map.entry(key.clone()).or_insert(Bucket { tokens: capacity, updated: now });
+            let refill = now.duration_since(bucket.updated).as_secs_f64() * capacity / 60.0;
+            bucket.tokens = (bucket.tokens + refill).min(capacity);
+            bucket.updated = now;
+            if bucket.tokens < 1.0 {
+                wait = wait.max(Duration::from_secs_f64((1.0 - bucket.tokens) * 60.0 / capacity));
+            }
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        for (key, _) in buckets {
+            if let Some(bucket) = map.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Layer for the AI and email routes. The per-session buckets are keyed on the route's
+// `:id`, so one busy session can't use up the whole server's allowance. Clients on the
+// exemption list (see the `exempt` admin command) skip the per-IP and per-session limits;
+// the global ones always apply.
+pub async fn limit(
+    State((state, feature)): State<(AppState, Feature)>,
+    params: RawPathParams,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let limiter = &state.limiter;
+    let limits = limiter.limits(feature);
+    let ip = limiter.client_ip(&request);
+    let session = params.iter()
+        .find(|(key, _)| *key == "id")
+        .map(|(_, id)| id.to_string());
+
+    let mut exempt = state.store.get_rate_limit_exemption(&ip).await?.is_some();
+    if let (false, Some(session)) = (exempt, &session) {
+        exempt = state.store.get_rate_limit_exemption(session).await?.is_some();
+    }
+
+    let name = feature.name();
+    let mut buckets = vec![(format!("{}:all", name), limits.global)];
+    if !exempt {
+        buckets.push((format!("{}:ip:{}", name, ip), limits.per_ip));
+        if let Some(session) = &session {
+            buckets.push((format!("{}:session:{}", name, session), limits.per_session));
+        }
+    }
+    buckets.retain(|(_, per_minute)| *per_minute > 0);
+    if let Err(wait) = limiter.take(&buckets).await {
+        tracing::warn!("Rate limited {} request from {}", name, ip);
+        return Err(AppError::RateLimited { retry_after: wait.as_secs_f64().ceil().max(1.0) as u64 });
+    }
+
+    // The address's own quota comes first, so a client that has used it up can't keep
+    // spending the server's
+    let now = Utc::now();
+    let today = now.date_naive();
+    let mut quotas = Vec::new();
+    if !exempt {
+        quotas.push((format!("{}:ip:{}", name, ip), limits.daily_per_ip));
+    }
+    quotas.push((format!("{}:all", name), limits.daily));
+    quotas.retain(|(_, quota)| *quota > 0);
+    for (subject, quota) in &quotas {
+        if state.store.get_daily_usage(today, subject).await? >= *quota as i64 {
+            tracing::warn!("Daily {} quota used up for {}", name, subject);
+            let midnight = today.succ_opt().unwrap_or(today).and_time(NaiveTime::MIN).and_utc();
+            let retry_after = (midnight - now).num_seconds().max(1) as u64;
+            return Err(AppError::QuotaExceeded { retry_after });
+        }
+    }
+
+    // Only requests the handler accepted are charged, so refused, unauthorized or malformed
+    // ones (a made-up session id, say) don't use up anyone's quota. Concurrent requests can
+    // overshoot a quota by a few; the buckets above bound how many.
+    let response = next.run(request).await;
+    if !response.status().is_client_error() {
+        for (subject, _) in &quotas {
+            // The work is done by now, so a counter that can't be updated doesn't fail it
+            if let Err(e) = state.store.increment_daily_usage(today, subject).await {
+                tracing::error!("Failed to count {} usage for {}: {}", name, subject, e);
+            }
+        }
+    }
+    Ok(response)
+}
+
+// The peer address, or the address the proxy in front of us saw when it is trusted.
+// Only the last X-Forwarded-For entry is the proxy's own; earlier ones come from the client.
+fn client_ip(request: &Request, trust_forwarded_for: bool) -> String {
+    if trust_forwarded_for {
+        if let Some(ip) = forwarded_for(request.headers()) {
+            return ip;
+        }
+    }
+    request.extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn forwarded_for(headers: &HeaderMap) -> Option<String> {
+    headers.get_all("X-Forwarded-For")
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .map(str::trim)
+        .rfind(|ip| !ip.is_empty())
+        .map(str::to_string)
+}
@@ -0,0 +1,331 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use split_bills_core::{plan_transfers, CalculateRequest, CalculateResponse, Person, Transfer};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::email::{format_amount, Attachment};
+use crate::models::DbSession;
+use crate::pdf::{Font, PdfDocument, MONO_COLUMNS, MONO_SIZE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    Csv,
+    Pdf,
+}
+
+impl ReportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "text/csv; charset=utf-8",
+            ReportFormat::Pdf => "application/pdf",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "csv",
+            ReportFormat::Pdf => "pdf",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReportQuery {
+    pub format: ReportFormat,
+}
+
+// Something that changes what people owe besides their own expense lines
+struct Adjustment {
+    kind: String,
+    name: String,
+    description: String,
+    amount: f64,
+}
+
+// A session's settlement for the record: every expense line, the adjustments, the
+// per-person settlement table and the transfers, as CSV or PDF
+pub struct SettlementReport {
+    session_id: String,
+    currency: Option<String>,
+    generated_at: DateTime<Utc>,
+    people: Vec<Person>,
+    tip_percentage: f64,
+    result: CalculateResponse,
+    transfers: Vec<Transfer>,
+}
+
+impl SettlementReport {
+    // Calculated the same way as the results email
+    pub fn from_session(session: &DbSession, generated_at: DateTime<Utc>) -> Result<Self, serde_json::Error> {
+        let people: Vec<Person> = serde_json::from_str(&session.people)?;
+        let result = split_bills_core::calculate_split(CalculateRequest {
+            people: people.clone(),
+            include_sponsor: session.include_sponsor,
+            restrict_sponsor_to_spent: Some(true),
+            fund_amount: session.fund_amount,
+            tip_percentage: session.tip_percentage,
+        });
+        let transfers = plan_transfers(&result.settlements);
+        Ok(SettlementReport {
+            session_id: session.id.clone(),
+            currency: session.currency.clone(),
+            generated_at,
+            people,
+            tip_percentage: session.tip_percentage,
+            result,
+            transfers,
+        })
+    }
+
+    pub fn filename(&self, format: ReportFormat) -> String {
+        let short_id: String = self.session_id.chars().take(8).collect();
+        format!("split-bills-{}-{}.{}", short_id, self.generated_at.format("%Y-%m-%d"), format.extension())
+    }
+
+    pub fn render(&self, format: ReportFormat) -> Result<Vec<u8>, String> {
+        match format {
+            ReportFormat::Csv => self.csv(),
+            ReportFormat::Pdf => Ok(self.pdf()),
+        }
+    }
+
+    pub fn attachment(&self, format: ReportFormat) -> Result<Attachment, String> {
+        Ok(Attachment {
+            filename: self.filename(format),
+            content_type: format.content_type().to_string(),
+            content: self.render(format)?,
+        })
+    }
+
+    fn adjustments(&self) -> Vec<Adjustment> {
+        let mut adjustments = Vec::new();
+        if self.result.fund_amount > 0.0 {
+            adjustments.push(Adjustment {
+                kind: "Fund".to_string(),
+                name: String::new(),
+                description: "Collected before splitting".to_string(),
+                amount: self.result.fund_amount,
+            });
+        }
+        if self.result.total_tip > 0.0 {
+            adjustments.push(Adjustment {
+                kind: "Tip".to_string(),
+                name: String::new(),
+                description: format!("{}% on top of the bill", self.tip_percentage),
+                amount: self.result.total_tip,
+            });
+        }
+        for person in &self.people {
+            if person.is_sponsor && person.sponsor_amount > 0.0 {
+                adjustments.push(Adjustment {
+                    kind: "Sponsorship".to_string(),
+                    name: person.name.clone(),
+                    description: person.description.clone(),
+                    amount: person.sponsor_amount,
+                });
+            }
+            match person.paid_by.as_deref() {
+                Some(paid_by) if paid_by == person.name => adjustments.push(Adjustment {
+                    kind: "Private expense".to_string(),
+                    name: person.name.clone(),
+                    description: person.description.clone(),
+                    amount: line_total(person),
+                }),
+                Some(paid_by) => adjustments.push(Adjustment {
+                    kind: format!("Reimbursed by {}", paid_by),
+                    name: person.name.clone(),
+                    description: person.description.clone(),
+                    amount: line_total(person),
+                }),
+                None => {}
+            }
+        }
+        adjustments
+    }
+
+    // One file with a section per table: a row with its title, then its header row
+    fn csv(&self) -> Result<Vec<u8>, String> {
+        let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(Vec::new());
+        let mut write = |record: Vec<String>| writer.write_record(&record).map_err(|e| e.to_string());
+        let amount = |value: f64| format!("{:.2}", value);
+
+        write(vec!["Split Bills settlement report".to_string()])?;
+        write(vec!["session".to_string(), self.session_id.clone()])?;
+        write(vec!["generated_at".to_string(), self.generated_at.to_rfc3339()])?;
+        write(vec!["currency".to_string(), self.currency.clone().unwrap_or_default()])?;
+
+        write(vec!["Summary".to_string()])?;
+        let r = &self.result;
+        for (label, value) in [
+            ("total_spent", amount(r.total_spent)),
+            ("total_sponsored", amount(r.total_sponsored)),
+            ("total_tip", amount(r.total_tip)),
+            ("fund_amount", amount(r.fund_amount)),
+            ("amount_to_share", amount(r.amount_to_share)),
+            ("num_participants", r.num_participants.to_string()),
+            ("per_person_share", amount(r.per_person_share)),
+        ] {
+            write(vec![label.to_string(), value])?;
+        }
+
+        write(vec!["Expenses".to_string()])?;
+        write(["name", "description", "amount", "quantity", "tip", "total", "sponsor_amount", "paid_by", "receiver"].map(String::from).to_vec())?;
+        for person in &self.people {
+            write(vec![
+                person.name.clone(),
+                person.description.clone(),
+                amount(person.amount_spent),
+                person.quantity.to_string(),
+                amount(person.tip),
+                amount(line_total(person)),
+                amount(if person.is_sponsor { person.sponsor_amount } else { 0.0 }),
+                person.paid_by.clone().unwrap_or_default(),
+                person.is_receiver.to_string(),
+            ])?;
+        }
+
+        write(vec!["Adjustments".to_string()])?;
+        write(["kind", "name", "description", "amount"].map(String::from).to_vec())?;
+        for adjustment in self.adjustments() {
+            write(vec![adjustment.kind, adjustment.name, adjustment.description, amount(adjustment.amount)])?;
+        }
+
+        write(vec!["Settlements".to_string()])?;
+        write(["name", "amount_spent", "tip_paid", "sponsor_cost", "share_cost", "balance", "settlement_type"].map(String::from).to_vec())?;
+        for s in &self.result.settlements {
+            write(vec![
+                s.name.clone(),
+                amount(s.amount_spent),
+                amount(s.tip_paid),
+                amount(s.sponsor_cost),
+                amount(s.share_cost),
+                amount(s.balance),
+                s.settlement_type.clone(),
+            ])?;
+        }
+
+        write(vec!["Transfers".to_string()])?;
+        write(["from", "to", "amount"].map(String::from).to_vec())?;
+        for t in &self.transfers {
+            write(vec![t.from.clone(), t.to.clone(), amount(t.amount)])?;
+        }
+
+        writer.into_inner().map_err(|e| e.to_string())
+    }
+
+    fn pdf(&self) -> Vec<u8> {
+        let mut doc = PdfDocument::new();
+        let r = &self.result;
+        let currency = self.currency.as_deref().filter(|c| !c.is_empty()).unwrap_or("$");
+
+        doc.line(Font::Heading, 16.0, "Split Bills settlement report");
+        doc.line(Font::Mono, MONO_SIZE, &format!(
+            "Session {}, generated {}. Amounts in {}.",
+            self.session_id,
+            self.generated_at.format("%Y-%m-%d %H:%M UTC"),
+            currency,
+        ));
+
+        heading(&mut doc, "Summary");
+        for (label, value) in [
+            ("Total spent", format_amount(r.total_spent)),
+            ("Total sponsored", format_amount(r.total_sponsored)),
+            ("Total tip/tax", format_amount(r.total_tip)),
+            ("Fund used", format_amount(r.fund_amount)),
+            ("Amount to share", format_amount(r.amount_to_share)),
+            ("Participants", r.num_participants.to_string()),
+            ("Per person share", format_amount(r.per_person_share)),
+        ] {
+            row(&mut doc, &[(label, 20), (&value, -16)]);
+        }
+
+        heading(&mut doc, "Expenses");
+        let columns = [12, 20, -13, -3, -10, -13, 10];
+        row(&mut doc, &zip(["Name", "Description", "Amount", "Qty", "Tip", "Total", "Paid by"], columns));
+        for person in &self.people {
+            let values = [
+                person.name.clone(),
+                person.description.clone(),
+                format_amount(person.amount_spent),
+                person.quantity.to_string(),
+                format_amount(person.tip),
+                format_amount(line_total(person)),
+                person.paid_by.clone().unwrap_or_default(),
+            ];
+            row(&mut doc, &zip(values.each_ref().map(String::as_str), columns));
+        }
+
+        let adjustments = self.adjustments();
+        if !adjustments.is_empty() {
+            heading(&mut doc, "Adjustments");
+            let columns = [22, 12, 30, -15];
+            row(&mut doc, &zip(["Kind", "Name", "Description", "Amount"], columns));
+            for a in &adjustments {
+                let amount = format_amount(a.amount);
+                row(&mut doc, &zip([a.kind.as_str(), &a.name, &a.description, &amount], columns));
+            }
+        }
+
+        heading(&mut doc, "Settlements");
+        let columns = [12, -12, -10, -12, -12, -13, 8];
+        row(&mut doc, &zip(["Name", "Spent", "Tip", "Sponsored", "Share", "Balance", "Action"], columns));
+        for s in &r.settlements {
+            let values = [
+                s.name.clone(),
+                format_amount(s.amount_spent),
+                format_amount(s.tip_paid),
+                format_amount(s.sponsor_cost),
+                format_amount(s.share_cost),
+                format_amount(s.balance),
+                s.settlement_type.clone(),
+            ];
+            row(&mut doc, &zip(values.each_ref().map(String::as_str), columns));
+        }
+
+        heading(&mut doc, "Transfers");
+        if self.transfers.is_empty() {
+            doc.line(Font::Mono, MONO_SIZE, "Nobody needs to pay anyone.");
+        }
+        let columns = [20, 20, -15];
+        for t in &self.transfers {
+            let amount = format_amount(t.amount);
+            row(&mut doc, &zip([t.from.as_str(), &format!("pays {}", t.to), &amount], columns));
+        }
+
+        doc.finish()
+    }
+}
+
+fn line_total(person: &Person) -> f64 {
+    person.amount_spent * person.quantity as f64 + person.tip
+}
+
+fn heading(doc: &mut PdfDocument, text: &str) {
+    doc.space(8.0);
+    doc.line(Font::Heading, 11.0, text);
+}
+
+fn zip<const N: usize>(values: [&str; N], widths: [i32; N]) -> Vec<(&str, i32)> {
+    values.into_iter().zip(widths).collect()
+}
+
+// A line of fixed-width columns. Negative widths are right-aligned; values too long for
+// their column are cut short.
+fn row(doc: &mut PdfDocument, columns: &[(&str, i32)]) {
+    let cells: Vec<String> = columns
+        .iter()
+        .map(|&(value, width)| {
+            let len = width.unsigned_abs() as usize;
+            let value: String = value.chars().take(len).collect();
+            if width < 0 {
+                format!("{:>len$}", value)
+            } else {
+                format!("{:<len$}", value)
+            }
+        })
+        .collect();
+    let line: String = cells.join(" ").chars().take(MONO_COLUMNS).collect();
+    doc.line(Font::Mono, MONO_SIZE, line.trim_end());
+}
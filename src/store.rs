@@ -0,0 +1,265 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::sqlite::SqlitePoolOptions;
+
+use crate::models::*;
+
+mod memory;
+mod postgres;
+mod sqlite;
+
+pub use memory::MemoryStore;
+pub use postgres::PostgresStore;
+pub use sqlite::{run_migrations, SqliteStore};
+
+pub type StoreResult<T> = Result<T, sqlx::Error>;
+
+pub const DEFAULT_DATABASE_URL: &str = "sqlite:sessions.db?mode=rwc";
+
+// Pick the backend from the URL scheme (`sqlite:`, `postgres://` or `memory:`) and run
+// its migrations. `memory:` keeps everything in process and loses it on restart.
+pub async fn connect(database_url: &str) -> StoreResult<Arc<dyn SessionStore>> {
+    if database_url == "memory:" {
+        return Ok(Arc::new(MemoryStore::new()));
+    }
+
+    if database_url.starts_with("sqlite:") {
+        let pool = SqlitePoolOptions::new().connect(database_url).await?;
+        run_migrations(&pool).await;
+        return Ok(Arc::new(SqliteStore::new(pool)));
+    }
+
+    if database_url.starts_with("postgres:") || database_url.starts_with("postgresql:") {
+        return Ok(Arc::new(PostgresStore::connect(database_url.parse()?).await?));
+    }
+
+    Err(sqlx::Error::Configuration(format!("unsupported DATABASE_URL scheme: {}", database_url).into()))
+}
+
+// Everything the handlers persist. JSON columns (people, members, balances, ...) are
+// passed through as strings so every backend stores exactly the same data.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    // Sessions
+
+    async fn insert_session(&self, session: &DbSession) -> StoreResult<()>;
+
+    async fn get_session(&self, id: &str) -> StoreResult<Option<DbSession>>;
+
+    // Bump last_accessed_at, which is what keeps a session from expiring
+    async fn touch_session(&self, id: &str, at: DateTime<Utc>) -> StoreResult<()>;
+
+    // Writes people, fund_amount, tip_percentage, include_sponsor, currency and last_accessed_at
+    async fn update_session_contents(&self, session: &DbSession) -> StoreResult<()>;
+
+    // Writes expiry_days, pinned and last_accessed_at
+    async fn update_session_expiry(&self, session: &DbSession) -> StoreResult<()>;
+
+    async fn update_session_notifications(&self, id: &str, emails: &str) -> StoreResult<()>;
+
+    async fn record_expiry_warning(&self, id: &str, extend_token: &str, at: DateTime<Utc>) -> StoreResult<()>;
+
+    // Touch the session if the token matches; false when nothing was extended
+    async fn extend_session(&self, id: &str, extend_token: &str, at: DateTime<Utc>) -> StoreResult<bool>;
+
+    // Set the owner if the secret matches; false when nothing was claimed
+    async fn set_session_owner(&self, id: &str, edit_secret: &str, user_id: &str) -> StoreResult<bool>;
+
+    // Also removes the session's participant links, sync operations and reminders
+    async fn delete_session(&self, id: &str) -> StoreResult<()>;
+
+    // Delete unpinned sessions past their expiry. While warnings are enabled (`warning_days`
+    // is set), sessions with notification emails are held back until `warning_days` after a
+    // warning sent since their last access, but never beyond `warning_days` past their expiry,
+    // so a warning that can't be sent doesn't keep a session forever.
+    // Returns the number of sessions deleted.
+    async fn delete_expired_sessions(&self, default_expiry_days: i64, warning_days: Option<i64>, now: DateTime<Utc>) -> StoreResult<u64>;
+
+    // Sessions with notification emails, not warned since their last access,
+    // that expire within `warning_days`
+    async fn sessions_due_for_warning(&self, default_expiry_days: i64, warning_days: i64, now: DateTime<Utc>) -> StoreResult<Vec<DbSession>>;
+
+    async fn sessions_owned_by(&self, user_id: &str) -> StoreResult<Vec<DbSession>>;
+
+    // Every session, most recently accessed first
+    async fn all_sessions(&self) -> StoreResult<Vec<DbSession>>;
+
+    // Participant links
+
+    // Create a link for each name that doesn't have one yet
+    async fn ensure_participant_links(&self, session_id: &str, names: &[&str], at: DateTime<Utc>) -> StoreResult<()>;
+
+    async fn participant_links_for_session(&self, session_id: &str) -> StoreResult<Vec<DbParticipantLink>>;
+
+    async fn participant_links_for_user(&self, user_id: &str) -> StoreResult<Vec<DbParticipantLink>>;
+
+    async fn get_participant_link(&self, token: &str) -> StoreResult<Option<DbParticipantLink>>;
+
+    // Sets claimed_at unless the link was already claimed
+    async fn mark_participant_link_claimed(&self, token: &str, at: DateTime<Utc>) -> StoreResult<()>;
+
+    async fn set_participant_link_user(&self, token: &str, user_id: &str) -> StoreResult<()>;
+
+    // The address results may be emailed to; None clears it
+    async fn set_participant_link_email(&self, session_id: &str, name: &str, email: Option<&str>) -> StoreResult<()>;
+
+    // Language code of the participant's emails, e.g. "vi"
+    async fn set_participant_link_language(&self, session_id: &str, name: &str, language: &str) -> StoreResult<()>;
+
+    // Sync operations
+
+    async fn sync_operations(&self, session_id: &str) -> StoreResult<Vec<DbSyncOperation>>;
+
+    // Operations already recorded for the session are left alone
+    async fn insert_sync_operations(&self, operations: &[DbSyncOperation]) -> StoreResult<()>;
+
+    // Archives
+
+    async fn insert_archive(&self, archive: &DbArchive) -> StoreResult<()>;
+
+    async fn get_archive(&self, id: &str) -> StoreResult<Option<DbArchive>>;
+
+    // Newest first
+    async fn archives_for_group(&self, group_id: &str) -> StoreResult<Vec<DbArchive>>;
+
+    // Newest first
+    async fn archives_for_owner(&self, owner_token: &str) -> StoreResult<Vec<DbArchive>>;
+
+    async fn delete_archive(&self, id: &str) -> StoreResult<()>;
+
+    // Templates
+
+    async fn insert_template(&self, template: &DbTemplate) -> StoreResult<()>;
+
+    async fn get_template(&self, id: &str) -> StoreResult<Option<DbTemplate>>;
+
+    // Ordered by name
+    async fn templates_for_owner(&self, owner_token: &str) -> StoreResult<Vec<DbTemplate>>;
+
+    // Writes name, body and updated_at
+    async fn update_template(&self, template: &DbTemplate) -> StoreResult<()>;
+
+    async fn delete_template(&self, id: &str) -> StoreResult<()>;
+
+    // Groups
+
+    async fn insert_group(&self, group: &DbGroup) -> StoreResult<()>;
+
+    async fn get_group(&self, id: &str) -> StoreResult<Option<DbGroup>>;
+
+    // Writes name and members
+    async fn update_group(&self, group: &DbGroup) -> StoreResult<()>;
+
+    // Set the owner if the secret matches; false when nothing was claimed
+    async fn set_group_owner(&self, id: &str, edit_secret: &str, user_id: &str) -> StoreResult<bool>;
+
+    // Groups the user owns or that contain a session they claimed a participant link in.
+    // Ordered by name.
+    async fn groups_for_user(&self, user_id: &str) -> StoreResult<Vec<DbGroup>>;
+
+    // Adding a session that is already in the group replaces its balances
+    async fn upsert_group_session(&self, snapshot: &DbGroupSession) -> StoreResult<()>;
+
+    // False when the session wasn't in the group
+    async fn delete_group_session(&self, group_id: &str, session_id: &str) -> StoreResult<bool>;
+
+    // Ordered by added_at
+    async fn group_sessions(&self, group_id: &str) -> StoreResult<Vec<DbGroupSession>>;
+
+    // The group's sessions that still exist, i.e. haven't expired or been deleted
+    async fn active_group_session_ids(&self, group_id: &str) -> StoreResult<Vec<String>>;
+
+    // Replace the balances of this session in every group that contains it
+    async fn refresh_group_snapshots(&self, session_id: &str, balances: &str, at: DateTime<Utc>) -> StoreResult<()>;
+
+    // Accounts
+
+    async fn insert_login_token(&self, login: &DbLoginToken) -> StoreResult<()>;
+
+    // Mark an unused, unexpired token used and return it. Only the first call for a
+    // token gets Some, so each magic link works once.
+    async fn consume_login_token(&self, token: &str, now: DateTime<Utc>) -> StoreResult<Option<DbLoginToken>>;
+
+    async fn find_or_create_user(&self, email: &str, at: DateTime<Utc>) -> StoreResult<DbUser>;
+
+    async fn insert_access_token(&self, token: &str, user_id: &str, created_at: DateTime<Utc>, expires_at: DateTime<Utc>) -> StoreResult<()>;
+
+    async fn delete_access_token(&self, token: &str) -> StoreResult<()>;
+
+    // The user behind an unexpired access token
+    async fn user_for_access_token(&self, token: &str, now: DateTime<Utc>) -> StoreResult<Option<DbUser>>;
+
+    // Drop expired login and access tokens
+    async fn delete_expired_tokens(&self, now: DateTime<Utc>) -> StoreResult<()>;
+
+    // Idempotency keys
+
+    async fn get_idempotency_record(&self, key: &str) -> StoreResult<Option<DbIdempotencyRecord>>;
+
+    // Claim the key for a request; false when a record for it already exists
+    async fn insert_idempotency_record(&self, record: &DbIdempotencyRecord) -> StoreResult<bool>;
+
+    // Store the response the request got
+    async fn complete_idempotency_record(&self, key: &str, status: i64, content_type: Option<&str>, body: &str) -> StoreResult<()>;
+
+    async fn delete_idempotency_record(&self, key: &str) -> StoreResult<()>;
+
+    // Returns the number of records deleted
+    async fn delete_idempotency_records_before(&self, cutoff: DateTime<Utc>) -> StoreResult<u64>;
+
+    // Rate limits
+
+    // Count one more request for the subject on the day and return the new total
+    async fn increment_daily_usage(&self, day: NaiveDate, subject: &str) -> StoreResult<i64>;
+
+    // Requests counted for the subject on the day so far
+    async fn get_daily_usage(&self, day: NaiveDate, subject: &str) -> StoreResult<i64>;
+
+    // Busiest first
+    async fn daily_usage(&self, day: NaiveDate) -> StoreResult<Vec<DbDailyUsage>>;
+
+    async fn delete_daily_usage_before(&self, day: NaiveDate) -> StoreResult<()>;
+
+    // Replaces the note of an existing exemption
+    async fn insert_rate_limit_exemption(&self, exemption: &DbRateLimitExemption) -> StoreResult<()>;
+
+    async fn get_rate_limit_exemption(&self, subject: &str) -> StoreResult<Option<DbRateLimitExemption>>;
+
+    // Ordered by subject
+    async fn rate_limit_exemptions(&self) -> StoreResult<Vec<DbRateLimitExemption>>;
+
+    // False when there was no such exemption
+    async fn delete_rate_limit_exemption(&self, subject: &str) -> StoreResult<bool>;
+
+    // Payment reminders
+
+    // Replaces the session's schedule
+    async fn set_reminder_schedule(&self, schedule: &DbReminderSchedule) -> StoreResult<()>;
+
+    async fn get_reminder_schedule(&self, session_id: &str) -> StoreResult<Option<DbReminderSchedule>>;
+
+    async fn reminder_schedules(&self) -> StoreResult<Vec<DbReminderSchedule>>;
+
+    // False when the session had no schedule
+    async fn delete_reminder_schedule(&self, session_id: &str) -> StoreResult<bool>;
+
+    // Ordered by name
+    async fn payment_reminders_for_session(&self, session_id: &str) -> StoreResult<Vec<DbPaymentReminder>>;
+
+    async fn get_payment_reminder(&self, token: &str) -> StoreResult<Option<DbPaymentReminder>>;
+
+    // Insert the debtor's record, or update amount, transfers, reminders_sent, last_sent_at
+    // and paid_at of the existing one, keeping its token. A debt marked paid after
+    // last_sent_at stays paid, as that happened while the reminder was being sent.
+    async fn record_payment_reminder(&self, reminder: &DbPaymentReminder) -> StoreResult<()>;
+
+    // Sets paid_at unless the debt was already marked paid
+    async fn mark_payment_paid(&self, token: &str, at: DateTime<Utc>) -> StoreResult<bool>;
+
+    // Maintenance
+
+    // Reclaim space left by deleted rows
+    async fn vacuum(&self) -> StoreResult<()>;
+}
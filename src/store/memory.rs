@@ -0,0 +1,668 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use super::{SessionStore, StoreResult};
+use crate::models::*;
+use crate::session_expires_at;
+
+// Keeps everything in process memory. Nothing survives a restart, so this is
+// meant for tests and throwaway local runs.
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<MemoryData>,
+}
+
+#[derive(Default)]
+struct MemoryData {
+    sessions: HashMap<String, DbSession>,
+    participant_links: HashMap<String, DbParticipantLink>,
+    sync_operations: Vec<DbSyncOperation>,
+    archives: HashMap<String, DbArchive>,
+    templates: HashMap<String, DbTemplate>,
+    groups: HashMap<String, DbGroup>,
+    group_sessions: Vec<DbGroupSession>,
+    users: HashMap<String, DbUser>,
+    login_tokens: HashMap<String, DbLoginToken>,
+    // token -> (user id, expires at)
+    access_tokens: HashMap<String, (String, DateTime<Utc>)>,
+    idempotency_keys: HashMap<String, DbIdempotencyRecord>,
+    daily_usage: HashMap<(NaiveDate, String), i64>,
+    rate_limit_exemptions: HashMap<String, DbRateLimitExemption>,
+    reminder_schedules: HashMap<String, DbReminderSchedule>,
+    payment_reminders: HashMap<String, DbPaymentReminder>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn insert_session(&self, session: &DbSession) -> StoreResult<()> {
+        let mut data = self.data.lock().await;
+        if data.sessions.contains_key(&session.id) {
+            return Err(sqlx::Error::Protocol(format!("session {} already exists", session.id)));
+        }
+        data.sessions.insert(session.id.clone(), session.clone());
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &str) -> StoreResult<Option<DbSession>> {
+        Ok(self.data.lock().await.sessions.get(id).cloned())
+    }
+
+    async fn touch_session(&self, id: &str, at: DateTime<Utc>) -> StoreResult<()> {
+        if let Some(session) = self.data.lock().await.sessions.get_mut(id) {
+            session.last_accessed_at = at;
+        }
+        Ok(())
+    }
+
+    async fn update_session_contents(&self, session: &DbSession) -> StoreResult<()> {
+        if let Some(stored) = self.data.lock().await.sessions.get_mut(&session.id) {
+            stored.people = session.people.clone();
+            stored.fund_amount = session.fund_amount;
+            stored.tip_percentage = session.tip_percentage;
+            stored.include_sponsor = session.include_sponsor;
+            stored.currency = session.currency.clone();
+            stored.last_accessed_at = session.last_accessed_at;
+        }
+        Ok(())
+    }
+
+    async fn update_session_expiry(&self, session: &DbSession) -> StoreResult<()> {
+        if let Some(stored) = self.data.lock().await.sessions.get_mut(&session.id) {
+            stored.expiry_days = session.expiry_days;
+            stored.pinned = session.pinned;
+            stored.last_accessed_at = session.last_accessed_at;
+        }
+        Ok(())
+    }
+
+    async fn update_session_notifications(&self, id: &str, emails: &str) -> StoreResult<()> {
+        if let Some(session) = self.data.lock().await.sessions.get_mut(id) {
+            session.notification_emails = emails.to_string();
+        }
+        Ok(())
+    }
+
+    async fn record_expiry_warning(&self, id: &str, extend_token: &str, at: DateTime<Utc>) -> StoreResult<()> {
+        if let Some(session) = self.data.lock().await.sessions.get_mut(id) {
+            session.expiry_warning_sent_at = Some(at);
+            session.extend_token = Some(extend_token.to_string());
+        }
+        Ok(())
+    }
+
+    async fn extend_session(&self, id: &str, extend_token: &str, at: DateTime<Utc>) -> StoreResult<bool> {
+        match self.data.lock().await.sessions.get_mut(id) {
+            Some(session) if session.extend_token.as_deref() == Some(extend_token) => {
+                session.last_accessed_at = at;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn set_session_owner(&self, id: &str, edit_secret: &str, user_id: &str) -> StoreResult<bool> {
+        match self.data.lock().await.sessions.get_mut(id) {
+            Some(session) if session.edit_secret == edit_secret => {
+                session.owner_user_id = Some(user_id.to_string());
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_session(&self, id: &str) -> StoreResult<()> {
+        let mut data = self.data.lock().await;
+        data.sessions.remove(id);
+        data.participant_links.retain(|_, link| link.session_id != id);
+        data.sync_operations.retain(|op| op.session_id != id);
+        data.reminder_schedules.remove(id);
+        data.payment_reminders.retain(|_, reminder| reminder.session_id != id);
+        Ok(())
+    }
+
+    async fn delete_expired_sessions(&self, default_expiry_days: i64, warning_days: Option<i64>, now: DateTime<Utc>) -> StoreResult<u64> {
+        let mut data = self.data.lock().await;
+        let before = data.sessions.len();
+
+        data.sessions.retain(|_, session| {
+            let Some(expires_at) = session_expires_at(session, default_expiry_days).filter(|at| *at < now) else {
+                return true;
+            };
+            let Some(warning_days) = warning_days.filter(|_| has_notification_emails(session)) else {
+                return false;
+            };
+            let warning_days = chrono::Duration::days(warning_days);
+            let warned_long_enough = matches!(
+                session.expiry_warning_sent_at,
+                Some(at) if at >= session.last_accessed_at && at + warning_days <= now
+            );
+            !warned_long_enough && expires_at + warning_days > now
+        });
+
+        let deleted = (before - data.sessions.len()) as u64;
+        let MemoryData { sessions, participant_links, sync_operations, reminder_schedules, payment_reminders, .. } = &mut *data;
+        participant_links.retain(|_, link| sessions.contains_key(&link.session_id));
+        sync_operations.retain(|op| sessions.contains_key(&op.session_id));
+        reminder_schedules.retain(|session_id, _| sessions.contains_key(session_id));
+        payment_reminders.retain(|_, reminder| sessions.contains_key(&reminder.session_id));
+        Ok(deleted)
+    }
+
+    async fn sessions_due_for_warning(&self, default_expiry_days: i64, warning_days: i64, now: DateTime<Utc>) -> StoreResult<Vec<DbSession>> {
+        let data = self.data.lock().await;
+        Ok(data.sessions
+            .values()
+            .filter(|session| has_notification_emails(session))
+            .filter(|session| !matches!(session.expiry_warning_sent_at, Some(at) if at >= session.last_accessed_at))
+            .filter(|session| matches!(
+                session_expires_at(session, default_expiry_days),
+                Some(at) if at - chrono::Duration::days(warning_days) < now
+            ))
+            .cloned()
+            .collect())
+    }
+
+    async fn sessions_owned_by(&self, user_id: &str) -> StoreResult<Vec<DbSession>> {
+        let data = self.data.lock().await;
+        let mut sessions: Vec<DbSession> = data.sessions
+            .values()
+            .filter(|session| session.owner_user_id.as_deref() == Some(user_id))
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_accessed_at));
+        Ok(sessions)
+    }
+
+    async fn all_sessions(&self) -> StoreResult<Vec<DbSession>> {
+        let data = self.data.lock().await;
+        let mut sessions: Vec<DbSession> = data.sessions.values().cloned().collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_accessed_at));
+        Ok(sessions)
+    }
+
+    async fn ensure_participant_links(&self, session_id: &str, names: &[&str], at: DateTime<Utc>) -> StoreResult<()> {
+        let mut data = self.data.lock().await;
+        for name in names {
+            let exists = data.participant_links
+                .values()
+                .any(|link| link.session_id == session_id && link.name == *name);
+            if !exists {
+                let token = Uuid::new_v4().to_string();
+                data.participant_links.insert(token.clone(), DbParticipantLink {
+                    token,
+                    session_id: session_id.to_string(),
+                    name: name.to_string(),
+                    created_at: at,
+                    claimed_at: None,
+                    user_id: None,
+                    email: None,
+                    language: None,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    async fn participant_links_for_session(&self, session_id: &str) -> StoreResult<Vec<DbParticipantLink>> {
+        let data = self.data.lock().await;
+        let mut links: Vec<DbParticipantLink> = data.participant_links
+            .values()
+            .filter(|link| link.session_id == session_id)
+            .cloned()
+            .collect();
+        links.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(links)
+    }
+
+    async fn participant_links_for_user(&self, user_id: &str) -> StoreResult<Vec<DbParticipantLink>> {
+        let data = self.data.lock().await;
+        Ok(data.participant_links
+            .values()
+            .filter(|link| link.user_id.as_deref() == Some(user_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_participant_link(&self, token: &str) -> StoreResult<Option<DbParticipantLink>> {
+        Ok(self.data.lock().await.participant_links.get(token).cloned())
+    }
+
+    async fn mark_participant_link_claimed(&self, token: &str, at: DateTime<Utc>) -> StoreResult<()> {
+        if let Some(link) = self.data.lock().await.participant_links.get_mut(token) {
+            link.claimed_at.get_or_insert(at);
+        }
+        Ok(())
+    }
+
+    async fn set_participant_link_user(&self, token: &str, user_id: &str) -> StoreResult<()> {
+        if let Some(link) = self.data.lock().await.participant_links.get_mut(token) {
+            link.user_id = Some(user_id.to_string());
+        }
+        Ok(())
+    }
+
+    async fn set_participant_link_email(&self, session_id: &str, name: &str, email: Option<&str>) -> StoreResult<()> {
+        let mut data = self.data.lock().await;
+        if let Some(link) = data.participant_links.values_mut().find(|l| l.session_id == session_id && l.name == name) {
+            link.email = email.map(str::to_string);
+        }
+        Ok(())
+    }
+
+    async fn set_participant_link_language(&self, session_id: &str, name: &str, language: &str) -> StoreResult<()> {
+        let mut data = self.data.lock().await;
+        if let Some(link) = data.participant_links.values_mut().find(|l| l.session_id == session_id && l.name == name) {
+            link.language = Some(language.to_string());
+        }
+        Ok(())
+    }
+
+    async fn sync_operations(&self, session_id: &str) -> StoreResult<Vec<DbSyncOperation>> {
+        let data = self.data.lock().await;
+        Ok(data.sync_operations.iter().filter(|op| op.session_id == session_id).cloned().collect())
+    }
+
+    async fn insert_sync_operations(&self, operations: &[DbSyncOperation]) -> StoreResult<()> {
+        let mut data = self.data.lock().await;
+        for op in operations {
+            let exists = data.sync_operations
+                .iter()
+                .any(|existing| existing.session_id == op.session_id && existing.op_id == op.op_id);
+            if !exists {
+                data.sync_operations.push(op.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn insert_archive(&self, archive: &DbArchive) -> StoreResult<()> {
+        self.data.lock().await.archives.insert(archive.id.clone(), archive.clone());
+        Ok(())
+    }
+
+    async fn get_archive(&self, id: &str) -> StoreResult<Option<DbArchive>> {
+        Ok(self.data.lock().await.archives.get(id).cloned())
+    }
+
+    async fn archives_for_group(&self, group_id: &str) -> StoreResult<Vec<DbArchive>> {
+        let data = self.data.lock().await;
+        Ok(newest_first(data.archives.values().filter(|a| a.group_id.as_deref() == Some(group_id))))
+    }
+
+    async fn archives_for_owner(&self, owner_token: &str) -> StoreResult<Vec<DbArchive>> {
+        let data = self.data.lock().await;
+        Ok(newest_first(data.archives.values().filter(|a| a.owner_token.as_deref() == Some(owner_token))))
+    }
+
+    async fn delete_archive(&self, id: &str) -> StoreResult<()> {
+        self.data.lock().await.archives.remove(id);
+        Ok(())
+    }
+
+    async fn insert_template(&self, template: &DbTemplate) -> StoreResult<()> {
+        self.data.lock().await.templates.insert(template.id.clone(), template.clone());
+        Ok(())
+    }
+
+    async fn get_template(&self, id: &str) -> StoreResult<Option<DbTemplate>> {
+        Ok(self.data.lock().await.templates.get(id).cloned())
+    }
+
+    async fn templates_for_owner(&self, owner_token: &str) -> StoreResult<Vec<DbTemplate>> {
+        let data = self.data.lock().await;
+        let mut templates: Vec<DbTemplate> = data.templates
+            .values()
+            .filter(|t| t.owner_token == owner_token)
+            .cloned()
+            .collect();
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(templates)
+    }
+
+    async fn update_template(&self, template: &DbTemplate) -> StoreResult<()> {
+        if let Some(stored) = self.data.lock().await.templates.get_mut(&template.id) {
+            stored.name = template.name.clone();
+            stored.body = template.body.clone();
+            stored.updated_at = template.updated_at;
+        }
+        Ok(())
+    }
+
+    async fn delete_template(&self, id: &str) -> StoreResult<()> {
+        self.data.lock().await.templates.remove(id);
+        Ok(())
+    }
+
+    async fn insert_group(&self, group: &DbGroup) -> StoreResult<()> {
+        self.data.lock().await.groups.insert(group.id.clone(), group.clone());
+        Ok(())
+    }
+
+    async fn get_group(&self, id: &str) -> StoreResult<Option<DbGroup>> {
+        Ok(self.data.lock().await.groups.get(id).cloned())
+    }
+
+    async fn update_group(&self, group: &DbGroup) -> StoreResult<()> {
+        if let Some(stored) = self.data.lock().await.groups.get_mut(&group.id) {
+            stored.name = group.name.clone();
+            stored.members = group.members.clone();
+        }
+        Ok(())
+    }
+
+    async fn set_group_owner(&self, id: &str, edit_secret: &str, user_id: &str) -> StoreResult<bool> {
+        match self.data.lock().await.groups.get_mut(id) {
+            Some(group) if group.edit_secret == edit_secret => {
+                group.owner_user_id = Some(user_id.to_string());
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn groups_for_user(&self, user_id: &str) -> StoreResult<Vec<DbGroup>> {
+        let data = self.data.lock().await;
+        let joined_sessions: Vec<&str> = data.participant_links
+            .values()
+            .filter(|link| link.user_id.as_deref() == Some(user_id))
+            .map(|link| link.session_id.as_str())
+            .collect();
+
+        let mut groups: Vec<DbGroup> = data.groups
+            .values()
+            .filter(|group| {
+                group.owner_user_id.as_deref() == Some(user_id)
+                    || data.group_sessions.iter().any(|s| s.group_id == group.id && joined_sessions.contains(&s.session_id.as_str()))
+            })
+            .cloned()
+            .collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(groups)
+    }
+
+    async fn upsert_group_session(&self, snapshot: &DbGroupSession) -> StoreResult<()> {
+        let mut data = self.data.lock().await;
+        match data.group_sessions
+            .iter_mut()
+            .find(|s| s.group_id == snapshot.group_id && s.session_id == snapshot.session_id)
+        {
+            Some(existing) => {
+                existing.balances = snapshot.balances.clone();
+                existing.updated_at = snapshot.updated_at;
+            }
+            None => data.group_sessions.push(snapshot.clone()),
+        }
+        Ok(())
+    }
+
+    async fn delete_group_session(&self, group_id: &str, session_id: &str) -> StoreResult<bool> {
+        let mut data = self.data.lock().await;
+        let before = data.group_sessions.len();
+        data.group_sessions.retain(|s| !(s.group_id == group_id && s.session_id == session_id));
+        Ok(data.group_sessions.len() < before)
+    }
+
+    async fn group_sessions(&self, group_id: &str) -> StoreResult<Vec<DbGroupSession>> {
+        let data = self.data.lock().await;
+        let mut snapshots: Vec<DbGroupSession> = data.group_sessions
+            .iter()
+            .filter(|s| s.group_id == group_id)
+            .cloned()
+            .collect();
+        snapshots.sort_by_key(|s| s.added_at);
+        Ok(snapshots)
+    }
+
+    async fn active_group_session_ids(&self, group_id: &str) -> StoreResult<Vec<String>> {
+        let data = self.data.lock().await;
+        Ok(data.group_sessions
+            .iter()
+            .filter(|s| s.group_id == group_id && data.sessions.contains_key(&s.session_id))
+            .map(|s| s.session_id.clone())
+            .collect())
+    }
+
+    async fn refresh_group_snapshots(&self, session_id: &str, balances: &str, at: DateTime<Utc>) -> StoreResult<()> {
+        let mut data = self.data.lock().await;
+        for snapshot in data.group_sessions.iter_mut().filter(|s| s.session_id == session_id) {
+            snapshot.balances = balances.to_string();
+            snapshot.updated_at = at;
+        }
+        Ok(())
+    }
+
+    async fn insert_login_token(&self, login: &DbLoginToken) -> StoreResult<()> {
+        self.data.lock().await.login_tokens.insert(login.token.clone(), login.clone());
+        Ok(())
+    }
+
+    async fn consume_login_token(&self, token: &str, now: DateTime<Utc>) -> StoreResult<Option<DbLoginToken>> {
+        match self.data.lock().await.login_tokens.get_mut(token) {
+            Some(login) if login.used_at.is_none() && login.expires_at > now => {
+                login.used_at = Some(now);
+                Ok(Some(login.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn find_or_create_user(&self, email: &str, at: DateTime<Utc>) -> StoreResult<DbUser> {
+        let mut data = self.data.lock().await;
+        if let Some(user) = data.users.values().find(|u| u.email == email) {
+            return Ok(user.clone());
+        }
+
+        let user = DbUser {
+            id: Uuid::new_v4().to_string(),
+            email: email.to_string(),
+            created_at: at,
+        };
+        data.users.insert(user.id.clone(), user.clone());
+        Ok(user)
+    }
+
+    async fn insert_access_token(&self, token: &str, user_id: &str, _created_at: DateTime<Utc>, expires_at: DateTime<Utc>) -> StoreResult<()> {
+        self.data.lock().await.access_tokens.insert(token.to_string(), (user_id.to_string(), expires_at));
+        Ok(())
+    }
+
+    async fn delete_access_token(&self, token: &str) -> StoreResult<()> {
+        self.data.lock().await.access_tokens.remove(token);
+        Ok(())
+    }
+
+    async fn user_for_access_token(&self, token: &str, now: DateTime<Utc>) -> StoreResult<Option<DbUser>> {
+        let data = self.data.lock().await;
+        Ok(match data.access_tokens.get(token) {
+            Some((user_id, expires_at)) if *expires_at > now => data.users.get(user_id).cloned(),
+            _ => None,
+        })
+    }
+
+    async fn delete_expired_tokens(&self, now: DateTime<Utc>) -> StoreResult<()> {
+        let mut data = self.data.lock().await;
+        data.login_tokens.retain(|_, login| login.expires_at >= now);
+        data.access_tokens.retain(|_, (_, expires_at)| *expires_at >= now);
+        Ok(())
+    }
+
+    async fn get_idempotency_record(&self, key: &str) -> StoreResult<Option<DbIdempotencyRecord>> {
+        Ok(self.data.lock().await.idempotency_keys.get(key).cloned())
+    }
+
+    async fn insert_idempotency_record(&self, record: &DbIdempotencyRecord) -> StoreResult<bool> {
+        let mut data = self.data.lock().await;
+        if data.idempotency_keys.contains_key(&record.key) {
+            return Ok(false);
+        }
+        data.idempotency_keys.insert(record.key.clone(), record.clone());
+        Ok(true)
+    }
+
+    async fn complete_idempotency_record(&self, key: &str, status: i64, content_type: Option<&str>, body: &str) -> StoreResult<()> {
+        if let Some(record) = self.data.lock().await.idempotency_keys.get_mut(key) {
+            record.status = Some(status);
+            record.content_type = content_type.map(str::to_string);
+            record.body = Some(body.to_string());
+        }
+        Ok(())
+    }
+
+    async fn delete_idempotency_record(&self, key: &str) -> StoreResult<()> {
+        self.data.lock().await.idempotency_keys.remove(key);
+        Ok(())
+    }
+
+    async fn delete_idempotency_records_before(&self, cutoff: DateTime<Utc>) -> StoreResult<u64> {
+        let mut data = self.data.lock().await;
+        let before = data.idempotency_keys.len();
+        data.idempotency_keys.retain(|_, record| record.created_at >= cutoff);
+        Ok((before - data.idempotency_keys.len()) as u64)
+    }
+
+    async fn increment_daily_usage(&self, day: NaiveDate, subject: &str) -> StoreResult<i64> {
+        let mut data = self.data.lock().await;
+        let count = data.daily_usage.entry((day, subject.to_string())).or_insert(0);
+        *count += 1;
+        Ok(*count)
+    }
+
+    async fn get_daily_usage(&self, day: NaiveDate, subject: &str) -> StoreResult<i64> {
+        let data = self.data.lock().await;
+        Ok(data.daily_usage.get(&(day, subject.to_string())).copied().unwrap_or(0))
+    }
+
+    async fn daily_usage(&self, day: NaiveDate) -> StoreResult<Vec<DbDailyUsage>> {
+        let data = self.data.lock().await;
+        let mut usage: Vec<DbDailyUsage> = data.daily_usage.iter()
+            .filter(|((d, _), _)| *d == day)
+            .map(|((day, subject), count)| DbDailyUsage { day: *day, subject: subject.clone(), count: *count })
+            .collect();
+        usage.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.subject.cmp(&b.subject)));
+        Ok(usage)
+    }
+
+    async fn delete_daily_usage_before(&self, day: NaiveDate) -> StoreResult<()> {
+        self.data.lock().await.daily_usage.retain(|(d, _), _| *d >= day);
+        Ok(())
+    }
+
+    async fn insert_rate_limit_exemption(&self, exemption: &DbRateLimitExemption) -> StoreResult<()> {
+        let mut data = self.data.lock().await;
+        match data.rate_limit_exemptions.get_mut(&exemption.subject) {
+            Some(existing) => existing.note = exemption.note.clone(),
+            None => {
+                data.rate_limit_exemptions.insert(exemption.subject.clone(), exemption.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_rate_limit_exemption(&self, subject: &str) -> StoreResult<Option<DbRateLimitExemption>> {
+        Ok(self.data.lock().await.rate_limit_exemptions.get(subject).cloned())
+    }
+
+    async fn rate_limit_exemptions(&self) -> StoreResult<Vec<DbRateLimitExemption>> {
+        let mut exemptions: Vec<DbRateLimitExemption> = self.data.lock().await.rate_limit_exemptions.values().cloned().collect();
+        exemptions.sort_by(|a, b| a.subject.cmp(&b.subject));
+        Ok(exemptions)
+    }
+
+    async fn delete_rate_limit_exemption(&self, subject: &str) -> StoreResult<bool> {
+        Ok(self.data.lock().await.rate_limit_exemptions.remove(subject).is_some())
+    }
+
+    async fn set_reminder_schedule(&self, schedule: &DbReminderSchedule) -> StoreResult<()> {
+        let mut data = self.data.lock().await;
+        let created_at = data.reminder_schedules
+            .get(&schedule.session_id)
+            .map_or(schedule.created_at, |existing| existing.created_at);
+        data.reminder_schedules.insert(schedule.session_id.clone(), DbReminderSchedule { created_at, ..schedule.clone() });
+        Ok(())
+    }
+
+    async fn get_reminder_schedule(&self, session_id: &str) -> StoreResult<Option<DbReminderSchedule>> {
+        Ok(self.data.lock().await.reminder_schedules.get(session_id).cloned())
+    }
+
+    async fn reminder_schedules(&self) -> StoreResult<Vec<DbReminderSchedule>> {
+        Ok(self.data.lock().await.reminder_schedules.values().cloned().collect())
+    }
+
+    async fn delete_reminder_schedule(&self, session_id: &str) -> StoreResult<bool> {
+        Ok(self.data.lock().await.reminder_schedules.remove(session_id).is_some())
+    }
+
+    async fn payment_reminders_for_session(&self, session_id: &str) -> StoreResult<Vec<DbPaymentReminder>> {
+        let data = self.data.lock().await;
+        let mut reminders: Vec<DbPaymentReminder> = data.payment_reminders
+            .values()
+            .filter(|reminder| reminder.session_id == session_id)
+            .cloned()
+            .collect();
+        reminders.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(reminders)
+    }
+
+    async fn get_payment_reminder(&self, token: &str) -> StoreResult<Option<DbPaymentReminder>> {
+        Ok(self.data.lock().await.payment_reminders.get(token).cloned())
+    }
+
+    async fn record_payment_reminder(&self, reminder: &DbPaymentReminder) -> StoreResult<()> {
+        let mut data = self.data.lock().await;
+        let existing = data.payment_reminders
+            .values_mut()
+            .find(|r| r.session_id == reminder.session_id && r.name == reminder.name);
+        match existing {
+            Some(existing) => {
+                existing.amount = reminder.amount;
+                existing.transfers = reminder.transfers.clone();
+                existing.reminders_sent = reminder.reminders_sent;
+                existing.last_sent_at = reminder.last_sent_at;
+                if existing.paid_at.is_none_or(|at| at <= reminder.last_sent_at) {
+                    existing.paid_at = reminder.paid_at;
+                }
+            }
+            None => {
+                data.payment_reminders.insert(reminder.token.clone(), reminder.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn mark_payment_paid(&self, token: &str, at: DateTime<Utc>) -> StoreResult<bool> {
+        let mut data = self.data.lock().await;
+        match data.payment_reminders.get_mut(token) {
+            Some(reminder) if reminder.paid_at.is_none() => {
+                reminder.paid_at = Some(at);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    // Nothing to reclaim in memory
+    async fn vacuum(&self) -> StoreResult<()> {
+        Ok(())
+    }
+}
+
+fn has_notification_emails(session: &DbSession) -> bool {
+    !session.notification_emails.is_empty() && session.notification_emails != "[]"
+}
+
+fn newest_first<'a>(archives: impl Iterator<Item = &'a DbArchive>) -> Vec<DbArchive> {
+    let mut archives: Vec<DbArchive> = archives.cloned().collect();
+    archives.sort_by_key(|a| std::cmp::Reverse(a.archived_at));
+    archives
+}
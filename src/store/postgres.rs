@@ -0,0 +1,1032 @@
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions};
+use uuid::Uuid;
+
+use super::{SessionStore, StoreResult};
+use crate::models::*;
+
+// Same tables and columns as the SQLite backend, with Postgres types. Lets several
+// instances share one database behind a load balancer.
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    pub async fn connect(options: PgConnectOptions) -> StoreResult<Self> {
+        let pool = PgPoolOptions::new().connect_with(options).await?;
+        run_migrations(&pool).await?;
+        Ok(Self { pool })
+    }
+}
+
+async fn run_migrations(pool: &PgPool) -> StoreResult<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS sessions (
+            id TEXT PRIMARY KEY,
+            edit_secret TEXT NOT NULL,
+            people TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL,
+            last_accessed_at TIMESTAMPTZ NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    for column in [
+        "fund_amount DOUBLE PRECISION DEFAULT 0.0",
+        "tip_percentage DOUBLE PRECISION DEFAULT 0.0",
+        "include_sponsor BOOLEAN DEFAULT TRUE",
+        "expiry_days BIGINT",
+        "pinned BOOLEAN DEFAULT FALSE",
+        "notification_emails TEXT DEFAULT '[]'",
+        "expiry_warning_sent_at TIMESTAMPTZ",
+        "extend_token TEXT",
+        "currency TEXT",
+        "owner_user_id TEXT",
+    ] {
+        sqlx::query(&format!("ALTER TABLE sessions ADD COLUMN IF NOT EXISTS {}", column))
+            .execute(pool)
+            .await?;
+    }
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS participant_links (
+            token TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL,
+            claimed_at TIMESTAMPTZ,
+            UNIQUE (session_id, name)
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("ALTER TABLE participant_links ADD COLUMN IF NOT EXISTS user_id TEXT")
+        .execute(pool)
+        .await?;
+
+    sqlx::query("ALTER TABLE participant_links ADD COLUMN IF NOT EXISTS email TEXT")
+        .execute(pool)
+        .await?;
+
+    sqlx::query("ALTER TABLE participant_links ADD COLUMN IF NOT EXISTS language TEXT")
+        .execute(pool)
+        .await?;
+
+    // Archives outlive their sessions, so they are never touched by cleanup
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS archives (
+            id TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            edit_secret TEXT NOT NULL,
+            owner_token TEXT,
+            group_id TEXT,
+            title TEXT NOT NULL DEFAULT '',
+            people TEXT NOT NULL,
+            fund_amount DOUBLE PRECISION NOT NULL DEFAULT 0.0,
+            tip_percentage DOUBLE PRECISION NOT NULL DEFAULT 0.0,
+            include_sponsor BOOLEAN NOT NULL DEFAULT TRUE,
+            calculation TEXT NOT NULL,
+            archived_at TIMESTAMPTZ NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("ALTER TABLE archives ADD COLUMN IF NOT EXISTS currency TEXT")
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS templates (
+            id TEXT PRIMARY KEY,
+            owner_token TEXT NOT NULL,
+            name TEXT NOT NULL,
+            body TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL,
+            updated_at TIMESTAMPTZ NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS groups (
+            id TEXT PRIMARY KEY,
+            edit_secret TEXT NOT NULL,
+            name TEXT NOT NULL,
+            members TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("ALTER TABLE groups ADD COLUMN IF NOT EXISTS owner_user_id TEXT")
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS group_sessions (
+            group_id TEXT NOT NULL,
+            session_id TEXT NOT NULL,
+            balances TEXT NOT NULL,
+            added_at TIMESTAMPTZ NOT NULL,
+            updated_at TIMESTAMPTZ NOT NULL,
+            PRIMARY KEY (group_id, session_id)
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS users (
+            id TEXT PRIMARY KEY,
+            email TEXT NOT NULL UNIQUE,
+            created_at TIMESTAMPTZ NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS login_tokens (
+            token TEXT PRIMARY KEY,
+            email TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL,
+            expires_at TIMESTAMPTZ NOT NULL,
+            used_at TIMESTAMPTZ
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS access_tokens (
+            token TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL,
+            expires_at TIMESTAMPTZ NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS sync_operations (
+            session_id TEXT NOT NULL,
+            op_id TEXT NOT NULL,
+            target TEXT NOT NULL,
+            client_timestamp BIGINT NOT NULL,
+            applied_at TIMESTAMPTZ NOT NULL,
+            PRIMARY KEY (session_id, op_id)
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS idempotency_keys (
+            key TEXT PRIMARY KEY,
+            request_hash TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL,
+            status BIGINT,
+            content_type TEXT,
+            body TEXT
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS daily_usage (
+            day DATE NOT NULL,
+            subject TEXT NOT NULL,
+            count BIGINT NOT NULL,
+            PRIMARY KEY (day, subject)
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS rate_limit_exemptions (
+            subject TEXT PRIMARY KEY,
+            note TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS reminder_schedules (
+            session_id TEXT PRIMARY KEY,
+            first_after_days BIGINT NOT NULL,
+            repeat_days BIGINT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS payment_reminders (
+            token TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            amount DOUBLE PRECISION NOT NULL,
+            transfers TEXT NOT NULL DEFAULT '[]',
+            reminders_sent BIGINT NOT NULL,
+            last_sent_at TIMESTAMPTZ NOT NULL,
+            paid_at TIMESTAMPTZ,
+            UNIQUE (session_id, name)
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("ALTER TABLE payment_reminders ADD COLUMN IF NOT EXISTS transfers TEXT NOT NULL DEFAULT '[]'")
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl SessionStore for PostgresStore {
+    async fn insert_session(&self, session: &DbSession) -> StoreResult<()> {
+        sqlx::query(
+            "INSERT INTO sessions (id, edit_secret, people, created_at, last_accessed_at, fund_amount, tip_percentage, include_sponsor, expiry_days, pinned, notification_emails, expiry_warning_sent_at, extend_token, currency, owner_user_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)"
+        )
+        .bind(&session.id)
+        .bind(&session.edit_secret)
+        .bind(&session.people)
+        .bind(session.created_at)
+        .bind(session.last_accessed_at)
+        .bind(session.fund_amount)
+        .bind(session.tip_percentage)
+        .bind(session.include_sponsor)
+        .bind(session.expiry_days)
+        .bind(session.pinned)
+        .bind(&session.notification_emails)
+        .bind(session.expiry_warning_sent_at)
+        .bind(&session.extend_token)
+        .bind(&session.currency)
+        .bind(&session.owner_user_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &str) -> StoreResult<Option<DbSession>> {
+        sqlx::query_as("SELECT * FROM sessions WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn touch_session(&self, id: &str, at: DateTime<Utc>) -> StoreResult<()> {
+        sqlx::query("UPDATE sessions SET last_accessed_at = $1 WHERE id = $2")
+            .bind(at)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_session_contents(&self, session: &DbSession) -> StoreResult<()> {
+        sqlx::query("UPDATE sessions SET people = $1, fund_amount = $2, tip_percentage = $3, include_sponsor = $4, currency = $5, last_accessed_at = $6 WHERE id = $7")
+            .bind(&session.people)
+            .bind(session.fund_amount)
+            .bind(session.tip_percentage)
+            .bind(session.include_sponsor)
+            .bind(&session.currency)
+            .bind(session.last_accessed_at)
+            .bind(&session.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_session_expiry(&self, session: &DbSession) -> StoreResult<()> {
+        sqlx::query("UPDATE sessions SET expiry_days = $1, pinned = $2, last_accessed_at = $3 WHERE id = $4")
+            .bind(session.expiry_days)
+            .bind(session.pinned)
+            .bind(session.last_accessed_at)
+            .bind(&session.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_session_notifications(&self, id: &str, emails: &str) -> StoreResult<()> {
+        sqlx::query("UPDATE sessions SET notification_emails = $1 WHERE id = $2")
+            .bind(emails)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn record_expiry_warning(&self, id: &str, extend_token: &str, at: DateTime<Utc>) -> StoreResult<()> {
+        sqlx::query("UPDATE sessions SET expiry_warning_sent_at = $1, extend_token = $2 WHERE id = $3")
+            .bind(at)
+            .bind(extend_token)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn extend_session(&self, id: &str, extend_token: &str, at: DateTime<Utc>) -> StoreResult<bool> {
+        let result = sqlx::query("UPDATE sessions SET last_accessed_at = $1 WHERE id = $2 AND extend_token = $3")
+            .bind(at)
+            .bind(id)
+            .bind(extend_token)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_session_owner(&self, id: &str, edit_secret: &str, user_id: &str) -> StoreResult<bool> {
+        let result = sqlx::query("UPDATE sessions SET owner_user_id = $1 WHERE id = $2 AND edit_secret = $3")
+            .bind(user_id)
+            .bind(id)
+            .bind(edit_secret)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_session(&self, id: &str) -> StoreResult<()> {
+        sqlx::query("DELETE FROM sessions WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        for table in ["participant_links", "sync_operations", "reminder_schedules", "payment_reminders"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE session_id = $1", table))
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_expired_sessions(&self, default_expiry_days: i64, warning_days: Option<i64>, now: DateTime<Utc>) -> StoreResult<u64> {
+        // Sessions without their own expiry use the server default; pinned sessions never expire.
+        // Sessions with notification emails are kept until warning_days after a warning that went
+        // out after their last access, or at most warning_days past their expiry.
+        let result = sqlx::query(
+            r#"
+            DELETE FROM sessions
+            WHERE NOT pinned
+              AND last_accessed_at + make_interval(days => COALESCE(expiry_days, $1)::int) < $2
+              AND (
+                $3::bigint IS NULL
+                OR COALESCE(notification_emails, '[]') = '[]'
+                OR (
+                  expiry_warning_sent_at IS NOT NULL
+                  AND expiry_warning_sent_at >= last_accessed_at
+                  AND expiry_warning_sent_at + make_interval(days => $3::int) <= $2
+                )
+                OR last_accessed_at + make_interval(days => (COALESCE(expiry_days, $1) + $3)::int) <= $2
+              )
+            "#
+        )
+            .bind(default_expiry_days)
+            .bind(now)
+            .bind(warning_days)
+            .execute(&self.pool)
+            .await?;
+
+        for table in ["participant_links", "sync_operations", "reminder_schedules", "payment_reminders"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE session_id NOT IN (SELECT id FROM sessions)", table))
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(result.rows_affected())
+    }
+
+    async fn sessions_due_for_warning(&self, default_expiry_days: i64, warning_days: i64, now: DateTime<Utc>) -> StoreResult<Vec<DbSession>> {
+        sqlx::query_as(
+            r#"
+            SELECT * FROM sessions
+            WHERE NOT pinned
+              AND COALESCE(notification_emails, '[]') != '[]'
+              AND (expiry_warning_sent_at IS NULL OR expiry_warning_sent_at < last_accessed_at)
+              AND last_accessed_at + make_interval(days => (COALESCE(expiry_days, $1) - $2)::int) < $3
+            "#
+        )
+            .bind(default_expiry_days)
+            .bind(warning_days)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn sessions_owned_by(&self, user_id: &str) -> StoreResult<Vec<DbSession>> {
+        sqlx::query_as("SELECT * FROM sessions WHERE owner_user_id = $1 ORDER BY last_accessed_at DESC")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn all_sessions(&self) -> StoreResult<Vec<DbSession>> {
+        sqlx::query_as("SELECT * FROM sessions ORDER BY last_accessed_at DESC")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn ensure_participant_links(&self, session_id: &str, names: &[&str], at: DateTime<Utc>) -> StoreResult<()> {
+        for name in names {
+            sqlx::query("INSERT INTO participant_links (token, session_id, name, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING")
+                .bind(Uuid::new_v4().to_string())
+                .bind(session_id)
+                .bind(name)
+                .bind(at)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn participant_links_for_session(&self, session_id: &str) -> StoreResult<Vec<DbParticipantLink>> {
+        sqlx::query_as("SELECT * FROM participant_links WHERE session_id = $1 ORDER BY name")
+            .bind(session_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn participant_links_for_user(&self, user_id: &str) -> StoreResult<Vec<DbParticipantLink>> {
+        sqlx::query_as("SELECT * FROM participant_links WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_participant_link(&self, token: &str) -> StoreResult<Option<DbParticipantLink>> {
+        sqlx::query_as("SELECT * FROM participant_links WHERE token = $1")
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn mark_participant_link_claimed(&self, token: &str, at: DateTime<Utc>) -> StoreResult<()> {
+        sqlx::query("UPDATE participant_links SET claimed_at = $1 WHERE token = $2 AND claimed_at IS NULL")
+            .bind(at)
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_participant_link_user(&self, token: &str, user_id: &str) -> StoreResult<()> {
+        sqlx::query("UPDATE participant_links SET user_id = $1 WHERE token = $2")
+            .bind(user_id)
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_participant_link_email(&self, session_id: &str, name: &str, email: Option<&str>) -> StoreResult<()> {
+        sqlx::query("UPDATE participant_links SET email = $1 WHERE session_id = $2 AND name = $3")
+            .bind(email)
+            .bind(session_id)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_participant_link_language(&self, session_id: &str, name: &str, language: &str) -> StoreResult<()> {
+        sqlx::query("UPDATE participant_links SET language = $1 WHERE session_id = $2 AND name = $3")
+            .bind(language)
+            .bind(session_id)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn sync_operations(&self, session_id: &str) -> StoreResult<Vec<DbSyncOperation>> {
+        sqlx::query_as("SELECT * FROM sync_operations WHERE session_id = $1")
+            .bind(session_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn insert_sync_operations(&self, operations: &[DbSyncOperation]) -> StoreResult<()> {
+        for op in operations {
+            sqlx::query("INSERT INTO sync_operations (session_id, op_id, target, client_timestamp, applied_at) VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING")
+                .bind(&op.session_id)
+                .bind(&op.op_id)
+                .bind(&op.target)
+                .bind(op.client_timestamp)
+                .bind(op.applied_at)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_archive(&self, archive: &DbArchive) -> StoreResult<()> {
+        sqlx::query(
+            "INSERT INTO archives (id, session_id, edit_secret, owner_token, group_id, title, people, fund_amount, tip_percentage, include_sponsor, calculation, archived_at, currency) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)"
+        )
+        .bind(&archive.id)
+        .bind(&archive.session_id)
+        .bind(&archive.edit_secret)
+        .bind(&archive.owner_token)
+        .bind(&archive.group_id)
+        .bind(&archive.title)
+        .bind(&archive.people)
+        .bind(archive.fund_amount)
+        .bind(archive.tip_percentage)
+        .bind(archive.include_sponsor)
+        .bind(&archive.calculation)
+        .bind(archive.archived_at)
+        .bind(&archive.currency)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_archive(&self, id: &str) -> StoreResult<Option<DbArchive>> {
+        sqlx::query_as("SELECT * FROM archives WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn archives_for_group(&self, group_id: &str) -> StoreResult<Vec<DbArchive>> {
+        sqlx::query_as("SELECT * FROM archives WHERE group_id = $1 ORDER BY archived_at DESC")
+            .bind(group_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn archives_for_owner(&self, owner_token: &str) -> StoreResult<Vec<DbArchive>> {
+        sqlx::query_as("SELECT * FROM archives WHERE owner_token = $1 ORDER BY archived_at DESC")
+            .bind(owner_token)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_archive(&self, id: &str) -> StoreResult<()> {
+        sqlx::query("DELETE FROM archives WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_template(&self, template: &DbTemplate) -> StoreResult<()> {
+        sqlx::query("INSERT INTO templates (id, owner_token, name, body, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(&template.id)
+            .bind(&template.owner_token)
+            .bind(&template.name)
+            .bind(&template.body)
+            .bind(template.created_at)
+            .bind(template.updated_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_template(&self, id: &str) -> StoreResult<Option<DbTemplate>> {
+        sqlx::query_as("SELECT * FROM templates WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn templates_for_owner(&self, owner_token: &str) -> StoreResult<Vec<DbTemplate>> {
+        sqlx::query_as("SELECT * FROM templates WHERE owner_token = $1 ORDER BY name")
+            .bind(owner_token)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn update_template(&self, template: &DbTemplate) -> StoreResult<()> {
+        sqlx::query("UPDATE templates SET name = $1, body = $2, updated_at = $3 WHERE id = $4")
+            .bind(&template.name)
+            .bind(&template.body)
+            .bind(template.updated_at)
+            .bind(&template.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_template(&self, id: &str) -> StoreResult<()> {
+        sqlx::query("DELETE FROM templates WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_group(&self, group: &DbGroup) -> StoreResult<()> {
+        sqlx::query("INSERT INTO groups (id, edit_secret, name, members, created_at, owner_user_id) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(&group.id)
+            .bind(&group.edit_secret)
+            .bind(&group.name)
+            .bind(&group.members)
+            .bind(group.created_at)
+            .bind(&group.owner_user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_group(&self, id: &str) -> StoreResult<Option<DbGroup>> {
+        sqlx::query_as("SELECT * FROM groups WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn update_group(&self, group: &DbGroup) -> StoreResult<()> {
+        sqlx::query("UPDATE groups SET name = $1, members = $2 WHERE id = $3")
+            .bind(&group.name)
+            .bind(&group.members)
+            .bind(&group.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_group_owner(&self, id: &str, edit_secret: &str, user_id: &str) -> StoreResult<bool> {
+        let result = sqlx::query("UPDATE groups SET owner_user_id = $1 WHERE id = $2 AND edit_secret = $3")
+            .bind(user_id)
+            .bind(id)
+            .bind(edit_secret)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn groups_for_user(&self, user_id: &str) -> StoreResult<Vec<DbGroup>> {
+        sqlx::query_as(
+            r#"
+            SELECT * FROM groups
+            WHERE owner_user_id = $1
+               OR id IN (
+                   SELECT g.group_id FROM group_sessions g
+                   JOIN participant_links p ON p.session_id = g.session_id
+                   WHERE p.user_id = $2
+               )
+            ORDER BY name
+            "#
+        )
+            .bind(user_id)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn upsert_group_session(&self, snapshot: &DbGroupSession) -> StoreResult<()> {
+        sqlx::query("INSERT INTO group_sessions (group_id, session_id, balances, added_at, updated_at) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (group_id, session_id) DO UPDATE SET balances = excluded.balances, updated_at = excluded.updated_at")
+            .bind(&snapshot.group_id)
+            .bind(&snapshot.session_id)
+            .bind(&snapshot.balances)
+            .bind(snapshot.added_at)
+            .bind(snapshot.updated_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_group_session(&self, group_id: &str, session_id: &str) -> StoreResult<bool> {
+        let result = sqlx::query("DELETE FROM group_sessions WHERE group_id = $1 AND session_id = $2")
+            .bind(group_id)
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn group_sessions(&self, group_id: &str) -> StoreResult<Vec<DbGroupSession>> {
+        sqlx::query_as("SELECT * FROM group_sessions WHERE group_id = $1 ORDER BY added_at")
+            .bind(group_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn active_group_session_ids(&self, group_id: &str) -> StoreResult<Vec<String>> {
+        let rows: Vec<(String,)> = sqlx::query_as("SELECT s.id FROM sessions s JOIN group_sessions g ON g.session_id = s.id WHERE g.group_id = $1")
+            .bind(group_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(|(id,)| id).collect())
+    }
+
+    async fn refresh_group_snapshots(&self, session_id: &str, balances: &str, at: DateTime<Utc>) -> StoreResult<()> {
+        sqlx::query("UPDATE group_sessions SET balances = $1, updated_at = $2 WHERE session_id = $3")
+            .bind(balances)
+            .bind(at)
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_login_token(&self, login: &DbLoginToken) -> StoreResult<()> {
+        sqlx::query("INSERT INTO login_tokens (token, email, created_at, expires_at) VALUES ($1, $2, $3, $4)")
+            .bind(&login.token)
+            .bind(&login.email)
+            .bind(login.created_at)
+            .bind(login.expires_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn consume_login_token(&self, token: &str, now: DateTime<Utc>) -> StoreResult<Option<DbLoginToken>> {
+        // Mark the token used in the same statement that checks it, so it only works once
+        sqlx::query_as("UPDATE login_tokens SET used_at = $1 WHERE token = $2 AND used_at IS NULL AND expires_at > $3 RETURNING *")
+            .bind(now)
+            .bind(token)
+            .bind(now)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn find_or_create_user(&self, email: &str, at: DateTime<Utc>) -> StoreResult<DbUser> {
+        sqlx::query("INSERT INTO users (id, email, created_at) VALUES ($1, $2, $3) ON CONFLICT (email) DO NOTHING")
+            .bind(Uuid::new_v4().to_string())
+            .bind(email)
+            .bind(at)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query_as("SELECT * FROM users WHERE email = $1")
+            .bind(email)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn insert_access_token(&self, token: &str, user_id: &str, created_at: DateTime<Utc>, expires_at: DateTime<Utc>) -> StoreResult<()> {
+        sqlx::query("INSERT INTO access_tokens (token, user_id, created_at, expires_at) VALUES ($1, $2, $3, $4)")
+            .bind(token)
+            .bind(user_id)
+            .bind(created_at)
+            .bind(expires_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_access_token(&self, token: &str) -> StoreResult<()> {
+        sqlx::query("DELETE FROM access_tokens WHERE token = $1")
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn user_for_access_token(&self, token: &str, now: DateTime<Utc>) -> StoreResult<Option<DbUser>> {
+        sqlx::query_as("SELECT u.* FROM users u JOIN access_tokens t ON t.user_id = u.id WHERE t.token = $1 AND t.expires_at > $2")
+            .bind(token)
+            .bind(now)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn delete_expired_tokens(&self, now: DateTime<Utc>) -> StoreResult<()> {
+        for table in ["login_tokens", "access_tokens"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE expires_at < $1", table))
+                .bind(now)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn get_idempotency_record(&self, key: &str) -> StoreResult<Option<DbIdempotencyRecord>> {
+        sqlx::query_as("SELECT * FROM idempotency_keys WHERE key = $1")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn insert_idempotency_record(&self, record: &DbIdempotencyRecord) -> StoreResult<bool> {
+        let result = sqlx::query("INSERT INTO idempotency_keys (key, request_hash, created_at, status, content_type, body) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT DO NOTHING")
+            .bind(&record.key)
+            .bind(&record.request_hash)
+            .bind(record.created_at)
+            .bind(record.status)
+            .bind(&record.content_type)
+            .bind(&record.body)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn complete_idempotency_record(&self, key: &str, status: i64, content_type: Option<&str>, body: &str) -> StoreResult<()> {
+        sqlx::query("UPDATE idempotency_keys SET status = $1, content_type = $2, body = $3 WHERE key = $4")
+            .bind(status)
+            .bind(content_type)
+            .bind(body)
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_idempotency_record(&self, key: &str) -> StoreResult<()> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE key = $1")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_idempotency_records_before(&self, cutoff: DateTime<Utc>) -> StoreResult<u64> {
+        let result = sqlx::query("DELETE FROM idempotency_keys WHERE created_at < $1")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn increment_daily_usage(&self, day: NaiveDate, subject: &str) -> StoreResult<i64> {
+        sqlx::query_scalar(
+            "INSERT INTO daily_usage (day, subject, count) VALUES ($1, $2, 1) ON CONFLICT (day, subject) DO UPDATE SET count = daily_usage.count + 1 RETURNING count"
+        )
+            .bind(day)
+            .bind(subject)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn get_daily_usage(&self, day: NaiveDate, subject: &str) -> StoreResult<i64> {
+        let count: Option<i64> = sqlx::query_scalar("SELECT count FROM daily_usage WHERE day = $1 AND subject = $2")
+            .bind(day)
+            .bind(subject)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(count.unwrap_or(0))
+    }
+
+    async fn daily_usage(&self, day: NaiveDate) -> StoreResult<Vec<DbDailyUsage>> {
+        sqlx::query_as("SELECT * FROM daily_usage WHERE day = $1 ORDER BY count DESC, subject")
+            .bind(day)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_daily_usage_before(&self, day: NaiveDate) -> StoreResult<()> {
+        sqlx::query("DELETE FROM daily_usage WHERE day < $1")
+            .bind(day)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_rate_limit_exemption(&self, exemption: &DbRateLimitExemption) -> StoreResult<()> {
+        sqlx::query("INSERT INTO rate_limit_exemptions (subject, note, created_at) VALUES ($1, $2, $3) ON CONFLICT (subject) DO UPDATE SET note = excluded.note")
+            .bind(&exemption.subject)
+            .bind(&exemption.note)
+            .bind(exemption.created_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_rate_limit_exemption(&self, subject: &str) -> StoreResult<Option<DbRateLimitExemption>> {
+        sqlx::query_as("SELECT * FROM rate_limit_exemptions WHERE subject = $1")
+            .bind(subject)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn rate_limit_exemptions(&self) -> StoreResult<Vec<DbRateLimitExemption>> {
+        sqlx::query_as("SELECT * FROM rate_limit_exemptions ORDER BY subject")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_rate_limit_exemption(&self, subject: &str) -> StoreResult<bool> {
+        let result = sqlx::query("DELETE FROM rate_limit_exemptions WHERE subject = $1")
+            .bind(subject)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_reminder_schedule(&self, schedule: &DbReminderSchedule) -> StoreResult<()> {
+        sqlx::query(
+            "INSERT INTO reminder_schedules (session_id, first_after_days, repeat_days, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT (session_id) DO UPDATE SET first_after_days = excluded.first_after_days, repeat_days = excluded.repeat_days"
+        )
+            .bind(&schedule.session_id)
+            .bind(schedule.first_after_days)
+            .bind(schedule.repeat_days)
+            .bind(schedule.created_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_reminder_schedule(&self, session_id: &str) -> StoreResult<Option<DbReminderSchedule>> {
+        sqlx::query_as("SELECT * FROM reminder_schedules WHERE session_id = $1")
+            .bind(session_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn reminder_schedules(&self) -> StoreResult<Vec<DbReminderSchedule>> {
+        sqlx::query_as("SELECT * FROM reminder_schedules")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_reminder_schedule(&self, session_id: &str) -> StoreResult<bool> {
+        let result = sqlx::query("DELETE FROM reminder_schedules WHERE session_id = $1")
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn payment_reminders_for_session(&self, session_id: &str) -> StoreResult<Vec<DbPaymentReminder>> {
+        sqlx::query_as("SELECT * FROM payment_reminders WHERE session_id = $1 ORDER BY name")
+            .bind(session_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_payment_reminder(&self, token: &str) -> StoreResult<Option<DbPaymentReminder>> {
+        sqlx::query_as("SELECT * FROM payment_reminders WHERE token = $1")
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn record_payment_reminder(&self, reminder: &DbPaymentReminder) -> StoreResult<()> {
+        sqlx::query(
+            "INSERT INTO payment_reminders (token, session_id, name, amount, transfers, reminders_sent, last_sent_at, paid_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (session_id, name) DO UPDATE SET amount = excluded.amount, transfers = excluded.transfers, reminders_sent = excluded.reminders_sent, last_sent_at = excluded.last_sent_at, paid_at = CASE WHEN payment_reminders.paid_at > excluded.last_sent_at THEN payment_reminders.paid_at ELSE excluded.paid_at END"
+        )
+            .bind(&reminder.token)
+            .bind(&reminder.session_id)
+            .bind(&reminder.name)
+            .bind(reminder.amount)
+            .bind(&reminder.transfers)
+            .bind(reminder.reminders_sent)
+            .bind(reminder.last_sent_at)
+            .bind(reminder.paid_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn mark_payment_paid(&self, token: &str, at: DateTime<Utc>) -> StoreResult<bool> {
+        let result = sqlx::query("UPDATE payment_reminders SET paid_at = $1 WHERE token = $2 AND paid_at IS NULL")
+            .bind(at)
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn vacuum(&self) -> StoreResult<()> {
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        Ok(())
+    }
+}
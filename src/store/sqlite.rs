@@ -0,0 +1,1080 @@
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use super::{SessionStore, StoreResult};
+use crate::models::*;
+
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+pub async fn run_migrations(pool: &SqlitePool) {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS sessions (
+            id TEXT PRIMARY KEY,
+            edit_secret TEXT NOT NULL,
+            people TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            last_accessed_at DATETIME NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create table");
+
+    // Migration: Add fund_amount column if it doesn't exist
+    // We ignore the error because it will fail if the column already exists
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN fund_amount REAL DEFAULT 0.0")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN tip_percentage REAL DEFAULT 0.0")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN include_sponsor BOOLEAN DEFAULT 1")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN expiry_days INTEGER")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN pinned BOOLEAN DEFAULT 0")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN notification_emails TEXT DEFAULT '[]'")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN expiry_warning_sent_at DATETIME")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN extend_token TEXT")
+        .execute(pool)
+        .await;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS participant_links (
+            token TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            claimed_at DATETIME,
+            UNIQUE (session_id, name)
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create participant_links table");
+
+    // Archives outlive their sessions, so they are never touched by cleanup
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS archives (
+            id TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            edit_secret TEXT NOT NULL,
+            owner_token TEXT,
+            group_id TEXT,
+            title TEXT NOT NULL DEFAULT '',
+            people TEXT NOT NULL,
+            fund_amount REAL NOT NULL DEFAULT 0.0,
+            tip_percentage REAL NOT NULL DEFAULT 0.0,
+            include_sponsor BOOLEAN NOT NULL DEFAULT 1,
+            calculation TEXT NOT NULL,
+            archived_at DATETIME NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create archives table");
+
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN currency TEXT")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE archives ADD COLUMN currency TEXT")
+        .execute(pool)
+        .await;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS templates (
+            id TEXT PRIMARY KEY,
+            owner_token TEXT NOT NULL,
+            name TEXT NOT NULL,
+            body TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create templates table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS groups (
+            id TEXT PRIMARY KEY,
+            edit_secret TEXT NOT NULL,
+            name TEXT NOT NULL,
+            members TEXT NOT NULL,
+            created_at DATETIME NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create groups table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS group_sessions (
+            group_id TEXT NOT NULL,
+            session_id TEXT NOT NULL,
+            balances TEXT NOT NULL,
+            added_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL,
+            PRIMARY KEY (group_id, session_id)
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create group_sessions table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS users (
+            id TEXT PRIMARY KEY,
+            email TEXT NOT NULL UNIQUE,
+            created_at DATETIME NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create users table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS login_tokens (
+            token TEXT PRIMARY KEY,
+            email TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            expires_at DATETIME NOT NULL,
+            used_at DATETIME
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create login_tokens table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS access_tokens (
+            token TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            expires_at DATETIME NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create access_tokens table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS sync_operations (
+            session_id TEXT NOT NULL,
+            op_id TEXT NOT NULL,
+            target TEXT NOT NULL,
+            client_timestamp INTEGER NOT NULL,
+            applied_at DATETIME NOT NULL,
+            PRIMARY KEY (session_id, op_id)
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create sync_operations table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS idempotency_keys (
+            key TEXT PRIMARY KEY,
+            request_hash TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            status INTEGER,
+            content_type TEXT,
+            body TEXT
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create idempotency_keys table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS daily_usage (
+            day TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            count INTEGER NOT NULL,
+            PRIMARY KEY (day, subject)
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create daily_usage table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS rate_limit_exemptions (
+            subject TEXT PRIMARY KEY,
+            note TEXT NOT NULL,
+            created_at DATETIME NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create rate_limit_exemptions table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS reminder_schedules (
+            session_id TEXT PRIMARY KEY,
+            first_after_days INTEGER NOT NULL,
+            repeat_days INTEGER NOT NULL,
+            created_at DATETIME NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create reminder_schedules table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS payment_reminders (
+            token TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            amount REAL NOT NULL,
+            transfers TEXT NOT NULL DEFAULT '[]',
+            reminders_sent INTEGER NOT NULL,
+            last_sent_at DATETIME NOT NULL,
+            paid_at DATETIME,
+            UNIQUE (session_id, name)
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create payment_reminders table");
+
+    let _ = sqlx::query("ALTER TABLE payment_reminders ADD COLUMN transfers TEXT NOT NULL DEFAULT '[]'")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN owner_user_id TEXT")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE groups ADD COLUMN owner_user_id TEXT")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE participant_links ADD COLUMN user_id TEXT")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE participant_links ADD COLUMN email TEXT")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE participant_links ADD COLUMN language TEXT")
+        .execute(pool)
+        .await;
+}
+
+#[async_trait]
+impl SessionStore for SqliteStore {
+    async fn insert_session(&self, session: &DbSession) -> StoreResult<()> {
+        sqlx::query(
+            "INSERT INTO sessions (id, edit_secret, people, created_at, last_accessed_at, fund_amount, tip_percentage, include_sponsor, expiry_days, pinned, notification_emails, expiry_warning_sent_at, extend_token, currency, owner_user_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&session.id)
+        .bind(&session.edit_secret)
+        .bind(&session.people)
+        .bind(session.created_at)
+        .bind(session.last_accessed_at)
+        .bind(session.fund_amount)
+        .bind(session.tip_percentage)
+        .bind(session.include_sponsor)
+        .bind(session.expiry_days)
+        .bind(session.pinned)
+        .bind(&session.notification_emails)
+        .bind(session.expiry_warning_sent_at)
+        .bind(&session.extend_token)
+        .bind(&session.currency)
+        .bind(&session.owner_user_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &str) -> StoreResult<Option<DbSession>> {
+        sqlx::query_as("SELECT * FROM sessions WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn touch_session(&self, id: &str, at: DateTime<Utc>) -> StoreResult<()> {
+        sqlx::query("UPDATE sessions SET last_accessed_at = ? WHERE id = ?")
+            .bind(at)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_session_contents(&self, session: &DbSession) -> StoreResult<()> {
+        sqlx::query("UPDATE sessions SET people = ?, fund_amount = ?, tip_percentage = ?, include_sponsor = ?, currency = ?, last_accessed_at = ? WHERE id = ?")
+            .bind(&session.people)
+            .bind(session.fund_amount)
+            .bind(session.tip_percentage)
+            .bind(session.include_sponsor)
+            .bind(&session.currency)
+            .bind(session.last_accessed_at)
+            .bind(&session.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_session_expiry(&self, session: &DbSession) -> StoreResult<()> {
+        sqlx::query("UPDATE sessions SET expiry_days = ?, pinned = ?, last_accessed_at = ? WHERE id = ?")
+            .bind(session.expiry_days)
+            .bind(session.pinned)
+            .bind(session.last_accessed_at)
+            .bind(&session.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_session_notifications(&self, id: &str, emails: &str) -> StoreResult<()> {
+        sqlx::query("UPDATE sessions SET notification_emails = ? WHERE id = ?")
+            .bind(emails)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn record_expiry_warning(&self, id: &str, extend_token: &str, at: DateTime<Utc>) -> StoreResult<()> {
+        sqlx::query("UPDATE sessions SET expiry_warning_sent_at = ?, extend_token = ? WHERE id = ?")
+            .bind(at)
+            .bind(extend_token)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn extend_session(&self, id: &str, extend_token: &str, at: DateTime<Utc>) -> StoreResult<bool> {
+        let result = sqlx::query("UPDATE sessions SET last_accessed_at = ? WHERE id = ? AND extend_token = ?")
+            .bind(at)
+            .bind(id)
+            .bind(extend_token)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_session_owner(&self, id: &str, edit_secret: &str, user_id: &str) -> StoreResult<bool> {
+        let result = sqlx::query("UPDATE sessions SET owner_user_id = ? WHERE id = ? AND edit_secret = ?")
+            .bind(user_id)
+            .bind(id)
+            .bind(edit_secret)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_session(&self, id: &str) -> StoreResult<()> {
+        sqlx::query("DELETE FROM sessions WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        for table in ["participant_links", "sync_operations", "reminder_schedules", "payment_reminders"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE session_id = ?", table))
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_expired_sessions(&self, default_expiry_days: i64, warning_days: Option<i64>, now: DateTime<Utc>) -> StoreResult<u64> {
+        // Sessions without their own expiry use the server default; pinned sessions never expire.
+        // Sessions with notification emails are kept until warning_days after a warning that went
+        // out after their last access, or at most warning_days past their expiry.
+        let result = sqlx::query(
+            r#"
+            DELETE FROM sessions
+            WHERE pinned = 0
+              AND julianday(last_accessed_at) + COALESCE(expiry_days, ?) < julianday(?)
+              AND (
+                ? IS NULL
+                OR COALESCE(notification_emails, '[]') = '[]'
+                OR (
+                  expiry_warning_sent_at IS NOT NULL
+                  AND expiry_warning_sent_at >= last_accessed_at
+                  AND julianday(expiry_warning_sent_at) + ? <= julianday(?)
+                )
+                OR julianday(last_accessed_at) + COALESCE(expiry_days, ?) + ? <= julianday(?)
+              )
+            "#
+        )
+            .bind(default_expiry_days)
+            .bind(now)
+            .bind(warning_days)
+            .bind(warning_days)
+            .bind(now)
+            .bind(default_expiry_days)
+            .bind(warning_days)
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        for table in ["participant_links", "sync_operations", "reminder_schedules", "payment_reminders"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE session_id NOT IN (SELECT id FROM sessions)", table))
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(result.rows_affected())
+    }
+
+    async fn sessions_due_for_warning(&self, default_expiry_days: i64, warning_days: i64, now: DateTime<Utc>) -> StoreResult<Vec<DbSession>> {
+        sqlx::query_as(
+            r#"
+            SELECT * FROM sessions
+            WHERE pinned = 0
+              AND COALESCE(notification_emails, '[]') != '[]'
+              AND (expiry_warning_sent_at IS NULL OR expiry_warning_sent_at < last_accessed_at)
+              AND julianday(last_accessed_at) + COALESCE(expiry_days, ?) - ? < julianday(?)
+            "#
+        )
+            .bind(default_expiry_days)
+            .bind(warning_days)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn sessions_owned_by(&self, user_id: &str) -> StoreResult<Vec<DbSession>> {
+        sqlx::query_as("SELECT * FROM sessions WHERE owner_user_id = ? ORDER BY last_accessed_at DESC")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn all_sessions(&self) -> StoreResult<Vec<DbSession>> {
+        sqlx::query_as("SELECT * FROM sessions ORDER BY last_accessed_at DESC")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn ensure_participant_links(&self, session_id: &str, names: &[&str], at: DateTime<Utc>) -> StoreResult<()> {
+        for name in names {
+            sqlx::query("INSERT OR IGNORE INTO participant_links (token, session_id, name, created_at) VALUES (?, ?, ?, ?)")
+                .bind(Uuid::new_v4().to_string())
+                .bind(session_id)
+                .bind(name)
+                .bind(at)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn participant_links_for_session(&self, session_id: &str) -> StoreResult<Vec<DbParticipantLink>> {
+        sqlx::query_as("SELECT * FROM participant_links WHERE session_id = ? ORDER BY name")
+            .bind(session_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn participant_links_for_user(&self, user_id: &str) -> StoreResult<Vec<DbParticipantLink>> {
+        sqlx::query_as("SELECT * FROM participant_links WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_participant_link(&self, token: &str) -> StoreResult<Option<DbParticipantLink>> {
+        sqlx::query_as("SELECT * FROM participant_links WHERE token = ?")
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn mark_participant_link_claimed(&self, token: &str, at: DateTime<Utc>) -> StoreResult<()> {
+        sqlx::query("UPDATE participant_links SET claimed_at = ? WHERE token = ? AND claimed_at IS NULL")
+            .bind(at)
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_participant_link_user(&self, token: &str, user_id: &str) -> StoreResult<()> {
+        sqlx::query("UPDATE participant_links SET user_id = ? WHERE token = ?")
+            .bind(user_id)
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_participant_link_email(&self, session_id: &str, name: &str, email: Option<&str>) -> StoreResult<()> {
+        sqlx::query("UPDATE participant_links SET email = ? WHERE session_id = ? AND name = ?")
+            .bind(email)
+            .bind(session_id)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_participant_link_language(&self, session_id: &str, name: &str, language: &str) -> StoreResult<()> {
+        sqlx::query("UPDATE participant_links SET language = ? WHERE session_id = ? AND name = ?")
+            .bind(language)
+            .bind(session_id)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn sync_operations(&self, session_id: &str) -> StoreResult<Vec<DbSyncOperation>> {
+        sqlx::query_as("SELECT * FROM sync_operations WHERE session_id = ?")
+            .bind(session_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn insert_sync_operations(&self, operations: &[DbSyncOperation]) -> StoreResult<()> {
+        for op in operations {
+            sqlx::query("INSERT OR IGNORE INTO sync_operations (session_id, op_id, target, client_timestamp, applied_at) VALUES (?, ?, ?, ?, ?)")
+                .bind(&op.session_id)
+                .bind(&op.op_id)
+                .bind(&op.target)
+                .bind(op.client_timestamp)
+                .bind(op.applied_at)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_archive(&self, archive: &DbArchive) -> StoreResult<()> {
+        sqlx::query(
+            "INSERT INTO archives (id, session_id, edit_secret, owner_token, group_id, title, people, fund_amount, tip_percentage, include_sponsor, calculation, archived_at, currency) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&archive.id)
+        .bind(&archive.session_id)
+        .bind(&archive.edit_secret)
+        .bind(&archive.owner_token)
+        .bind(&archive.group_id)
+        .bind(&archive.title)
+        .bind(&archive.people)
+        .bind(archive.fund_amount)
+        .bind(archive.tip_percentage)
+        .bind(archive.include_sponsor)
+        .bind(&archive.calculation)
+        .bind(archive.archived_at)
+        .bind(&archive.currency)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_archive(&self, id: &str) -> StoreResult<Option<DbArchive>> {
+        sqlx::query_as("SELECT * FROM archives WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn archives_for_group(&self, group_id: &str) -> StoreResult<Vec<DbArchive>> {
+        sqlx::query_as("SELECT * FROM archives WHERE group_id = ? ORDER BY archived_at DESC")
+            .bind(group_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn archives_for_owner(&self, owner_token: &str) -> StoreResult<Vec<DbArchive>> {
+        sqlx::query_as("SELECT * FROM archives WHERE owner_token = ? ORDER BY archived_at DESC")
+            .bind(owner_token)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_archive(&self, id: &str) -> StoreResult<()> {
+        sqlx::query("DELETE FROM archives WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_template(&self, template: &DbTemplate) -> StoreResult<()> {
+        sqlx::query("INSERT INTO templates (id, owner_token, name, body, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(&template.id)
+            .bind(&template.owner_token)
+            .bind(&template.name)
+            .bind(&template.body)
+            .bind(template.created_at)
+            .bind(template.updated_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_template(&self, id: &str) -> StoreResult<Option<DbTemplate>> {
+        sqlx::query_as("SELECT * FROM templates WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn templates_for_owner(&self, owner_token: &str) -> StoreResult<Vec<DbTemplate>> {
+        sqlx::query_as("SELECT * FROM templates WHERE owner_token = ? ORDER BY name")
+            .bind(owner_token)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn update_template(&self, template: &DbTemplate) -> StoreResult<()> {
+        sqlx::query("UPDATE templates SET name = ?, body = ?, updated_at = ? WHERE id = ?")
+            .bind(&template.name)
+            .bind(&template.body)
+            .bind(template.updated_at)
+            .bind(&template.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_template(&self, id: &str) -> StoreResult<()> {
+        sqlx::query("DELETE FROM templates WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_group(&self, group: &DbGroup) -> StoreResult<()> {
+        sqlx::query("INSERT INTO groups (id, edit_secret, name, members, created_at, owner_user_id) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(&group.id)
+            .bind(&group.edit_secret)
+            .bind(&group.name)
+            .bind(&group.members)
+            .bind(group.created_at)
+            .bind(&group.owner_user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_group(&self, id: &str) -> StoreResult<Option<DbGroup>> {
+        sqlx::query_as("SELECT * FROM groups WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn update_group(&self, group: &DbGroup) -> StoreResult<()> {
+        sqlx::query("UPDATE groups SET name = ?, members = ? WHERE id = ?")
+            .bind(&group.name)
+            .bind(&group.members)
+            .bind(&group.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_group_owner(&self, id: &str, edit_secret: &str, user_id: &str) -> StoreResult<bool> {
+        let result = sqlx::query("UPDATE groups SET owner_user_id = ? WHERE id = ? AND edit_secret = ?")
+            .bind(user_id)
+            .bind(id)
+            .bind(edit_secret)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn groups_for_user(&self, user_id: &str) -> StoreResult<Vec<DbGroup>> {
+        sqlx::query_as(
+            r#"
+            SELECT * FROM groups
+            WHERE owner_user_id = ?
+               OR id IN (
+                   SELECT g.group_id FROM group_sessions g
+                   JOIN participant_links p ON p.session_id = g.session_id
+                   WHERE p.user_id = ?
+               )
+            ORDER BY name
+            "#
+        )
+            .bind(user_id)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn upsert_group_session(&self, snapshot: &DbGroupSession) -> StoreResult<()> {
+        sqlx::query("INSERT INTO group_sessions (group_id, session_id, balances, added_at, updated_at) VALUES (?, ?, ?, ?, ?) ON CONFLICT (group_id, session_id) DO UPDATE SET balances = excluded.balances, updated_at = excluded.updated_at")
+            .bind(&snapshot.group_id)
+            .bind(&snapshot.session_id)
+            .bind(&snapshot.balances)
+            .bind(snapshot.added_at)
+            .bind(snapshot.updated_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_group_session(&self, group_id: &str, session_id: &str) -> StoreResult<bool> {
+        let result = sqlx::query("DELETE FROM group_sessions WHERE group_id = ? AND session_id = ?")
+            .bind(group_id)
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn group_sessions(&self, group_id: &str) -> StoreResult<Vec<DbGroupSession>> {
+        sqlx::query_as("SELECT * FROM group_sessions WHERE group_id = ? ORDER BY added_at")
+            .bind(group_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn active_group_session_ids(&self, group_id: &str) -> StoreResult<Vec<String>> {
+        let rows: Vec<(String,)> = sqlx::query_as("SELECT s.id FROM sessions s JOIN group_sessions g ON g.session_id = s.id WHERE g.group_id = ?")
+            .bind(group_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(|(id,)| id).collect())
+    }
+
+    async fn refresh_group_snapshots(&self, session_id: &str, balances: &str, at: DateTime<Utc>) -> StoreResult<()> {
+        sqlx::query("UPDATE group_sessions SET balances = ?, updated_at = ? WHERE session_id = ?")
+            .bind(balances)
+            .bind(at)
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_login_token(&self, login: &DbLoginToken) -> StoreResult<()> {
+        sqlx::query("INSERT INTO login_tokens (token, email, created_at, expires_at) VALUES (?, ?, ?, ?)")
+            .bind(&login.token)
+            .bind(&login.email)
+            .bind(login.created_at)
+            .bind(login.expires_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn consume_login_token(&self, token: &str, now: DateTime<Utc>) -> StoreResult<Option<DbLoginToken>> {
+        // Mark the token used in the same statement that checks it, so it only works once
+        let result = sqlx::query("UPDATE login_tokens SET used_at = ? WHERE token = ? AND used_at IS NULL AND expires_at > ?")
+            .bind(now)
+            .bind(token)
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        sqlx::query_as("SELECT * FROM login_tokens WHERE token = ?")
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn find_or_create_user(&self, email: &str, at: DateTime<Utc>) -> StoreResult<DbUser> {
+        sqlx::query("INSERT OR IGNORE INTO users (id, email, created_at) VALUES (?, ?, ?)")
+            .bind(Uuid::new_v4().to_string())
+            .bind(email)
+            .bind(at)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query_as("SELECT * FROM users WHERE email = ?")
+            .bind(email)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn insert_access_token(&self, token: &str, user_id: &str, created_at: DateTime<Utc>, expires_at: DateTime<Utc>) -> StoreResult<()> {
+        sqlx::query("INSERT INTO access_tokens (token, user_id, created_at, expires_at) VALUES (?, ?, ?, ?)")
+            .bind(token)
+            .bind(user_id)
+            .bind(created_at)
+            .bind(expires_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_access_token(&self, token: &str) -> StoreResult<()> {
+        sqlx::query("DELETE FROM access_tokens WHERE token = ?")
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn user_for_access_token(&self, token: &str, now: DateTime<Utc>) -> StoreResult<Option<DbUser>> {
+        sqlx::query_as("SELECT u.* FROM users u JOIN access_tokens t ON t.user_id = u.id WHERE t.token = ? AND t.expires_at > ?")
+            .bind(token)
+            .bind(now)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn delete_expired_tokens(&self, now: DateTime<Utc>) -> StoreResult<()> {
+        for table in ["login_tokens", "access_tokens"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE expires_at < ?", table))
+                .bind(now)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn get_idempotency_record(&self, key: &str) -> StoreResult<Option<DbIdempotencyRecord>> {
+        sqlx::query_as("SELECT * FROM idempotency_keys WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn insert_idempotency_record(&self, record: &DbIdempotencyRecord) -> StoreResult<bool> {
+        let result = sqlx::query("INSERT OR IGNORE INTO idempotency_keys (key, request_hash, created_at, status, content_type, body) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(&record.key)
+            .bind(&record.request_hash)
+            .bind(record.created_at)
+            .bind(record.status)
+            .bind(&record.content_type)
+            .bind(&record.body)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn complete_idempotency_record(&self, key: &str, status: i64, content_type: Option<&str>, body: &str) -> StoreResult<()> {
+        sqlx::query("UPDATE idempotency_keys SET status = ?, content_type = ?, body = ? WHERE key = ?")
+            .bind(status)
+            .bind(content_type)
+            .bind(body)
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_idempotency_record(&self, key: &str) -> StoreResult<()> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE key = ?")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_idempotency_records_before(&self, cutoff: DateTime<Utc>) -> StoreResult<u64> {
+        let result = sqlx::query("DELETE FROM idempotency_keys WHERE created_at < ?")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn increment_daily_usage(&self, day: NaiveDate, subject: &str) -> StoreResult<i64> {
+        sqlx::query_scalar(
+            "INSERT INTO daily_usage (day, subject, count) VALUES (?, ?, 1) ON CONFLICT (day, subject) DO UPDATE SET count = daily_usage.count + 1 RETURNING count"
+        )
+            .bind(day)
+            .bind(subject)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn get_daily_usage(&self, day: NaiveDate, subject: &str) -> StoreResult<i64> {
+        let count: Option<i64> = sqlx::query_scalar("SELECT count FROM daily_usage WHERE day = ? AND subject = ?")
+            .bind(day)
+            .bind(subject)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(count.unwrap_or(0))
+    }
+
+    async fn daily_usage(&self, day: NaiveDate) -> StoreResult<Vec<DbDailyUsage>> {
+        sqlx::query_as("SELECT * FROM daily_usage WHERE day = ? ORDER BY count DESC, subject")
+            .bind(day)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_daily_usage_before(&self, day: NaiveDate) -> StoreResult<()> {
+        sqlx::query("DELETE FROM daily_usage WHERE day < ?")
+            .bind(day)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_rate_limit_exemption(&self, exemption: &DbRateLimitExemption) -> StoreResult<()> {
+        sqlx::query("INSERT INTO rate_limit_exemptions (subject, note, created_at) VALUES (?, ?, ?) ON CONFLICT (subject) DO UPDATE SET note = excluded.note")
+            .bind(&exemption.subject)
+            .bind(&exemption.note)
+            .bind(exemption.created_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_rate_limit_exemption(&self, subject: &str) -> StoreResult<Option<DbRateLimitExemption>> {
+        sqlx::query_as("SELECT * FROM rate_limit_exemptions WHERE subject = ?")
+            .bind(subject)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn rate_limit_exemptions(&self) -> StoreResult<Vec<DbRateLimitExemption>> {
+        sqlx::query_as("SELECT * FROM rate_limit_exemptions ORDER BY subject")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_rate_limit_exemption(&self, subject: &str) -> StoreResult<bool> {
+        let result = sqlx::query("DELETE FROM rate_limit_exemptions WHERE subject = ?")
+            .bind(subject)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_reminder_schedule(&self, schedule: &DbReminderSchedule) -> StoreResult<()> {
+        sqlx::query(
+            "INSERT INTO reminder_schedules (session_id, first_after_days, repeat_days, created_at) VALUES (?, ?, ?, ?) ON CONFLICT (session_id) DO UPDATE SET first_after_days = excluded.first_after_days, repeat_days = excluded.repeat_days"
+        )
+            .bind(&schedule.session_id)
+            .bind(schedule.first_after_days)
+            .bind(schedule.repeat_days)
+            .bind(schedule.created_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_reminder_schedule(&self, session_id: &str) -> StoreResult<Option<DbReminderSchedule>> {
+        sqlx::query_as("SELECT * FROM reminder_schedules WHERE session_id = ?")
+            .bind(session_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn reminder_schedules(&self) -> StoreResult<Vec<DbReminderSchedule>> {
+        sqlx::query_as("SELECT * FROM reminder_schedules")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_reminder_schedule(&self, session_id: &str) -> StoreResult<bool> {
+        let result = sqlx::query("DELETE FROM reminder_schedules WHERE session_id = ?")
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn payment_reminders_for_session(&self, session_id: &str) -> StoreResult<Vec<DbPaymentReminder>> {
+        sqlx::query_as("SELECT * FROM payment_reminders WHERE session_id = ? ORDER BY name")
+            .bind(session_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_payment_reminder(&self, token: &str) -> StoreResult<Option<DbPaymentReminder>> {
+        sqlx::query_as("SELECT * FROM payment_reminders WHERE token = ?")
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn record_payment_reminder(&self, reminder: &DbPaymentReminder) -> StoreResult<()> {
+        sqlx::query(
+            "INSERT INTO payment_reminders (token, session_id, name, amount, transfers, reminders_sent, last_sent_at, paid_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT (session_id, name) DO UPDATE SET amount = excluded.amount, transfers = excluded.transfers, reminders_sent = excluded.reminders_sent, last_sent_at = excluded.last_sent_at, paid_at = CASE WHEN payment_reminders.paid_at > excluded.last_sent_at THEN payment_reminders.paid_at ELSE excluded.paid_at END"
+        )
+            .bind(&reminder.token)
+            .bind(&reminder.session_id)
+            .bind(&reminder.name)
+            .bind(reminder.amount)
+            .bind(&reminder.transfers)
+            .bind(reminder.reminders_sent)
+            .bind(reminder.last_sent_at)
+            .bind(reminder.paid_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn mark_payment_paid(&self, token: &str, at: DateTime<Utc>) -> StoreResult<bool> {
+        let result = sqlx::query("UPDATE payment_reminders SET paid_at = ? WHERE token = ? AND paid_at IS NULL")
+            .bind(at)
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn vacuum(&self) -> StoreResult<()> {
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        Ok(())
+    }
+}
@@ -1,7 +1,23 @@
+#[allow(clippy::module_inception)]
 #[cfg(test)]
 mod tests {
+    use crate::admin::{self, matches_search};
+    use crate::calc::parse_csv;
+    use crate::error::{self, AppError};
+    use crate::import::import_json;
+    use crate::openapi::ApiDoc;
+    use crate::rate_limit::{Limits, RateLimiter};
+    use crate::report::{ReportFormat, SettlementReport};
     use crate::models::*;
-    use crate::calculate_split_internal;
+    use crate::store::{run_migrations, MemoryStore, PostgresStore, SessionStore, SqliteStore};
+    use crate::{app, apply_sync_operations, calculate_split, cleanup_expired_sessions, create_session, current_user, delete_session, get_session, instantiate_template, owns_archive, session_expires_at, sync_session, update_session, update_session_expiry};
+    use axum::body::Body;
+    use axum::extract::{Path, Query, State};
+    use axum::http::{HeaderMap, Request, StatusCode};
+    use axum::response::{IntoResponse, Response};
+    use axum::Json;
+    use std::sync::Arc;
+    use utoipa::OpenApi;
 
     fn create_person(
         id: u64,
@@ -26,550 +42,1258 @@ mod tests {
     }
 
     #[test]
-    fn test_simple_equal_split() {
-        let people = vec![
-            create_person(1, "Alice", 100.0, 1, 10.0, None),
-            create_person(2, "Bob", 0.0, 1, 0.0, None),
-        ];
-
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
+    fn test_session_expires_at() {
+        let last_accessed_at = chrono::Utc::now();
+        let mut session = DbSession {
+            id: "s1".to_string(),
+            edit_secret: "secret".to_string(),
+            people: "[]".to_string(),
+            created_at: last_accessed_at,
+            last_accessed_at,
             fund_amount: 0.0,
             tip_percentage: 0.0,
+            include_sponsor: true,
+            expiry_days: None,
+            pinned: false,
+            notification_emails: "[]".to_string(),
+            expiry_warning_sent_at: None,
+            extend_token: None,
+            currency: None,
+            owner_user_id: None,
         };
 
-        let response = calculate_split_internal(request);
+        // Falls back to the server default
+        assert_eq!(session_expires_at(&session, 7), Some(last_accessed_at + chrono::Duration::days(7)));
 
-        assert_eq!(response.total_spent, 110.0);
-        assert_eq!(response.num_participants, 2);
-        assert_eq!(response.per_person_share, 55.0);
+        // Per-session override wins
+        session.expiry_days = Some(30);
+        assert_eq!(session_expires_at(&session, 7), Some(last_accessed_at + chrono::Duration::days(30)));
 
-        // Alice should receive 55.0 (paid 110, should pay 55)
-        // Bob should pay 55.0 (paid 0, should pay 55)
-        let alice = response.settlements.iter().find(|s| s.name == "Alice").unwrap();
-        let bob = response.settlements.iter().find(|s| s.name == "Bob").unwrap();
+        // A stored expiry too far out to represent is treated as never, not a panic
+        session.expiry_days = Some(100_000_000);
+        assert_eq!(session_expires_at(&session, 7), None);
 
-        assert_eq!(alice.balance, 55.0);
-        assert_eq!(alice.settlement_type, "receive");
-        assert_eq!(bob.balance, -55.0);
-        assert_eq!(bob.settlement_type, "pay");
+        // Pinned sessions never expire
+        session.pinned = true;
+        assert_eq!(session_expires_at(&session, 7), None);
     }
 
-    #[test]
-    fn test_reimbursement_paid_by_other() {
-        // Son pays $1000, marked "paid by Tuan"
-        // Son should receive $1000, Tuan should pay $1000
-        let people = vec![
-            create_person(1, "Son", 1000.0, 1, 0.0, Some("Tuan".to_string())),
-            create_person(2, "Tuan", 0.0, 1, 0.0, None),
-        ];
-
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 0.0,
-            tip_percentage: 0.0,
-        };
-
-        let response = calculate_split_internal(request);
-
-        // Debug output
-        println!("Total spent: {}", response.total_spent);
-        println!("Amount to share: {}", response.amount_to_share);
-        
-        let son = response.settlements.iter().find(|s| s.name == "Son").unwrap();
-        let tuan = response.settlements.iter().find(|s| s.name == "Tuan").unwrap();
-        
-        println!("Son: amount_spent={}, balance={}", son.amount_spent, son.balance);
-        println!("Tuan: amount_spent={}, balance={}", tuan.amount_spent, tuan.balance);
-
-        // No shared expenses since it's all reimbursement
-        assert_eq!(response.amount_to_share, 0.0);
-        assert_eq!(response.per_person_share, 0.0);
-
-        // Son should receive 1000 (will_receive_from_others = 1000, owes = 0)
-        assert_eq!(son.balance, 1000.0);
-        assert_eq!(son.settlement_type, "receive");
-
-        // Tuan should pay 1000 (will_receive = 0, owes_to_others = 1000)
-        assert_eq!(tuan.balance, -1000.0);
-        assert_eq!(tuan.settlement_type, "pay");
+    // Every backend, so each storage test runs against all of them. Postgres is only
+    // included when TEST_DATABASE_URL points at a server, e.g.
+    // TEST_DATABASE_URL=postgres://postgres@localhost/split_bills_test cargo test
+    async fn stores() -> Vec<Arc<dyn SessionStore>> {
+        // A single connection, since every SQLite in-memory connection is its own database
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        run_migrations(&pool).await;
+
+        let mut stores: Vec<Arc<dyn SessionStore>> = vec![Arc::new(MemoryStore::new()), Arc::new(SqliteStore::new(pool))];
+        if let Ok(url) = std::env::var("TEST_DATABASE_URL") {
+            stores.push(Arc::new(postgres_store(&url).await));
+        }
+        stores
     }
 
-    #[test]
-    fn test_private_expense_paid_by_self() {
-        // Son pays $1000 marked "paid by Son" (private expense)
-        // Bob pays $500 (shared expense)
-        let people = vec![
-            create_person(1, "Son", 1000.0, 1, 0.0, Some("Son".to_string())),
-            create_person(2, "Bob", 500.0, 1, 0.0, None),
-        ];
-
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 0.0,
-            tip_percentage: 0.0,
-        };
-
-        let response = calculate_split_internal(request);
-
-        // Only Bob's $500 is shared (Son's is private)
-        assert_eq!(response.amount_to_share, 500.0);
-        assert_eq!(response.per_person_share, 250.0);
+    // Tests run in parallel, so each one gets its own schema
+    async fn postgres_store(url: &str) -> PostgresStore {
+        let options: sqlx::postgres::PgConnectOptions = url.parse().unwrap();
+        let schema = format!("test_{}", uuid::Uuid::new_v4().simple());
 
-        let son = response.settlements.iter().find(|s| s.name == "Son").unwrap();
-        let bob = response.settlements.iter().find(|s| s.name == "Bob").unwrap();
+        let mut conn = sqlx::ConnectOptions::connect(&options).await.unwrap();
+        sqlx::query(&format!("CREATE SCHEMA {}", schema)).execute(&mut conn).await.unwrap();
 
-        // Son pays his share (250) + private expense (1000) = -250
-        assert_eq!(son.balance, -250.0);
-        assert_eq!(son.settlement_type, "pay");
-
-        // Bob receives: paid 500, should pay 250 = +250
-        assert_eq!(bob.balance, 250.0);
-        assert_eq!(bob.settlement_type, "receive");
+        PostgresStore::connect(options.options([("search_path", schema.as_str())])).await.unwrap()
     }
 
-    #[test]
-    fn test_complex_scenario_multiple_reimbursements() {
-        // Real scenario from user:
-        // Son pays multiple expenses, some reimbursed by others
-        let people = vec![
-            create_person(1, "Son", 990.0, 1, 99.0, Some("Tuan".to_string())),
-            create_person(2, "Son", 990.0, 1, 99.0, Some("Doffy".to_string())),
-            create_person(3, "Son", 500.0, 1, 50.0, Some("Doffy".to_string())),
-            create_person(4, "Son", 990.0, 2, 99.0, Some("Dac".to_string())),
-            create_person(5, "Son", 500.0, 1, 50.0, Some("Tuan".to_string())),
-            create_person(6, "Dac", 0.0, 1, 0.0, None),
-            create_person(7, "Doffy", 0.0, 1, 0.0, None),
-            create_person(8, "Tuan", 0.0, 1, 0.0, None),
-        ];
+    fn app_state(store: Arc<dyn SessionStore>) -> crate::models::AppState {
+        AppState {
+            store,
+            session_expiry_days: 7,
+            limiter: Default::default(),
+            email: None,
+        }
+    }
 
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
+    async fn insert_session(store: &dyn SessionStore, id: &str, days_idle: i64, emails: &str, warned: bool) {
+        let last_accessed_at = chrono::Utc::now() - chrono::Duration::days(days_idle);
+        store.insert_session(&DbSession {
+            id: id.to_string(),
+            edit_secret: "secret".to_string(),
+            people: "[]".to_string(),
+            created_at: last_accessed_at,
+            last_accessed_at,
             fund_amount: 0.0,
             tip_percentage: 0.0,
-        };
-
-        let response = calculate_split_internal(request);
-
-        // All expenses are reimbursements, no shared pool
-        assert_eq!(response.amount_to_share, 0.0);
-
-        let son = response.settlements.iter().find(|s| s.name == "Son").unwrap();
-        let dac = response.settlements.iter().find(|s| s.name == "Dac").unwrap();
-        let doffy = response.settlements.iter().find(|s| s.name == "Doffy").unwrap();
-        let tuan = response.settlements.iter().find(|s| s.name == "Tuan").unwrap();
-
-        // Calculate expected values
-        let tuan_owes = 1089.0 + 550.0; // 1639
-        let doffy_owes = 1089.0 + 550.0; // 1639
-        let dac_owes = 2079.0; // 1980 + 99
-
-        // Son should receive total reimbursements
-        assert_eq!(son.balance, tuan_owes + doffy_owes + dac_owes);
-        assert_eq!(son.settlement_type, "receive");
+            include_sponsor: true,
+            expiry_days: None,
+            pinned: false,
+            notification_emails: emails.to_string(),
+            expiry_warning_sent_at: warned.then(|| last_accessed_at + chrono::Duration::hours(1)),
+            extend_token: None,
+            currency: None,
+            owner_user_id: None,
+        }).await.unwrap();
+    }
 
-        // Each person should pay what they owe
-        assert_eq!(tuan.balance, -tuan_owes);
-        assert_eq!(tuan.settlement_type, "pay");
-        assert_eq!(doffy.balance, -doffy_owes);
-        assert_eq!(doffy.settlement_type, "pay");
-        assert_eq!(dac.balance, -dac_owes);
-        assert_eq!(dac.settlement_type, "pay");
+    async fn session_ids(store: &dyn SessionStore) -> Vec<String> {
+        let mut ids = Vec::new();
+        for id in ["abandoned", "expired", "fresh", "late", "pinned", "unwarned", "warned"] {
+            if store.get_session(id).await.unwrap().is_some() {
+                ids.push(id.to_string());
+            }
+        }
+        ids
+    }
 
-        // Total should balance to zero
-        let total_balance: f64 = response.settlements.iter().map(|s| s.balance).sum();
-        assert!(total_balance.abs() < 0.01, "Total balance should be near zero, got {}", total_balance);
+    #[tokio::test]
+    async fn test_cleanup_waits_for_expiry_warning() {
+        for store in stores().await {
+            let store = store.as_ref();
+            let now = chrono::Utc::now();
+            insert_session(store, "fresh", 1, "[]", false).await;
+            insert_session(store, "expired", 10, "[]", false).await;
+            insert_session(store, "pinned", 10, "[]", false).await;
+            // Expired a day ago, so still inside the two days a warning can hold it back
+            insert_session(store, "unwarned", 8, r#"["a@example.com"]"#, false).await;
+            // Two days past that, a warning that never went out no longer holds it
+            insert_session(store, "abandoned", 10, r#"["a@example.com"]"#, false).await;
+            insert_session(store, "warned", 10, r#"["a@example.com"]"#, true).await;
+            // Warned only an hour ago, after it had expired
+            insert_session(store, "late", 8, r#"["a@example.com"]"#, false).await;
+            store.record_expiry_warning("late", "token", now - chrono::Duration::hours(1)).await.unwrap();
+
+            let mut pinned = store.get_session("pinned").await.unwrap().unwrap();
+            pinned.pinned = true;
+            store.update_session_expiry(&pinned).await.unwrap();
+
+            // Pinned and fresh sessions aren't close to expiring
+            let due = store.sessions_due_for_warning(7, 2, now).await.unwrap();
+            let mut due: Vec<_> = due.iter().map(|s| s.id.as_str()).collect();
+            due.sort();
+            assert_eq!(due, vec!["abandoned", "unwarned"]);
+
+            cleanup_expired_sessions(store, 7, Some(2)).await;
+            assert_eq!(session_ids(store).await, vec!["fresh", "late", "pinned", "unwarned"]);
+
+            // The late warning still holds for at most two days past expiry
+            let deleted = store.delete_expired_sessions(7, Some(2), now + chrono::Duration::hours(23)).await.unwrap();
+            assert_eq!(deleted, 0);
+            store.delete_expired_sessions(7, Some(2), now + chrono::Duration::hours(25)).await.unwrap();
+            assert_eq!(session_ids(store).await, vec!["fresh", "pinned"]);
+
+            // Without an email transport nothing can be warned, so expiry applies as usual
+            insert_session(store, "unwarned", 8, r#"["a@example.com"]"#, false).await;
+            cleanup_expired_sessions(store, 7, None).await;
+            assert_eq!(session_ids(store).await, vec!["fresh", "pinned"]);
+        }
     }
 
-    #[test]
-    fn test_quantity_multiplier() {
-        // Test that quantity multiplies the amount correctly
-        let people = vec![
-            create_person(1, "Alice", 100.0, 2, 10.0, None), // $200 + $10 tip
-            create_person(2, "Bob", 0.0, 1, 0.0, None),
-        ];
+    struct FailingTransport;
 
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 0.0,
-            tip_percentage: 0.0,
-        };
+    #[async_trait::async_trait]
+    impl crate::email::EmailTransport for FailingTransport {
+        async fn send(&self, _message: &crate::email::EmailMessage) -> crate::email::EmailResult<()> {
+            Err("mailbox unavailable".into())
+        }
+    }
 
-        let response = calculate_split_internal(request);
+    #[tokio::test]
+    async fn test_expiry_warnings() {
+        use crate::send_expiry_warnings;
+        for store in stores().await {
+            let store = store.as_ref();
+            let now = chrono::Utc::now();
+
+            // A warning that can't be sent isn't recorded, and doesn't keep the session
+            // beyond two days past its expiry
+            insert_session(store, "unwarned", 8, r#"["a@example.com"]"#, false).await;
+            let failing = crate::email::EmailService::new(Arc::new(FailingTransport), "test@example.com".to_string());
+            send_expiry_warnings(store, &failing, 7, 2).await;
+            assert!(store.get_session("unwarned").await.unwrap().unwrap().expiry_warning_sent_at.is_none());
+            store.delete_expired_sessions(7, Some(2), now + chrono::Duration::hours(23)).await.unwrap();
+            assert_eq!(session_ids(store).await, vec!["unwarned"]);
+            store.delete_expired_sessions(7, Some(2), now + chrono::Duration::hours(25)).await.unwrap();
+            assert!(session_ids(store).await.is_empty());
+
+            // A late warning gives the date the session will actually go, not its expiry
+            insert_session(store, "late", 8, r#"["a@example.com"]"#, false).await;
+            let session = store.get_session("late").await.unwrap().unwrap();
+            let outbox = std::env::temp_dir().join(format!("split-bills-test-{}", uuid::Uuid::new_v4()));
+            let email_service = crate::email::EmailService::new(Arc::new(crate::email::FileTransport::new(&outbox)), "test@example.com".to_string());
+            send_expiry_warnings(store, &email_service, 7, 2).await;
+            let files: Vec<_> = std::fs::read_dir(&outbox).unwrap().map(|entry| entry.unwrap().path()).collect();
+            let message: serde_json::Value = serde_json::from_slice(&std::fs::read(&files[0]).unwrap()).unwrap();
+            let deleted_at = session.last_accessed_at + chrono::Duration::days(9);
+            assert!(message["html"].as_str().unwrap().contains(&deleted_at.format("%Y-%m-%d %H:%M UTC").to_string()));
+            assert!(store.get_session("late").await.unwrap().unwrap().expiry_warning_sent_at.is_some());
+            let _ = std::fs::remove_dir_all(&outbox);
+        }
+    }
 
-        assert_eq!(response.total_spent, 210.0); // 100*2 + 10
-        assert_eq!(response.per_person_share, 105.0);
+    #[tokio::test]
+    async fn test_session_handlers() {
+        for store in stores().await {
+            let state = app_state(store);
+            let person = create_person(1, "Alice", 100.0, 1, 0.0, None);
+
+            let Json(created) = create_session(
+                State(state.clone()),
+                Query(CreateSessionQuery { template: None }),
+                HeaderMap::new(),
+                Some(Json(CreateSessionRequest {
+                    people: vec![person.clone()],
+                    fund_amount: 0.0,
+                    tip_percentage: 0.0,
+                    include_sponsor: true,
+                    expiry_days: None,
+                    pinned: false,
+                    currency: Some("VND".to_string()),
+                })),
+            ).await.unwrap();
+
+            let Json(session) = get_session(State(state.clone()), Path(created.id.clone())).await.unwrap();
+            assert_eq!(session.people.len(), 1);
+            assert_eq!(session.currency.as_deref(), Some("VND"));
+            assert!(session.expires_at.is_some());
+
+            let update = || Json(UpdateSessionRequest {
+                people: vec![person.clone(), create_person(2, "Bob", 50.0, 1, 0.0, None)],
+                fund_amount: 10.0,
+                tip_percentage: 0.0,
+                include_sponsor: true,
+                currency: None,
+            });
+
+            // Without the edit secret nothing changes
+            let mut wrong = HeaderMap::new();
+            wrong.insert("X-Edit-Secret", "nope".parse().unwrap());
+            let result = update_session(State(state.clone()), Path(created.id.clone()), wrong, update()).await;
+            assert_eq!(result.unwrap_err().status(), StatusCode::FORBIDDEN);
+
+            let mut headers = HeaderMap::new();
+            headers.insert("X-Edit-Secret", created.edit_secret.parse().unwrap());
+            let _ = update_session(State(state.clone()), Path(created.id.clone()), headers.clone(), update()).await.unwrap();
+
+            let Json(session) = get_session(State(state.clone()), Path(created.id.clone())).await.unwrap();
+            assert_eq!(session.people.len(), 2);
+            assert_eq!(session.fund_amount, 10.0);
+            // An update without a currency keeps the stored one
+            assert_eq!(session.currency.as_deref(), Some("VND"));
+
+            // Expiry is capped so the date stays representable
+            let expiry = |days| Json(SessionExpiryRequest { expiry_days: Some(days), pinned: false });
+            let result = update_session_expiry(State(state.clone()), Path(created.id.clone()), headers.clone(), expiry(100_000_000)).await;
+            assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+            let Json(expiry) = update_session_expiry(State(state.clone()), Path(created.id.clone()), headers.clone(), expiry(3650)).await.unwrap();
+            assert!(expiry.expires_at.is_some());
+            let result = create_session(
+                State(state.clone()),
+                Query(CreateSessionQuery { template: None }),
+                HeaderMap::new(),
+                Some(Json(CreateSessionRequest {
+                    people: vec![person.clone()],
+                    fund_amount: 0.0,
+                    tip_percentage: 0.0,
+                    include_sponsor: true,
+                    expiry_days: Some(100_000_000),
+                    pinned: false,
+                    currency: None,
+                })),
+            ).await;
+            assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+            delete_session(State(state.clone()), Path(created.id.clone()), headers).await.unwrap();
+            let result = get_session(State(state.clone()), Path(created.id.clone())).await;
+            assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+        }
     }
 
     #[test]
-    fn test_mixed_reimbursement_and_shared() {
-        // Mix of shared expenses and reimbursements
-        let people = vec![
-            create_person(1, "Alice", 100.0, 1, 0.0, None), // Shared
-            create_person(2, "Bob", 50.0, 1, 0.0, Some("Charlie".to_string())), // Charlie owes Bob
-            create_person(3, "Charlie", 0.0, 1, 0.0, None),
-        ];
-
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
+    fn test_archive_ownership() {
+        let archive = DbArchive {
+            id: "a1".to_string(),
+            session_id: "s1".to_string(),
+            edit_secret: "secret".to_string(),
+            owner_token: Some("owner".to_string()),
+            group_id: None,
+            title: String::new(),
+            people: "[]".to_string(),
             fund_amount: 0.0,
             tip_percentage: 0.0,
+            include_sponsor: true,
+            calculation: "{}".to_string(),
+            archived_at: chrono::Utc::now(),
+            currency: None,
         };
 
-        let response = calculate_split_internal(request);
-
-        println!("Total spent: {}", response.total_spent);
-        println!("Amount to share: {}", response.amount_to_share);
-
-        // Only Alice's $100 is shared
-        assert_eq!(response.amount_to_share, 100.0);
-        assert_eq!(response.per_person_share, 100.0 / 3.0);
-
-        let alice = response.settlements.iter().find(|s| s.name == "Alice").unwrap();
-        let bob = response.settlements.iter().find(|s| s.name == "Bob").unwrap();
-        let charlie = response.settlements.iter().find(|s| s.name == "Charlie").unwrap();
-
-        let share = 100.0 / 3.0;
-
-        // Alice: paid 100, should pay share
-        assert!((alice.balance - (100.0 - share)).abs() < 0.01);
-
-        // Bob: will receive 50 from Charlie, should pay share
-        assert!((bob.balance - (50.0 - share)).abs() < 0.01);
-
-        // Charlie: owes 50 to Bob, should pay share
-        assert!((charlie.balance - (-50.0 - share)).abs() < 0.01);
-    }
-
-    #[test]
-    fn test_global_tip_percentage() {
-        let people = vec![
-            create_person(1, "Alice", 100.0, 1, 0.0, None),
-            create_person(2, "Bob", 0.0, 1, 0.0, None),
-        ];
-
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 0.0,
-            tip_percentage: 10.0, // 10% global tip
+        let headers = |name: &'static str, value: &'static str| {
+            let mut headers = axum::http::HeaderMap::new();
+            headers.insert(name, axum::http::HeaderValue::from_static(value));
+            headers
         };
 
-        let response = calculate_split_internal(request);
-
-        // Total: 100 + 10% tip = 110
-        assert_eq!(response.total_spent, 100.0);
-        assert!((response.total_tip - 10.0).abs() < 0.01); // Use approximate equality for floating point
-        assert!((response.per_person_share - 55.0).abs() < 0.01); // Use approximate equality
+        assert!(owns_archive(&archive, &headers("X-Owner-Token", "owner")));
+        assert!(owns_archive(&archive, &headers("X-Edit-Secret", "secret")));
+        assert!(!owns_archive(&archive, &headers("X-Owner-Token", "someone-else")));
+        assert!(!owns_archive(&archive, &axum::http::HeaderMap::new()));
     }
 
-    #[test]
-    fn test_sponsor_expense() {
-        // Test sponsor functionality: one person sponsors for everyone
-        let mut alice = create_person(1, "Alice", 100.0, 1, 0.0, None);
-        alice.is_sponsor = true;
-        alice.sponsor_amount = 100.0;
-
-        let people = vec![
-            alice,
-            create_person(2, "Bob", 50.0, 1, 0.0, None),
-            create_person(3, "Charlie", 0.0, 1, 0.0, None),
-        ];
-
-        let request = CalculateRequest {
-            people,
-            include_sponsor: true,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 0.0,
-            tip_percentage: 0.0,
-        };
-
-        let response = calculate_split_internal(request);
-
-        assert_eq!(response.total_sponsored, 100.0);
-        // 50 should be shared (Bob's expense), 100 sponsored
-        assert_eq!(response.amount_to_share, 50.0);
-        
-        let alice = response.settlements.iter().find(|s| s.name == "Alice").unwrap();
-        // Alice sponsored 100, which reduces others' bills but not counted in her balance
-        assert!(alice.sponsor_cost > 0.0);
+    #[tokio::test]
+    async fn test_group_archives() {
+        use crate::{archive_session, create_group, list_archives};
+        for store in stores().await {
+            let state = app_state(store.clone());
+            insert_session(store.as_ref(), "trip", 0, "[]", false).await;
+            let Json(group) = create_group(
+                State(state.clone()),
+                HeaderMap::new(),
+                Json(GroupRequest { name: "Friends".to_string(), members: vec![] }),
+            ).await.unwrap();
+
+            let secret = |value: &str| {
+                let mut headers = HeaderMap::new();
+                headers.insert("X-Edit-Secret", value.parse().unwrap());
+                headers
+            };
+            let archive = |group_edit_secret: Option<&str>| Some(Json(ArchiveSessionRequest {
+                title: None,
+                group_id: Some(group.id.clone()),
+                group_edit_secret: group_edit_secret.map(str::to_string),
+            }));
+            let list = || Query(ListArchivesQuery { group_id: Some(group.id.clone()) });
+
+            // Filing into a group takes the group's secret as well as the session's
+            for group_edit_secret in [None, Some("nope"), Some("secret")] {
+                let result = archive_session(State(state.clone()), Path("trip".to_string()), secret("secret"), archive(group_edit_secret)).await;
+                assert_eq!(result.unwrap_err().status(), StatusCode::FORBIDDEN);
+            }
+            let result = archive_session(State(state.clone()), Path("trip".to_string()), secret("nope"), archive(Some(&group.edit_secret))).await;
+            assert_eq!(result.unwrap_err().status(), StatusCode::FORBIDDEN);
+            let _ = archive_session(State(state.clone()), Path("trip".to_string()), secret("secret"), archive(Some(&group.edit_secret))).await.unwrap();
+
+            // And so does listing them
+            let result = list_archives(State(state.clone()), HeaderMap::new(), list()).await;
+            assert_eq!(result.unwrap_err().status(), StatusCode::FORBIDDEN);
+            let result = list_archives(State(state.clone()), secret("secret"), list()).await;
+            assert_eq!(result.unwrap_err().status(), StatusCode::FORBIDDEN);
+            let Json(archives) = list_archives(State(state.clone()), secret(&group.edit_secret), list()).await.unwrap();
+            assert_eq!(archives.len(), 1);
+        }
     }
 
     #[test]
-    fn test_fund_amount() {
-        // Test with a fund pool that reduces everyone's share
-        let people = vec![
-            create_person(1, "Alice", 100.0, 1, 0.0, None),
-            create_person(2, "Bob", 0.0, 1, 0.0, None),
-        ];
-
-        let request = CalculateRequest {
-            people,
+    fn test_instantiate_template() {
+        let template = SessionTemplate {
+            name: "Badminton".to_string(),
+            roster: vec!["Alice".to_string(), "Bob".to_string(), "Carol".to_string()],
+            fund_amount: 0.0,
+            tip_percentage: 5.0,
             include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 20.0, // $20 fund reduces total
-            tip_percentage: 0.0,
+            receiver: Some("Bob".to_string()),
+            currency: Some("VND".to_string()),
+            expenses: vec![create_person(1, "Alice", 300000.0, 1, 0.0, None)],
         };
 
-        let response = calculate_split_internal(request);
+        let extra = vec![create_person(7, "Dave", 50000.0, 1, 0.0, None)];
+        let request = instantiate_template(&template, extra, 1000);
 
-        assert_eq!(response.fund_amount, 20.0);
-        // Amount to share = 100 - 20 = 80
-        assert_eq!(response.amount_to_share, 80.0);
-        assert_eq!(response.per_person_share, 40.0);
+        // Template line, body line, then placeholders for Bob and Carol
+        let names: Vec<&str> = request.people.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["Alice", "Dave", "Bob", "Carol"]);
+        let ids: Vec<u64> = request.people.iter().map(|p| p.id).collect();
+        assert_eq!(ids, vec![1000, 1001, 1002, 1003]);
 
-        let alice = response.settlements.iter().find(|s| s.name == "Alice").unwrap();
-        let bob = response.settlements.iter().find(|s| s.name == "Bob").unwrap();
+        let receivers: Vec<&str> = request.people.iter().filter(|p| p.is_receiver).map(|p| p.name.as_str()).collect();
+        assert_eq!(receivers, vec!["Bob"]);
 
-        // Alice paid 100, should pay 40 = +60
-        assert_eq!(alice.balance, 60.0);
-        // Bob paid 0, should pay 40 = -40
-        assert_eq!(bob.balance, -40.0);
+        assert_eq!(request.tip_percentage, 5.0);
+        assert!(!request.include_sponsor);
+        assert_eq!(request.currency.as_deref(), Some("VND"));
     }
 
-    #[test]
-    fn test_empty_people_list() {
-        let people = vec![];
-
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 0.0,
-            tip_percentage: 0.0,
-        };
+    #[tokio::test]
+    async fn test_current_user_from_bearer_token() {
+        for store in stores().await {
+            let store = store.as_ref();
+            let now = chrono::Utc::now();
+            let user = store.find_or_create_user("a@example.com", now).await.unwrap();
+            store.insert_access_token("valid", &user.id, now, now + chrono::Duration::days(1)).await.unwrap();
+            store.insert_access_token("stale", &user.id, now, now - chrono::Duration::days(1)).await.unwrap();
+
+            let headers_with = |value: &str| {
+                let mut headers = HeaderMap::new();
+                headers.insert(axum::http::header::AUTHORIZATION, value.parse().unwrap());
+                headers
+            };
+
+            let found = current_user(store, &headers_with("Bearer valid")).await.unwrap();
+            assert_eq!(found.map(|u| u.email), Some("a@example.com".to_string()));
+
+            // Anonymous, expired and unknown tokens all count as signed out
+            assert!(current_user(store, &HeaderMap::new()).await.unwrap().is_none());
+            assert!(current_user(store, &headers_with("Bearer stale")).await.unwrap().is_none());
+            assert!(current_user(store, &headers_with("Bearer nope")).await.unwrap().is_none());
+
+            // Signing in again finds the same account
+            assert_eq!(store.find_or_create_user("a@example.com", now).await.unwrap().id, user.id);
+        }
+    }
 
-        let response = calculate_split_internal(request);
+    #[tokio::test]
+    async fn test_import_legacy_sessions_and_history() {
+        let person = r#"{"id":1,"name":"An","description":"","amount_spent":10.0,"is_sponsor":false,"sponsor_amount":0.0}"#;
+        let legacy = format!(
+            r#"{{
+                "old-1": {{"people":[{person}],"edit_secret":"s1","created_at":"2025-01-01T00:00:00Z","last_accessed_at":"2025-01-02T00:00:00Z"}},
+                "old-2": {{"people":"broken","edit_secret":"s2","created_at":"2025-01-01T00:00:00Z"}}
+            }}"#
+        );
+        let history = format!(
+            r#"[
+                {{"timestamp":1735689600000,"people":[{person}],"fundAmount":5,"tipPercentage":10,"source":"local"}},
+                {{"timestamp":1735689600000,"people":[{person}],"source":"server","archiveId":"a"}}
+            ]"#
+        );
+
+        for store in stores().await {
+            let store = store.as_ref();
+            let now = chrono::Utc::now();
+
+            let report = import_json(store, &legacy, now).await.unwrap();
+            assert_eq!(report.imported.len(), 1);
+            assert_eq!(report.skipped.len(), 1);
+            assert_eq!(report.skipped[0].0, "old-2");
+
+            // The original id and secret keep old share links working
+            let session = store.get_session("old-1").await.unwrap().unwrap();
+            assert_eq!(session.edit_secret, "s1");
+            assert_eq!(session.created_at.to_rfc3339(), "2025-01-01T00:00:00+00:00");
+            assert_eq!(session.last_accessed_at.timestamp(), now.timestamp());
+            assert_eq!(store.participant_links_for_session("old-1").await.unwrap().len(), 1);
+
+            // Running it again skips what is already there
+            let report = import_json(store, &legacy, now).await.unwrap();
+            assert!(report.imported.is_empty());
+            assert_eq!(report.skipped.len(), 2);
+
+            let report = import_json(store, &history, now).await.unwrap();
+            assert_eq!(report.imported.len(), 1);
+            assert_eq!(report.skipped.len(), 1);
+            let session = store.get_session(&report.imported[0].id).await.unwrap().unwrap();
+            assert_eq!(session.fund_amount, 5.0);
+            assert_eq!(session.tip_percentage, 10.0);
+
+            assert!(import_json(store, "42", now).await.is_err());
+        }
+    }
 
-        assert_eq!(response.total_spent, 0.0);
-        assert_eq!(response.num_participants, 0);
-        assert_eq!(response.settlements.len(), 0);
+    #[tokio::test]
+    async fn test_admin_commands() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+        for store in stores().await {
+            let store = store.as_ref();
+            insert_session(store, "stale", 30, "[\"ops@example.com\"]", false).await;
+            insert_session(store, "fresh", 0, "[]", false).await;
+
+            assert_eq!(store.all_sessions().await.unwrap().iter().map(|s| s.id.as_str()).collect::<Vec<_>>(), ["fresh", "stale"]);
+            let stale = store.get_session("stale").await.unwrap().unwrap();
+            assert!(matches_search(&stale, "ops@"));
+            assert!(matches_search(&stale, "sta"));
+            assert!(!matches_search(&stale, "fresh"));
+
+            // Inspecting doesn't keep a session alive, extending does
+            admin::run(store, &args(&["show", "stale"]), 7).await.unwrap();
+            admin::run(store, &args(&["export"]), 7).await.unwrap();
+            assert_eq!(store.get_session("stale").await.unwrap().unwrap().last_accessed_at, stale.last_accessed_at);
+
+            admin::run(store, &args(&["extend", "stale", "14"]), 7).await.unwrap();
+            let extended = store.get_session("stale").await.unwrap().unwrap();
+            assert_eq!(extended.expiry_days, Some(14));
+            assert!(extended.last_accessed_at > stale.last_accessed_at);
+
+            admin::run(store, &args(&["delete", "stale"]), 7).await.unwrap();
+            assert!(store.get_session("stale").await.unwrap().is_none());
+
+            assert!(admin::run(store, &args(&["show", "stale"]), 7).await.is_err());
+            assert!(admin::run(store, &args(&["extend", "fresh", "0"]), 7).await.is_err());
+            assert!(admin::run(store, &args(&["extend", "fresh", "100000000"]), 7).await.is_err());
+            assert!(admin::run(store, &args(&["frobnicate"]), 7).await.is_err());
+            admin::run(store, &args(&["vacuum"]), 7).await.unwrap();
+        }
     }
 
     #[test]
-    fn test_single_person() {
-        let people = vec![
-            create_person(1, "Alice", 100.0, 1, 10.0, None),
-        ];
-
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 0.0,
-            tip_percentage: 0.0,
-        };
-
-        let response = calculate_split_internal(request);
-
-        assert_eq!(response.total_spent, 110.0);
-        assert_eq!(response.num_participants, 1);
-        assert_eq!(response.per_person_share, 110.0);
+    fn test_parse_csv_people() {
+        let csv = "name,description,amount,quantity,tip,sponsor,paid_by\n\
+                   An,\"Dinner, mains\",300,1,,,\n\
+                   Binh,Drinks,100,2,10,,An\n\
+                   Dung,,0,,,150,\n";
+        let people = parse_csv(csv).unwrap();
+
+        assert_eq!(people.len(), 3);
+        assert_eq!(people[0].description, "Dinner, mains");
+        assert_eq!(people[0].paid_by, None);
+        assert_eq!((people[1].quantity, people[1].tip), (2, 10.0));
+        assert_eq!(people[1].paid_by.as_deref(), Some("An"));
+        assert_eq!(people[2].quantity, 1);
+        assert!(people[2].is_sponsor);
+        assert_eq!(people[2].sponsor_amount, 150.0);
+
+        // Only name and amount are required, in any column order
+        let people = parse_csv("amount,name\n12.5,Chi\n").unwrap();
+        assert_eq!((people[0].name.as_str(), people[0].amount_spent), ("Chi", 12.5));
+
+        let error = parse_csv("name,amount\nAn,lots\n").unwrap_err();
+        assert!(error.starts_with("line 2"));
+    }
 
-        let alice = response.settlements.iter().find(|s| s.name == "Alice").unwrap();
-        // Alice pays for herself, balance should be 0
-        assert_eq!(alice.balance, 0.0);
-        assert_eq!(alice.settlement_type, "settled");
+    fn sync_op(op_id: &str, client_timestamp: i64, change: SyncChange) -> SyncOperation {
+        SyncOperation { op_id: op_id.to_string(), client_timestamp, change }
     }
 
     #[test]
-    fn test_receiver_flag() {
-        // Test is_receiver flag behavior
-        let mut alice = create_person(1, "Alice", 100.0, 1, 0.0, None);
-        alice.is_receiver = true;
-
-        let people = vec![
-            alice,
-            create_person(2, "Bob", 0.0, 1, 0.0, None),
+    fn test_sync_operations_converge() {
+        let now = chrono::Utc::now();
+        let phone_a = vec![
+            sync_op("a1", 10, SyncChange::EditExpense { expense: create_person(1, "Alice", 120.0, 1, 0.0, None) }),
+            sync_op("a2", 11, SyncChange::AddExpense { expense: create_person(2, "Bob", 30.0, 1, 0.0, None) }),
+        ];
+        let phone_b = vec![
+            sync_op("b1", 12, SyncChange::EditExpense { expense: create_person(1, "Alice", 90.0, 1, 0.0, None) }),
+            sync_op("b2", 5, SyncChange::DeleteExpense { expense_id: 2 }),
+            sync_op("b3", 6, SyncChange::AddParticipant { name: "  ".to_string() }),
         ];
 
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 0.0,
-            tip_percentage: 0.0,
-        };
+        // Whichever phone syncs first, both end up with the same lines
+        let mut results = Vec::new();
+        for batches in [[&phone_a, &phone_b], [&phone_b, &phone_a]] {
+            let mut people = vec![create_person(1, "Alice", 100.0, 1, 0.0, None)];
+            let mut history = Vec::new();
+            let mut applied_a = Vec::new();
+            for batch in batches {
+                let outcome = apply_sync_operations("s", &mut people, &history, batch.clone(), now);
+                if std::ptr::eq(batch, &phone_a) {
+                    applied_a = outcome.applied;
+                }
+                history.extend(outcome.records);
+            }
+            let lines: Vec<(u64, String, f64)> = people.iter().map(|p| (p.id, p.name.clone(), p.amount_spent)).collect();
+            results.push(lines.clone());
+
+            // Replaying a batch changes nothing and gets the same answer as the first time
+            let outcome = apply_sync_operations("s", &mut people, &history, phone_a.clone(), now);
+            assert!(outcome.records.is_empty());
+            assert_eq!(outcome.applied, applied_a);
+            assert_eq!(people.iter().map(|p| (p.id, p.name.clone(), p.amount_spent)).collect::<Vec<_>>(), lines);
+        }
+        assert_eq!(results[0], results[1]);
+        assert_eq!(results[0], vec![(1, "Alice".to_string(), 90.0), (2, "Bob".to_string(), 30.0)]);
 
-        let response = calculate_split_internal(request);
+        // Renaming a participant follows their paid_by references; deleting clears them
+        let mut people = vec![
+            create_person(1, "Alice", 100.0, 1, 0.0, None),
+            create_person(2, "Bob", 20.0, 1, 0.0, Some("Alice".to_string())),
+        ];
+        let outcome = apply_sync_operations("s", &mut people, &[], vec![
+            sync_op("r1", 1, SyncChange::EditParticipant { name: "Alice".to_string(), new_name: "Alicia".to_string() }),
+            sync_op("r2", 2, SyncChange::EditParticipant { name: "Nobody".to_string(), new_name: "X".to_string() }),
+        ], now);
+        assert_eq!(outcome.applied, vec!["r1"]);
+        assert_eq!(outcome.rejected[0].op_id, "r2");
+        assert_eq!(people[1].paid_by.as_deref(), Some("Alicia"));
+
+        apply_sync_operations("s", &mut people, &[], vec![
+            sync_op("d1", 3, SyncChange::DeleteParticipant { name: "Alicia".to_string() }),
+        ], now);
+        assert_eq!(people.len(), 1);
+        assert_eq!(people[0].paid_by, None);
+    }
 
-        let alice = response.settlements.iter().find(|s| s.name == "Alice").unwrap();
-        assert!(alice.is_receiver);
+    #[tokio::test]
+    async fn test_sync_session_handler() {
+        for store in stores().await {
+            insert_session(store.as_ref(), "synced", 0, "[]", false).await;
+            let state = app_state(store.clone());
+            let mut headers = HeaderMap::new();
+            headers.insert("X-Edit-Secret", "secret".parse().unwrap());
+
+            let batch = || Json(SyncRequest {
+                operations: vec![
+                    sync_op("op-1", 1, SyncChange::AddExpense { expense: create_person(5, "Alice", 40.0, 1, 0.0, None) }),
+                    sync_op("op-2", 2, SyncChange::AddParticipant { name: "Bob".to_string() }),
+                    sync_op("op-3", 3, SyncChange::AddExpense { expense: create_person(7, "", 1.0, 1, 0.0, None) }),
+                ],
+            });
+
+            let Json(first) = sync_session(State(state.clone()), Path("synced".to_string()), headers.clone(), batch()).await.unwrap();
+            assert_eq!(first.applied, vec!["op-1", "op-2"]);
+            assert_eq!(first.rejected.len(), 1);
+            assert_eq!(first.session.people.len(), 2);
+            assert_eq!(store.participant_links_for_session("synced").await.unwrap().len(), 2);
+
+            // Sending the same batch again (e.g. after a lost response) is harmless
+            let Json(again) = sync_session(State(state.clone()), Path("synced".to_string()), headers.clone(), batch()).await.unwrap();
+            assert_eq!(again.applied, vec!["op-1", "op-2"]);
+            assert_eq!(again.session.people.len(), 2);
+            assert_eq!(store.sync_operations("synced").await.unwrap().len(), 2);
+
+            let error = sync_session(State(state.clone()), Path("synced".to_string()), HeaderMap::new(), batch()).await.unwrap_err();
+            assert_eq!(error.status(), StatusCode::FORBIDDEN);
+
+            store.delete_session("synced").await.unwrap();
+            assert!(store.sync_operations("synced").await.unwrap().is_empty());
+        }
     }
 
-    #[test]
-    fn test_large_quantity() {
-        // Test with large quantity multiplier
-        let people = vec![
-            create_person(1, "Alice", 10.0, 100, 5.0, None), // $1000 + $5 tip
-            create_person(2, "Bob", 0.0, 1, 0.0, None),
-        ];
+    async fn error_body(response: Response) -> ErrorResponse {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
 
+    #[tokio::test]
+    async fn test_error_responses() {
+        // Storage and provider failures are logged, not echoed to the client
+        let response = AppError::Storage(sqlx::Error::Protocol("password=hunter2".to_string())).into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = error_body(response).await;
+        assert_eq!(body.code, "storage_error");
+        assert!(!body.message.contains("hunter2"));
+        assert!(!body.request_id.is_empty());
+
+        let response = AppError::Ai("OpenAI API error: {\"error\": \"sk-secret\"}".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let body = error_body(response).await;
+        assert_eq!(body.code, "ai_provider_error");
+        assert!(!body.message.contains("sk-secret"));
+
+        let response = AppError::NotConfigured("AI").into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Validation failures say which field of which line is wrong
+        let mut person = create_person(3, "Alice", 10.0, 1, 0.0, None);
+        person.tip = -1.0;
         let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
+            people: vec![person],
+            include_sponsor: true,
+            restrict_sponsor_to_spent: None,
             fund_amount: 0.0,
             tip_percentage: 0.0,
         };
-
-        let response = calculate_split_internal(request);
-
-        assert_eq!(response.total_spent, 1005.0);
-        assert_eq!(response.per_person_share, 502.5);
+        let response = calculate_split(Json(request)).await.unwrap_err().into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = error_body(response).await;
+        assert_eq!(body.code, "validation_failed");
+        assert_eq!(body.message, "person 3 has an invalid tip");
+        assert_eq!(body.details, Some(serde_json::json!({ "field": "tip", "person_id": 3 })));
+
+        // Behind the middleware the client's request id comes back in the header and the body
+        use tower::ServiceExt;
+        let app = axum::Router::new()
+            .route("/missing", axum::routing::get(|| async { Err::<(), _>(AppError::NotFound) }))
+            .layer(axum::middleware::from_fn(error::request_id));
+        let request = Request::builder().uri("/missing").header("X-Request-ID", "req-42").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()["X-Request-ID"], "req-42");
+        let body = error_body(response).await;
+        assert_eq!((body.code.as_str(), body.request_id.as_str()), ("not_found", "req-42"));
     }
 
+    // openapi.json is checked in so API changes show up in review; regenerate it with
+    // UPDATE_OPENAPI=1 cargo test
     #[test]
-    fn test_all_private_expenses() {
-        // Everyone has private expenses, nothing to split
-        let people = vec![
-            create_person(1, "Alice", 100.0, 1, 0.0, Some("Alice".to_string())),
-            create_person(2, "Bob", 50.0, 1, 0.0, Some("Bob".to_string())),
-            create_person(3, "Charlie", 75.0, 1, 0.0, Some("Charlie".to_string())),
-        ];
-
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 0.0,
-            tip_percentage: 0.0,
-        };
-
-        let response = calculate_split_internal(request);
-
-        assert_eq!(response.amount_to_share, 0.0);
-        assert_eq!(response.per_person_share, 0.0);
-
-        // Everyone should be settled (balance 0)
-        for settlement in &response.settlements {
-            assert_eq!(settlement.balance, 0.0);
-            assert_eq!(settlement.settlement_type, "settled");
+    fn test_openapi_snapshot() {
+        let spec = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(path, &spec).unwrap();
         }
+        let snapshot = std::fs::read_to_string(path).unwrap_or_default();
+        assert!(snapshot == spec, "openapi.json is out of date; rerun with UPDATE_OPENAPI=1 cargo test");
     }
 
-    #[test]
-    fn test_duplicate_names() {
-        // Test that duplicate names are grouped correctly
-        let people = vec![
-            create_person(1, "Alice", 100.0, 1, 10.0, None),
-            create_person(2, "Alice", 50.0, 1, 5.0, None), // Same name, different expense
-            create_person(3, "Bob", 0.0, 1, 0.0, None),
-        ];
+    #[tokio::test]
+    async fn test_openapi_paths_are_routed() {
+        use tower::ServiceExt;
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let app = app(app_state(Arc::new(MemoryStore::new())));
+
+        let paths = spec["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/api/v1/calculate") && paths.contains_key("/api/v2/calculate"));
+        for (path, operations) in paths {
+            let uri = path.replace(['{', '}'], "");
+            for method in operations.as_object().unwrap().keys() {
+                let request = Request::builder()
+                    .method(method.to_uppercase().as_str())
+                    .uri(&uri)
+                    .body(Body::empty())
+                    .unwrap();
+                let response = app.clone().oneshot(request).await.unwrap();
+                // The router's own 404 has no body; handlers answer with an ErrorResponse
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                assert!(
+                    status != StatusCode::METHOD_NOT_ALLOWED && !(status == StatusCode::NOT_FOUND && body.is_empty()),
+                    "{} {} is documented but not routed",
+                    method,
+                    path
+                );
+            }
+        }
+    }
 
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 0.0,
-            tip_percentage: 0.0,
+    #[tokio::test]
+    async fn test_api_versions() {
+        use tower::ServiceExt;
+        let app = app(app_state(Arc::new(MemoryStore::new())));
+        let calculate = |uri: &str| {
+            let body = r#"{"people":[
+                {"id":1,"name":"Alice","description":"","amount_spent":90,"is_sponsor":false,"sponsor_amount":0},
+                {"id":2,"name":"Bob","description":"","amount_spent":0,"is_sponsor":false,"sponsor_amount":0}
+            ],"include_sponsor":true}"#;
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("Content-Type", "application/json")
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let json = |response: Response| async {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
         };
 
-        let response = calculate_split_internal(request);
-
-        // Should have 2 unique people in settlements (Alice, Bob)
-        assert_eq!(response.settlements.len(), 2);
-
-        let alice = response.settlements.iter().find(|s| s.name == "Alice").unwrap();
-        // Alice's base expenses are combined: 100 + 50 = 150 (tips are tracked separately)
-        assert_eq!(alice.amount_spent, 150.0);
-        // Tips are also combined: 10 + 5 = 15
-        assert_eq!(alice.tip_paid, 15.0);
+        let response = app.clone().oneshot(calculate("/api/v1/calculate")).await.unwrap();
+        assert!(response.headers().get("Deprecation").is_none());
+        let v1 = json(response).await;
+        assert_eq!(v1["per_person_share"], 45.0);
+
+        // The unversioned alias answers the same, flagged as deprecated
+        let response = app.clone().oneshot(calculate("/api/calculate")).await.unwrap();
+        assert_eq!(response.headers()["Deprecation"], "@1792368000");
+        assert_eq!(response.headers()["Sunset"], "Fri, 30 Apr 2027 00:00:00 GMT");
+        assert_eq!(response.headers()["Link"], "</api/v1/calculate>; rel=\"successor-version\"");
+        assert_eq!(json(response).await, v1);
+
+        // v2 reshapes the calculation and falls back to v1 for everything else
+        let v2 = json(app.clone().oneshot(calculate("/api/v2/calculate")).await.unwrap()).await;
+        assert_eq!(v2["summary"]["per_person_share"], 45.0);
+        assert_eq!(v2["settlements"], v1["settlements"]);
+        assert_eq!(v2["transfers"], serde_json::json!([{ "from": "Bob", "to": "Alice", "amount": 45.0 }]));
+
+        let request = Request::builder().uri("/api/v2/sessions/missing").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(error_body(response).await.code, "not_found");
     }
 
-    #[test]
-    fn test_zero_amounts() {
-        // Test handling of zero amounts
-        let people = vec![
-            create_person(1, "Alice", 0.0, 1, 0.0, None),
-            create_person(2, "Bob", 0.0, 1, 0.0, None),
-        ];
-
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 0.0,
-            tip_percentage: 0.0,
-        };
+    #[tokio::test]
+    async fn test_idempotency_keys() {
+        use tower::ServiceExt;
+        for store in stores().await {
+            let mut state = app_state(store.clone());
+            state.limiter = Arc::new(RateLimiter::new(Limits::AI, Limits::EMAIL, true));
+            let app = app(state);
+            let record_key = |key: &str| crate::idempotency::record_key(&HeaderMap::new(), "unknown", key);
+            let create = |key: &str, fund_amount: f64| {
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/sessions")
+                    .header("Content-Type", "application/json")
+                    .header("Idempotency-Key", key)
+                    .body(Body::from(format!(r#"{{"people":[],"fund_amount":{}}}"#, fund_amount)))
+                    .unwrap()
+            };
+            let json = |response: Response| async {
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+            };
+
+            // A retry gets the first response back instead of a second session
+            let first = app.clone().oneshot(create("create-1", 10.0)).await.unwrap();
+            assert_eq!(first.status(), StatusCode::OK);
+            assert!(first.headers().get("Idempotent-Replayed").is_none());
+            let first = json(first).await;
+            let retry = app.clone().oneshot(create("create-1", 10.0)).await.unwrap();
+            assert_eq!(retry.status(), StatusCode::OK);
+            assert_eq!(retry.headers()["Idempotent-Replayed"], "true");
+            assert_eq!(retry.headers()["Content-Type"], "application/json");
+            assert_eq!(json(retry).await, first);
+            assert_eq!(store.all_sessions().await.unwrap().len(), 1);
+
+            // The same key with a different body is a client bug
+            let response = app.clone().oneshot(create("create-1", 20.0)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(error_body(response).await.code, "idempotency_key_reused");
+
+            // The stored response is encrypted, as it carries the new session's secret
+            let secret = first["edit_secret"].as_str().unwrap();
+            let record = store.get_idempotency_record(&record_key("create-1")).await.unwrap().unwrap();
+            assert!(!record.body.unwrap().contains(secret));
+
+            // Keys are per client, so another client picking the same one isn't affected
+            let mut request = create("create-1", 20.0);
+            request.headers_mut().insert("X-Forwarded-For", "203.0.113.7".parse().unwrap());
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(response.headers().get("Idempotent-Replayed").is_none());
+            assert_ne!(json(response).await["id"], first["id"]);
+
+            // Without a key every request runs
+            let request = Request::builder()
+                .method("POST")
+                .uri("/api/v1/sessions")
+                .header("Content-Type", "application/json")
+                .body(Body::from(r#"{"people":[]}"#))
+                .unwrap();
+            assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+            assert_eq!(store.all_sessions().await.unwrap().len(), 3);
+
+            // Server errors aren't stored, so the client can retry them
+            std::env::remove_var("OPENAI_API_KEY");
+            let request = Request::builder()
+                .method("POST")
+                .uri("/api/v1/ai/text")
+                .header("Content-Type", "application/json")
+                .header("Idempotency-Key", "ai-1")
+                .body(Body::from(r#"{"text":"coffee 3"}"#))
+                .unwrap();
+            assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert!(store.get_idempotency_record(&record_key("ai-1")).await.unwrap().is_none());
+
+            // A request that died mid-flight holds its key for a while, then gives it up
+            app.clone().oneshot(create("stuck", 10.0)).await.unwrap();
+            let mut record = store.get_idempotency_record(&record_key("stuck")).await.unwrap().unwrap();
+            assert_eq!(record.status, Some(200));
+            store.delete_idempotency_record(&record_key("stuck")).await.unwrap();
+            record.status = None;
+            record.body = None;
+            assert!(store.insert_idempotency_record(&record).await.unwrap());
+            assert!(!store.insert_idempotency_record(&record).await.unwrap());
+            let response = app.clone().oneshot(create("stuck", 10.0)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::CONFLICT);
+            store.delete_idempotency_record(&record_key("stuck")).await.unwrap();
+            record.created_at = chrono::Utc::now() - chrono::Duration::minutes(10);
+            store.insert_idempotency_record(&record).await.unwrap();
+            let response = app.clone().oneshot(create("stuck", 10.0)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(response.headers().get("Idempotent-Replayed").is_none());
+
+            let cutoff = chrono::Utc::now() + chrono::Duration::seconds(1);
+            assert_eq!(store.delete_idempotency_records_before(cutoff).await.unwrap(), 3);
+            assert!(store.get_idempotency_record(&record_key("create-1")).await.unwrap().is_none());
+        }
+    }
 
-        let response = calculate_split_internal(request);
+    #[tokio::test]
+    async fn test_rate_limits() {
+        use tower::ServiceExt;
+        let off = Limits { per_ip: 0, per_session: 0, global: 0, daily_per_ip: 0, daily: 0 };
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let ai = || Request::builder()
+            .method("POST")
+            .uri("/api/v1/ai/text")
+            .header("Content-Type", "application/json")
+            .body(Body::from(r#"{"text":"coffee 3"}"#))
+            .unwrap();
+        let email = |session: &str| Request::builder()
+            .method("POST")
+            .uri(format!("/api/v1/sessions/{}/email", session))
+            .header("Content-Type", "application/json")
+            // Ignored: the session comes from the path, so a header can't pick a fresh bucket
+            .header("X-Session-ID", uuid::Uuid::new_v4().to_string())
+            .body(Body::from(r#"{"to":[]}"#))
+            .unwrap();
+        let login = || Request::builder()
+            .method("POST")
+            .uri("/api/v1/auth/login")
+            .header("Content-Type", "application/json")
+            .body(Body::from(r#"{"email":"someone@example.com"}"#))
+            .unwrap();
+
+        for store in stores().await {
+            // Buckets: a request is refused until a token refills
+            let mut state = app_state(store.clone());
+            state.limiter = Arc::new(RateLimiter::new(off, Limits { per_ip: 2, per_session: 1, ..off }, false));
+            let app = app(state);
+            assert_ne!(app.clone().oneshot(email("s1")).await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+            let response = app.clone().oneshot(email("s1")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            let retry_after: u64 = response.headers()["Retry-After"].to_str().unwrap().parse().unwrap();
+            assert!((1..=60).contains(&retry_after));
+            let body = error_body(response).await;
+            assert_eq!(body.code, "rate_limited");
+            assert_eq!(body.details, Some(serde_json::json!({ "retry_after": retry_after })));
+
+            // Another session still has its bucket, until the address runs out
+            assert_ne!(app.clone().oneshot(email("s2")).await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(app.clone().oneshot(email("s3")).await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+
+            // Sign-in links send email too, so they share the email limits; AI has its own
+            assert_eq!(app.clone().oneshot(login()).await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_ne!(app.clone().oneshot(ai()).await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+
+            // An exempt address skips the per-IP and per-session limits until the exemption goes
+            admin::run(store.as_ref(), &args(&["exempt", "unknown", "load", "test"]), 7).await.unwrap();
+            assert_eq!(store.rate_limit_exemptions().await.unwrap()[0].note, "load test");
+            assert_ne!(app.clone().oneshot(email("s1")).await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+            admin::run(store.as_ref(), &args(&["unexempt", "unknown"]), 7).await.unwrap();
+            assert!(admin::run(store.as_ref(), &args(&["unexempt", "unknown"]), 7).await.is_err());
+            let response = app.clone().oneshot(email("s4")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+            // Daily quotas are counted in the database and reset at midnight UTC
+            let mut state = app_state(store.clone());
+            state.limiter = Arc::new(RateLimiter::new(Limits { daily_per_ip: 1, ..off }, Limits { daily_per_ip: 1, daily: 10, ..off }, false));
+            let app = crate::app(state);
+            assert_ne!(app.clone().oneshot(ai()).await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+            let response = app.clone().oneshot(ai()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            let retry_after: i64 = response.headers()["Retry-After"].to_str().unwrap().parse().unwrap();
+            assert!((1..=86_400).contains(&retry_after));
+            assert_eq!(error_body(response).await.code, "quota_exceeded");
+
+            // Requests the handler refuses aren't charged
+            assert_eq!(app.clone().oneshot(email("missing")).await.unwrap().status(), StatusCode::FORBIDDEN);
+            assert_ne!(app.clone().oneshot(login()).await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+
+            // Once an address is past its own quota it stops counting against the server's
+            for _ in 0..3 {
+                let response = app.clone().oneshot(login()).await.unwrap();
+                assert_eq!(error_body(response).await.code, "quota_exceeded");
+            }
+            let usage = store.daily_usage(chrono::Utc::now().date_naive()).await.unwrap();
+            let mut usage: Vec<_> = usage.iter().map(|u| (u.subject.as_str(), u.count)).collect();
+            usage.sort();
+            assert_eq!(usage, [("ai:ip:unknown", 1), ("email:all", 1), ("email:ip:unknown", 1)]);
+            admin::run(store.as_ref(), &args(&["usage"]), 7).await.unwrap();
+        }
+    }
 
-        assert_eq!(response.total_spent, 0.0);
-        assert_eq!(response.amount_to_share, 0.0);
-        
-        for settlement in &response.settlements {
-            assert_eq!(settlement.balance, 0.0);
-            assert_eq!(settlement.settlement_type, "settled");
+    #[tokio::test]
+    async fn test_session_email() {
+        use crate::{send_email_handler, update_participant_emails};
+        for store in stores().await {
+            let state = app_state(store.clone());
+            insert_session(store.as_ref(), "trip", 0, "[]", false).await;
+            let people = vec![create_person(1, "Alice", 90.0, 1, 0.0, None), create_person(2, "Bob", 0.0, 1, 0.0, None)];
+            let mut session = store.get_session("trip").await.unwrap().unwrap();
+            session.people = serde_json::to_string(&people).unwrap();
+            store.update_session_contents(&session).await.unwrap();
+
+            let mut headers = HeaderMap::new();
+            headers.insert("X-Edit-Secret", session.edit_secret.parse().unwrap());
+            let emails = |pairs: &[(&str, Option<&str>)]| Json(ParticipantSettingsRequest {
+                emails: pairs.iter().map(|(name, email)| (name.to_string(), email.map(str::to_string))).collect(),
+                languages: Default::default(),
+            });
+            let send = |headers: HeaderMap, to: &[&str]| {
+                send_email_handler(
+                    State(state.clone()),
+                    Path("trip".to_string()),
+                    headers,
+                    Json(SendEmailRequest { to: to.iter().map(|e| e.to_string()).collect(), attachments: vec![] }),
+                )
+            };
+
+            let Json(links) = update_participant_emails(State(state.clone()), Path("trip".to_string()), headers.clone(), emails(&[("Alice", Some(" Alice@Example.com "))]))
+                .await
+                .unwrap();
+            assert_eq!(links.iter().map(|l| (l.name.as_str(), l.email.as_deref())).collect::<Vec<_>>(), [("Alice", Some("alice@example.com")), ("Bob", None)]);
+            let result = update_participant_emails(State(state.clone()), Path("trip".to_string()), headers.clone(), emails(&[("Mallory", Some("m@example.com"))])).await;
+            assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+            // Languages default to English and can be set without touching addresses
+            assert!(links.iter().all(|l| l.language == Language::En));
+            let languages = |pairs: &[(&str, Language)]| Json(ParticipantSettingsRequest {
+                emails: Default::default(),
+                languages: pairs.iter().map(|(name, language)| (name.to_string(), *language)).collect(),
+            });
+            let Json(links) = update_participant_emails(State(state.clone()), Path("trip".to_string()), headers.clone(), languages(&[("Bob", Language::Vi)]))
+                .await
+                .unwrap();
+            assert_eq!(
+                links.iter().map(|l| (l.name.as_str(), l.email.as_deref(), l.language)).collect::<Vec<_>>(),
+                [("Alice", Some("alice@example.com"), Language::En), ("Bob", None, Language::Vi)]
+            );
+            let result = update_participant_emails(State(state.clone()), Path("trip".to_string()), headers.clone(), languages(&[("Mallory", Language::Vi)])).await;
+            assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+            // Only the editor can send, and only to participants' recorded addresses
+            assert_eq!(send(HeaderMap::new(), &["alice@example.com"]).await.unwrap_err().status(), StatusCode::FORBIDDEN);
+            assert_eq!(send(headers.clone(), &[]).await.unwrap_err().status(), StatusCode::BAD_REQUEST);
+            let error = send(headers.clone(), &["alice@example.com", "victim@example.com"]).await.unwrap_err();
+            assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+            assert!(error.to_string().contains("victim@example.com"));
+
+            let error = send(headers.clone(), &["ALICE@example.com"]).await.unwrap_err();
+            assert_eq!(error.code(), "not_configured");
+
+            // Bob is a participant too, but only the addresses asked for are sent to
+            let outbox = std::env::temp_dir().join(format!("split-bills-test-{}", uuid::Uuid::new_v4()));
+            let transport = Arc::new(crate::email::FileTransport::new(&outbox));
+            let email_service = crate::email::EmailService::new(transport, "test@example.com".to_string());
+            let _ = update_participant_emails(State(state.clone()), Path("trip".to_string()), headers.clone(), emails(&[("Bob", Some("bob@example.com"))])).await.unwrap();
+            let sent = send_email_handler(
+                State(AppState { email: Some(Arc::new(email_service)), ..state.clone() }),
+                Path("trip".to_string()),
+                headers.clone(),
+                Json(SendEmailRequest { to: vec!["ALICE@example.com".to_string()], attachments: vec![ReportFormat::Csv, ReportFormat::Pdf, ReportFormat::Csv] }),
+            ).await;
+            assert_eq!(sent.unwrap(), StatusCode::OK);
+            let mut files: Vec<_> = std::fs::read_dir(&outbox).unwrap().map(|entry| entry.unwrap().path()).collect();
+            files.sort();
+            assert_eq!(files.len(), 3);
+            let json = files.iter().find(|f| f.extension().is_some_and(|e| e == "json")).unwrap();
+            let message: serde_json::Value = serde_json::from_slice(&std::fs::read(json).unwrap()).unwrap();
+            // Each format is attached once, however often it was asked for
+            assert_eq!(message["attachments"].as_array().unwrap().len(), 2);
+            assert_eq!(message["attachments"][0]["content_type"], "text/csv; charset=utf-8");
+            assert_eq!(message["attachments"][1]["content_type"], "application/pdf");
+            assert!(files.iter().any(|f| f.to_string_lossy().ends_with(".pdf")));
+            assert_eq!(message["from"], "test@example.com");
+            assert_eq!(message["to"], serde_json::json!(["alice@example.com"]));
+            assert_eq!(message["subject"], "Split Bills: your share");
+            assert!(message["text"].as_str().unwrap().contains("Hi Alice"));
+            std::fs::remove_dir_all(&outbox).unwrap();
+
+            // Clearing an address takes it off the list
+            let _ = update_participant_emails(State(state.clone()), Path("trip".to_string()), headers.clone(), emails(&[("Alice", None)])).await.unwrap();
+            assert_eq!(send(headers.clone(), &["alice@example.com"]).await.unwrap_err().status(), StatusCode::BAD_REQUEST);
         }
     }
 
     #[test]
-    fn test_high_tip_percentage() {
-        // Test with high tip percentage
+    fn test_settlement_email() {
+        use askama::Template;
         let people = vec![
-            create_person(1, "Alice", 100.0, 1, 0.0, None),
-            create_person(2, "Bob", 100.0, 1, 0.0, None),
+            create_person(1, "<b>Alice</b>", 1234.5, 1, 0.0, None),
+            create_person(2, "Bob", 0.0, 1, 0.0, None),
         ];
-
-        let request = CalculateRequest {
+        let result = split_bills_core::calculate_split(CalculateRequest {
             people,
-            include_sponsor: false,
+            include_sponsor: true,
             restrict_sponsor_to_spent: Some(true),
             fund_amount: 0.0,
-            tip_percentage: 25.0, // 25% tip
+            tip_percentage: 0.0,
+        });
+        let email = |name: &str, language| {
+            crate::email::settlement_email(&result, name, None, language, "https://example.com/?me=t".to_string()).unwrap()
         };
 
-        let response = calculate_split_internal(request);
-
-        assert_eq!(response.total_spent, 200.0);
-        // 25% of 200 = 50
-        assert!((response.total_tip - 50.0).abs() < 0.01);
-        // Total with tip: 250, split by 2
-        assert!((response.per_person_share - 125.0).abs() < 0.01);
+        let alice = email("<b>Alice</b>", Language::En);
+        let html = SettlementEmailHtml { email: &alice }.render().unwrap();
+        assert!(html.contains("Hi &lt;b&gt;Alice&lt;/b&gt;"));
+        assert!(html.contains("You receive <strong>$617.25</strong>"));
+        assert!(html.contains("Receive from <strong>Bob</strong>: $617.25"));
+        assert!(html.contains("Share of shared costs"));
+        assert!(html.contains("Pay <strong>$617.25</strong>"));
+        assert!(!html.contains("Total Tip/Tax"));
+        assert_eq!(alice.t.subject, "Split Bills: your share");
+
+        // Bob gets a separate copy, in Vietnamese, with a plain-text alternative
+        let bob = email("Bob", Language::Vi);
+        let html = SettlementEmailHtml { email: &bob }.render().unwrap();
+        assert!(html.contains("Chào Bob"));
+        assert!(html.contains("Bạn cần trả <strong>$617.25</strong>"));
+        assert!(html.contains("Phần chi phí chung"));
+        let text = SettlementEmailText { email: &bob }.render().unwrap();
+        assert!(text.contains("Chuyển cho <b>Alice</b>: $617.25"));
+        assert!(text.contains("Trang cá nhân của bạn: https://example.com/?me=t"));
+        assert!(!text.contains("<td"));
+        assert!(crate::email::settlement_email(&result, "Mallory", None, Language::En, String::new()).is_none());
+
+        assert_eq!(crate::email::format_money(1234567.891, None), "$1,234,567.89");
+        assert_eq!(crate::email::format_money(-50.0, Some("VND")), "-50.00 VND");
+        assert_eq!(crate::email::format_money(-0.001, None), "$0.00");
     }
 
     #[test]
-    fn test_reimbursement_with_tip() {
-        // Test reimbursement where the expense includes a tip
-        let people = vec![
-            create_person(1, "Alice", 100.0, 1, 20.0, Some("Bob".to_string())),
+    fn test_settlement_report() {
+        let mut people = vec![
+            create_person(1, "Nguyễn Văn Đức", 90.0, 1, 0.0, None),
             create_person(2, "Bob", 0.0, 1, 0.0, None),
+            create_person(3, "Bob", 12.5, 2, 0.0, Some("Bob".to_string())),
         ];
-
-        let request = CalculateRequest {
-            people,
-            include_sponsor: false,
-            restrict_sponsor_to_spent: Some(true),
-            fund_amount: 0.0,
+        people[1].is_sponsor = true;
+        people[1].sponsor_amount = 30.0;
+        let now = chrono::Utc::now();
+        let session = DbSession {
+            id: "0123456789abcdef".to_string(),
+            edit_secret: "secret".to_string(),
+            people: serde_json::to_string(&people).unwrap(),
+            created_at: now,
+            last_accessed_at: now,
+            fund_amount: 10.0,
             tip_percentage: 0.0,
+            include_sponsor: true,
+            expiry_days: None,
+            pinned: false,
+            notification_emails: "[]".to_string(),
+            expiry_warning_sent_at: None,
+            extend_token: None,
+            currency: Some("VND".to_string()),
+            owner_user_id: None,
         };
+        let report = SettlementReport::from_session(&session, now).unwrap();
+        assert_eq!(report.filename(ReportFormat::Pdf), format!("split-bills-01234567-{}.pdf", now.format("%Y-%m-%d")));
 
-        let response = calculate_split_internal(request);
-
-        assert_eq!(response.amount_to_share, 0.0);
-
-        let alice = response.settlements.iter().find(|s| s.name == "Alice").unwrap();
-        let bob = response.settlements.iter().find(|s| s.name == "Bob").unwrap();
+        // Every section, each with its header row
+        let csv = String::from_utf8(report.render(ReportFormat::Csv).unwrap()).unwrap();
+        for section in ["Summary", "Expenses", "Adjustments", "Settlements", "Transfers"] {
+            assert!(csv.lines().any(|line| line == section), "missing {}", section);
+        }
+        assert!(csv.contains("Nguyễn Văn Đức,,90.00,1,0.00,90.00,0.00,,false"));
+        assert!(csv.contains("Fund,,Collected before splitting,10.00"));
+        assert!(csv.contains("Sponsorship,Bob,,30.00"));
+        assert!(csv.contains("Private expense,Bob,,25.00"));
+        assert!(csv.contains("from,to,amount"));
+
+        // Text is in the content streams as-is, minus the accents the standard fonts lack
+        let pdf = report.render(ReportFormat::Pdf).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.4"));
+        assert!(text.trim_end().ends_with("%%EOF"));
+        assert!(text.contains("Nguyen Van Duc"));
+        assert!(text.contains("Amounts in VND"));
+        assert!(text.contains("(Adjustments)"));
+        let xref: usize = text.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+        assert!(text[xref..].starts_with("xref"));
+    }
 
-        // Alice should receive 120 (100 + 20 tip)
-        assert_eq!(alice.balance, 120.0);
-        // Note: tip_paid might be 0 in the settlement if tips are tracked differently in reimbursements
-        
-        // Bob should pay 120
-        assert_eq!(bob.balance, -120.0);
+    #[tokio::test]
+    async fn test_payment_reminders() {
+        use askama::Template;
+        use crate::{delete_reminders, mark_reminder_paid, reminder_paid_page, send_payment_reminders, update_participant_emails, update_reminders};
+        for store in stores().await {
+            let state = app_state(store.clone());
+            // Created three days ago; Bob and Carol each owe Alice 30
+            insert_session(store.as_ref(), "trip", 3, "[]", false).await;
+            let people = vec![
+                create_person(1, "Alice", 90.0, 1, 0.0, None),
+                create_person(2, "Bob", 0.0, 1, 0.0, None),
+                create_person(3, "Carol", 0.0, 1, 0.0, None),
+            ];
+            let mut session = store.get_session("trip").await.unwrap().unwrap();
+            session.people = serde_json::to_string(&people).unwrap();
+            store.update_session_contents(&session).await.unwrap();
+
+            let mut headers = HeaderMap::new();
+            headers.insert("X-Edit-Secret", session.edit_secret.parse().unwrap());
+            let schedule = |first_after_days, repeat_days| Json(ReminderSchedule { first_after_days, repeat_days });
+            let settings = ParticipantSettingsRequest {
+                emails: [("Alice", "alice@example.com"), ("Bob", "bob@example.com")]
+                    .into_iter()
+                    .map(|(name, email)| (name.to_string(), Some(email.to_string())))
+                    .collect(),
+                languages: [("Bob".to_string(), Language::Vi)].into_iter().collect(),
+            };
+            let _ = update_participant_emails(State(state.clone()), Path("trip".to_string()), headers.clone(), Json(settings)).await.unwrap();
+
+            assert_eq!(
+                update_reminders(State(state.clone()), Path("trip".to_string()), HeaderMap::new(), schedule(2, 7)).await.unwrap_err().status(),
+                StatusCode::FORBIDDEN
+            );
+            assert_eq!(
+                update_reminders(State(state.clone()), Path("trip".to_string()), headers.clone(), schedule(2, 91)).await.unwrap_err().status(),
+                StatusCode::BAD_REQUEST
+            );
+
+            let outbox = std::env::temp_dir().join(format!("split-bills-test-{}", uuid::Uuid::new_v4()));
+            let email_service = crate::email::EmailService::new(Arc::new(crate::email::FileTransport::new(&outbox)), "test@example.com".to_string());
+            let sent = || {
+                let mut messages: Vec<serde_json::Value> = std::fs::read_dir(&outbox)
+                    .map(|dir| dir.map(|entry| serde_json::from_slice(&std::fs::read(entry.unwrap().path()).unwrap()).unwrap()).collect())
+                    .unwrap_or_default();
+                messages.sort_by_key(|m| m["subject"].to_string());
+                messages
+            };
+            let now = chrono::Utc::now();
+
+            // Not due until five days after the session
+            let _ = update_reminders(State(state.clone()), Path("trip".to_string()), headers.clone(), schedule(5, 7)).await.unwrap();
+            send_payment_reminders(store.as_ref(), &email_service, now).await;
+            assert!(sent().is_empty());
+
+            // Only Bob has an address. Alice, who Bob pays, gets a note of their own rather than
+            // a copy, which would carry Bob's personal link.
+            let Json(response) = update_reminders(State(state.clone()), Path("trip".to_string()), headers.clone(), schedule(2, 7)).await.unwrap();
+            assert!(response.reminders.is_empty());
+            send_payment_reminders(store.as_ref(), &email_service, now).await;
+            let messages = sent();
+            assert_eq!(messages.len(), 2);
+            let (note, reminder) = (&messages[0], &messages[1]);
+            assert_eq!(reminder["to"], serde_json::json!(["bob@example.com"]));
+            assert_eq!(reminder["subject"], "Split Bills: nhắc thanh toán");
+            let html = reminder["html"].as_str().unwrap();
+            assert!(html.contains("<strong>$30.00</strong>"));
+            assert!(html.contains("Chuyển cho <strong>Alice</strong>: $30.00"));
+            assert!(html.contains("/?me="));
+            assert_eq!(note["to"], serde_json::json!(["alice@example.com"]));
+            assert_eq!(note["subject"], "Split Bills: a payment reminder was sent");
+            let note_html = note["html"].as_str().unwrap();
+            assert!(note_html.contains("<strong>Bob</strong>, who owes you <strong>$30.00</strong>"));
+            assert!(!note_html.contains("?me="));
+
+            // Then weekly, not hourly
+            send_payment_reminders(store.as_ref(), &email_service, now + chrono::Duration::days(1)).await;
+            assert_eq!(sent().len(), 2);
+            send_payment_reminders(store.as_ref(), &email_service, now + chrono::Duration::days(7)).await;
+            assert_eq!(sent().len(), 4);
+            let reminders = store.payment_reminders_for_session("trip").await.unwrap();
+            assert_eq!(reminders.len(), 1);
+            assert_eq!((reminders[0].name.as_str(), reminders[0].amount, reminders[0].reminders_sent), ("Bob", 30.0, 2));
+            let paid_url = format!("/api/v1/reminders/{}/paid", reminders[0].token);
+            assert!(html.contains(&paid_url) && note_html.contains(&paid_url));
+
+            // Following the link in the email only asks, so mail scanners can't stop them
+            let page = reminder_paid_page(State(state.clone()), Path(reminders[0].token.clone())).await.unwrap();
+            assert!(!page.paid);
+            assert!(page.render().unwrap().contains("<form method=\"post\">"));
+            assert!(store.payment_reminders_for_session("trip").await.unwrap()[0].paid_at.is_none());
+            assert_eq!(reminder_paid_page(State(state.clone()), Path("nope".to_string())).await.map(|_| ()).unwrap_err().status(), StatusCode::NOT_FOUND);
+
+            // The page's button does
+            let page = mark_reminder_paid(State(state.clone()), Path(reminders[0].token.clone())).await.unwrap();
+            let html = page.render().unwrap();
+            assert!(html.contains("Bob: <strong>$30.00</strong>"));
+            assert!(html.contains("Đã đánh dấu là đã trả"));
+            assert!(!html.contains("?me="));
+            assert_eq!(mark_reminder_paid(State(state.clone()), Path("nope".to_string())).await.map(|_| ()).unwrap_err().status(), StatusCode::NOT_FOUND);
+            send_payment_reminders(store.as_ref(), &email_service, now + chrono::Duration::days(14)).await;
+            assert_eq!(sent().len(), 4);
+            let Json(response) = crate::get_reminders(State(state.clone()), Path("trip".to_string()), headers.clone()).await.unwrap();
+            assert!(response.reminders[0].paid_at.is_some());
+
+            // Paying covers the debt as reminded. Once Alice's expense grows, Bob owes more and
+            // is reminded again without waiting for the next week.
+            let people = vec![
+                create_person(1, "Alice", 120.0, 1, 0.0, None),
+                create_person(2, "Bob", 0.0, 1, 0.0, None),
+                create_person(3, "Carol", 0.0, 1, 0.0, None),
+            ];
+            let mut session = store.get_session("trip").await.unwrap().unwrap();
+            session.people = serde_json::to_string(&people).unwrap();
+            store.update_session_contents(&session).await.unwrap();
+            send_payment_reminders(store.as_ref(), &email_service, now + chrono::Duration::days(15)).await;
+            assert_eq!(sent().len(), 6);
+            let reminders = store.payment_reminders_for_session("trip").await.unwrap();
+            assert_eq!((reminders[0].amount, reminders[0].reminders_sent), (40.0, 3));
+            assert!(reminders[0].paid_at.is_none());
+
+            // A debt marked paid while that reminder was going out stays paid
+            assert!(store.mark_payment_paid(&reminders[0].token, now + chrono::Duration::days(16)).await.unwrap());
+            store.record_payment_reminder(&reminders[0]).await.unwrap();
+            assert!(store.payment_reminders_for_session("trip").await.unwrap()[0].paid_at.is_some());
+
+            let status = delete_reminders(State(state.clone()), Path("trip".to_string()), headers.clone()).await.unwrap();
+            assert_eq!(status, StatusCode::NO_CONTENT);
+            assert!(store.get_reminder_schedule("trip").await.unwrap().is_none());
+            let _ = std::fs::remove_dir_all(&outbox);
+        }
     }
 }
@@ -0,0 +1,47 @@
+use axum::{routing::post, Json, Router};
+use split_bills_core::{plan_transfers, validate_request};
+
+use crate::error::AppError;
+use crate::models::*;
+
+// Handlers whose request or response shape changed in /api/v2. Only what differs is
+// listed here; every other /api/v2 path is answered by the v1 handler (see app in
+// main.rs), so clients can move to v2 without waiting for a full copy of the API.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/calculate", post(calculate_split))
+}
+
+// Same engine as v1, but the totals are grouped under `summary` and the transfers that
+// settle the bill come with the settlements instead of being left to the client
+#[utoipa::path(
+    post,
+    path = "/api/v2/calculate",
+    tag = "calculate",
+    summary = "Split a bill and plan the transfers",
+    request_body = CalculateRequest,
+    responses(
+        (status = 200, body = CalculateResponseV2),
+        (status = 400, description = "The request failed validation", body = ErrorResponse),
+    )
+)]
+pub async fn calculate_split(
+    Json(request): Json<CalculateRequest>,
+) -> Result<Json<CalculateResponseV2>, AppError> {
+    validate_request(&request)?;
+    let response = split_bills_core::calculate_split(request);
+
+    Ok(Json(CalculateResponseV2 {
+        summary: CalculateSummary {
+            total_spent: response.total_spent,
+            total_sponsored: response.total_sponsored,
+            fund_amount: response.fund_amount,
+            total_tip: response.total_tip,
+            amount_to_share: response.amount_to_share,
+            num_participants: response.num_participants,
+            per_person_share: response.per_person_share,
+        },
+        transfers: plan_transfers(&response.settlements),
+        settlements: response.settlements,
+    }))
+}
@@ -0,0 +1,51 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use chrono::DateTime;
+
+// Routes that still work but are going away. Their responses carry Deprecation
+// (RFC 9745) and Sunset (RFC 8594) headers and a Link to the replacement.
+#[derive(Debug, Clone, Copy)]
+pub struct Deprecation {
+    // Unix timestamps
+    pub since: i64,
+    pub sunset: i64,
+    // Prefix the same paths live under now, e.g. "/api/v1"
+    pub successor: &'static str,
+}
+
+// The unversioned /api/... paths are aliases of /api/v1, kept for scripts written
+// before versioning until 2027-04-30
+pub const UNVERSIONED_API: Deprecation = Deprecation {
+    since: 1_792_368_000,
+    sunset: 1_809_043_200,
+    successor: "/api/v1",
+};
+
+// Layer for a nested router, which sees paths without its own prefix
+pub async fn deprecation_headers(
+    State(deprecation): State<Deprecation>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let successor = format!("<{}{}>; rel=\"successor-version\"", deprecation.successor, request.uri().path());
+    tracing::debug!("Deprecated route {} {}", request.method(), request.uri().path());
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&format!("@{}", deprecation.since)) {
+        headers.insert("Deprecation", value);
+    }
+    if let Some(sunset) = DateTime::from_timestamp(deprecation.sunset, 0) {
+        if let Ok(value) = HeaderValue::from_str(&sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string()) {
+            headers.insert("Sunset", value);
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, value);
+    }
+    response
+}
@@ -3,9 +3,12 @@ let people = [];
 let history = [];
 let editingPersonId = null;
 let currentSessionId = null;
+// Participant name -> recorded email address, for the email dialog
+let participantEmails = {};
 let currentEditSecret = null;
 let isReadOnly = false;
 let lastCalculationResult = null;
+let myParticipantToken = null;
 
 // DOM elements
 const addPersonForm = document.getElementById('addPersonForm');
@@ -33,6 +36,17 @@ const editLinkInput = document.getElementById('editLink');
 const copyViewLinkBtn = document.getElementById('copyViewLink');
 const copyEditLinkBtn = document.getElementById('copyEditLink');
 const archiveBtn = document.getElementById('archiveBtn');
+const historyKeyLink = document.getElementById('historyKeyLink');
+const saveTemplateBtn = document.getElementById('saveTemplateBtn');
+const templateList = document.getElementById('templateList');
+const accountSignedOut = document.getElementById('accountSignedOut');
+const accountSignedIn = document.getElementById('accountSignedIn');
+const accountEmailInput = document.getElementById('accountEmail');
+const sendLoginLinkBtn = document.getElementById('sendLoginLinkBtn');
+const accountEmailLabel = document.getElementById('accountEmailLabel');
+const logoutBtn = document.getElementById('logoutBtn');
+const claimSessionBtn = document.getElementById('claimSessionBtn');
+const accountSessionsList = document.getElementById('accountSessionsList');
 const historyList = document.getElementById('historyList');
 const participantSelect = document.getElementById('participantSelect');
 const fundAmountInput = document.getElementById('fundAmount');
@@ -41,7 +55,21 @@ const tipAmountGroup = document.getElementById('tipAmountGroup');
 const tipPercentageInput = document.getElementById('tipPercentage');
 const exportBtn = document.getElementById('exportBtn');
 const exportExcelBtn = document.getElementById('exportExcelBtn');
+const exportPdfBtn = document.getElementById('exportPdfBtn');
 const paidBySelect = document.getElementById('paidBy');
+const personalLinks = document.getElementById('personalLinks');
+const personalLinksList = document.getElementById('personalLinksList');
+const myBalanceSection = document.getElementById('myBalanceSection');
+const myBalanceContent = document.getElementById('myBalanceContent');
+const expiryNotice = document.getElementById('expiryNotice');
+const pinSessionCheckbox = document.getElementById('pinSession');
+const notificationEmailsInput = document.getElementById('notificationEmails');
+const saveNotificationEmailsBtn = document.getElementById('saveNotificationEmails');
+const remindersEnabledCheckbox = document.getElementById('remindersEnabled');
+const reminderFirstDaysInput = document.getElementById('reminderFirstDays');
+const reminderRepeatDaysInput = document.getElementById('reminderRepeatDays');
+const saveRemindersBtn = document.getElementById('saveReminders');
+const reminderStatus = document.getElementById('reminderStatus');
 
 // New DOM elements for Image Tab
 const imagePersonNameInput = document.getElementById('imagePersonName');
@@ -74,13 +102,23 @@ sponsorAmountInput.addEventListener('input', function() { formatInputMoney(this)
 if (fundAmountInput) fundAmountInput.addEventListener('input', function() { formatInputMoney(this); savePeople(); });
 if (addTipCheckbox) addTipCheckbox.addEventListener('change', function() { toggleTipAmount(); savePeople(); });
 if (tipPercentageInput) tipPercentageInput.addEventListener('input', savePeople);
+includeSponsorCheckbox.addEventListener('change', savePeople);
+if (pinSessionCheckbox) pinSessionCheckbox.addEventListener('change', updateSessionPin);
+if (saveNotificationEmailsBtn) saveNotificationEmailsBtn.addEventListener('click', saveNotificationEmails);
+if (saveRemindersBtn) saveRemindersBtn.addEventListener('click', saveReminders);
 cancelEditBtn.addEventListener('click', cancelEdit);
 if (shareBtn) shareBtn.addEventListener('click', shareSplit);
 if (copyViewLinkBtn) copyViewLinkBtn.addEventListener('click', () => copyToClipboard(viewLinkInput, copyViewLinkBtn));
 if (copyEditLinkBtn) copyEditLinkBtn.addEventListener('click', () => copyToClipboard(editLinkInput, copyEditLinkBtn));
 if (archiveBtn) archiveBtn.addEventListener('click', archiveSession);
+if (historyKeyLink) historyKeyLink.addEventListener('click', changeHistoryKey);
+if (saveTemplateBtn) saveTemplateBtn.addEventListener('click', saveTemplate);
+if (sendLoginLinkBtn) sendLoginLinkBtn.addEventListener('click', sendLoginLink);
+if (logoutBtn) logoutBtn.addEventListener('click', logout);
+if (claimSessionBtn) claimSessionBtn.addEventListener('click', claimCurrentSession);
 if (exportBtn) exportBtn.addEventListener('click', exportToCSV);
 if (exportExcelBtn) exportExcelBtn.addEventListener('click', exportToExcel);
+if (exportPdfBtn) exportPdfBtn.addEventListener('click', downloadPdfReport);
 if (participantSelect) {
     participantSelect.addEventListener('change', function() {
         if (this.value) {
@@ -93,6 +131,14 @@ if (participantSelect) {
 
 if (emailBtn) {
     emailBtn.addEventListener('click', () => {
+        // The server only emails a shared session's results to its participants
+        if (!currentSessionId || !currentEditSecret) {
+            alert('Share the split first, then add participants\' emails under Personal Links.');
+            return;
+        }
+        if (!emailRecipientsInput.value) {
+            emailRecipientsInput.value = Object.values(participantEmails).join(', ');
+        }
         emailModal.style.display = 'flex';
     });
 }
@@ -226,11 +272,36 @@ function formatInputMoney(input) {
 init();
 
 async function init() {
-    loadHistoryFromLocalStorage();
+    // Fetch the engine up front, while there is still a connection
+    splitEngine.load().catch(error => console.warn('Could not load the local calculator:', error));
+    loadHistory();
+    loadTemplates();
 
     const urlParams = new URLSearchParams(window.location.search);
-    const sessionId = urlParams.get('session');
+    let sessionId = urlParams.get('session');
     const secret = urlParams.get('secret');
+    const meToken = urlParams.get('me');
+    const loginToken = urlParams.get('login');
+
+    // Magic link from the sign-in email
+    if (loginToken) {
+        await verifyLogin(loginToken);
+        window.history.replaceState({}, '', '/');
+    }
+    loadAccount();
+
+    // Personal link: remember which participant this device belongs to
+    if (meToken) {
+        const claimed = await claimParticipant(meToken);
+        if (claimed) {
+            sessionId = claimed.session_id;
+            window.history.replaceState({}, '', `/?session=${sessionId}`);
+        }
+    }
+
+    if (sessionId) {
+        myParticipantToken = getRememberedParticipant(sessionId);
+    }
 
     if (sessionId) {
         currentSessionId = sessionId;
@@ -245,6 +316,15 @@ async function init() {
         }
         
         await loadSession(sessionId);
+
+        if (secret) {
+            loadPersonalLinks();
+            loadNotificationEmails();
+            loadReminders();
+        }
+        if (myParticipantToken) {
+            loadMyBalance();
+        }
     } else {
         loadPeopleFromLocalStorage();
     }
@@ -252,7 +332,7 @@ async function init() {
 
 async function loadSession(id) {
     try {
-        const response = await fetch(`/api/sessions/${id}`);
+        const response = await fetch(`/api/v1/sessions/${id}`);
         if (response.ok) {
             const data = await response.json();
             people = data.people;
@@ -264,6 +344,9 @@ async function loadSession(id) {
                 tipPercentageInput.value = data.tip_percentage;
                 toggleTipAmount();
             }
+            includeSponsorCheckbox.checked = data.include_sponsor !== false;
+            if (pinSessionCheckbox) pinSessionCheckbox.checked = data.pinned;
+            renderExpiryNotice(data.expires_at);
             renderPeople(people);
             updatePaidByDropdown();
         } else {
@@ -333,7 +416,7 @@ async function savePeople() {
     // If we are in an editable session, sync to server
     if (currentSessionId && currentEditSecret) {
         try {
-            await fetch(`/api/sessions/${currentSessionId}`, {
+            await fetch(`/api/v1/sessions/${currentSessionId}`, {
                 method: 'PUT',
                 headers: {
                     'Content-Type': 'application/json',
@@ -342,9 +425,13 @@ async function savePeople() {
                 body: JSON.stringify({ 
                     people,
                     fund_amount: fundAmount,
-                    tip_percentage: tipPercentage
+                    tip_percentage: tipPercentage,
+                    include_sponsor: includeSponsorCheckbox.checked
                 })
             });
+            if (myParticipantToken) {
+                loadMyBalance();
+            }
         } catch (e) {
             console.error('Failed to sync session', e);
         }
@@ -361,13 +448,14 @@ async function shareSplit() {
     const tipPercentage = (addTipCheckbox && addTipCheckbox.checked && tipPercentageInput) ? (parseFloat(tipPercentageInput.value) || 0) : 0;
     
     try {
-        const response = await fetch('/api/sessions', {
+        const response = await fetchIdempotent('/api/v1/sessions', {
             method: 'POST',
-            headers: { 'Content-Type': 'application/json' },
+            headers: { 'Content-Type': 'application/json', ...authHeaders() },
             body: JSON.stringify({ 
                 people,
                 fund_amount: fundAmount,
-                tip_percentage: tipPercentage
+                tip_percentage: tipPercentage,
+                include_sponsor: includeSponsorCheckbox.checked
             })
         });
         
@@ -386,6 +474,8 @@ async function shareSplit() {
             
             // Update URL without reloading
             window.history.pushState({}, '', editUrl);
+            loadPersonalLinks();
+            loadAccountSessions();
         }
     } catch (e) {
         console.error(e);
@@ -393,6 +483,462 @@ async function shareSplit() {
     }
 }
 
+// Accounts
+// Signing in is optional. The access token from the magic link is kept in localStorage
+// and sent as a bearer token so new sessions are saved to the account.
+
+function authHeaders() {
+    const token = localStorage.getItem('splitBillsAuthToken');
+    return token ? { 'Authorization': `Bearer ${token}` } : {};
+}
+
+async function sendLoginLink() {
+    const email = accountEmailInput.value.trim();
+    if (!email) return;
+
+    try {
+        const response = await fetch('/api/v1/auth/login', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ email })
+        });
+        if (response.ok) {
+            alert(`Check ${email} for a sign-in link.`);
+        } else {
+            alert('Failed to send sign-in link');
+        }
+    } catch (e) {
+        console.error('Failed to send sign-in link', e);
+    }
+}
+
+async function verifyLogin(token) {
+    try {
+        const response = await fetch('/api/v1/auth/verify', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ token })
+        });
+        if (!response.ok) {
+            alert('This sign-in link has expired or was already used.');
+            return;
+        }
+        const data = await response.json();
+        localStorage.setItem('splitBillsAuthToken', data.access_token);
+    } catch (e) {
+        console.error('Failed to verify sign-in link', e);
+    }
+}
+
+async function logout(e) {
+    if (e) e.preventDefault();
+    try {
+        await fetch('/api/v1/auth/logout', { method: 'POST', headers: authHeaders() });
+    } catch (e) {
+        console.error('Failed to sign out', e);
+    }
+    localStorage.removeItem('splitBillsAuthToken');
+    loadAccount();
+}
+
+async function loadAccount() {
+    if (!accountSignedOut) return;
+
+    let user = null;
+    if (localStorage.getItem('splitBillsAuthToken')) {
+        try {
+            const response = await fetch('/api/v1/me', { headers: authHeaders() });
+            if (response.ok) {
+                user = await response.json();
+            } else if (response.status === 401) {
+                localStorage.removeItem('splitBillsAuthToken');
+            }
+        } catch (e) {
+            console.error('Failed to load account', e);
+        }
+    }
+
+    accountSignedOut.style.display = user ? 'none' : 'block';
+    accountSignedIn.style.display = user ? 'block' : 'none';
+    if (user) {
+        accountEmailLabel.textContent = user.email;
+        loadAccountSessions();
+    }
+}
+
+async function loadAccountSessions() {
+    if (!accountSessionsList || !localStorage.getItem('splitBillsAuthToken')) return;
+
+    try {
+        const response = await fetch('/api/v1/me/sessions', { headers: authHeaders() });
+        if (!response.ok) return;
+
+        const sessions = await response.json();
+        accountSessionsList.innerHTML = '';
+        if (sessions.length === 0) {
+            accountSessionsList.innerHTML = '<div style="text-align: center; color: #999; padding: 10px;">No sessions yet</div>';
+            return;
+        }
+
+        sessions.forEach(session => {
+            const url = session.edit_secret
+                ? `/?session=${session.id}&secret=${session.edit_secret}`
+                : `/?session=${session.id}`;
+            const label = session.participant_name
+                ? `${session.participant_name} · ${new Date(session.created_at).toLocaleDateString()}`
+                : new Date(session.created_at).toLocaleDateString();
+            const el = document.createElement('a');
+            el.href = url;
+            el.style.display = 'block';
+            el.style.fontSize = '0.85em';
+            el.textContent = `${label} (${session.role})`;
+            accountSessionsList.appendChild(el);
+        });
+    } catch (e) {
+        console.error('Failed to load account sessions', e);
+    }
+}
+
+// Save an anonymously created session to the signed-in account
+async function claimCurrentSession() {
+    if (!currentSessionId || !currentEditSecret) {
+        alert('Open a session with its edit link first.');
+        return;
+    }
+
+    try {
+        const response = await fetch('/api/v1/me/sessions', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json', ...authHeaders() },
+            body: JSON.stringify({ session_id: currentSessionId, edit_secret: currentEditSecret })
+        });
+        if (response.ok) {
+            loadAccountSessions();
+        } else {
+            alert('Failed to save session to your account');
+        }
+    } catch (e) {
+        console.error('Failed to claim session', e);
+    }
+}
+
+// Session expiry
+
+function renderExpiryNotice(expiresAt) {
+    if (!expiryNotice) return;
+
+    if (!expiresAt) {
+        expiryNotice.style.display = 'none';
+        return;
+    }
+
+    const expires = new Date(expiresAt);
+    const daysLeft = (expires - Date.now()) / (1000 * 60 * 60 * 24);
+    expiryNotice.textContent = daysLeft < 2
+        ? `⚠️ This session will be deleted on ${expires.toLocaleString()} unless someone opens it again.`
+        : `This session expires on ${expires.toLocaleDateString()} if nobody opens it.`;
+    expiryNotice.style.display = 'block';
+}
+
+async function updateSessionPin() {
+    if (!currentSessionId || !currentEditSecret) return;
+
+    try {
+        const response = await fetch(`/api/v1/sessions/${currentSessionId}/expiry`, {
+            method: 'PUT',
+            headers: {
+                'Content-Type': 'application/json',
+                'X-Edit-Secret': currentEditSecret
+            },
+            body: JSON.stringify({ pinned: pinSessionCheckbox.checked })
+        });
+        if (response.ok) {
+            const data = await response.json();
+            renderExpiryNotice(data.expires_at);
+        }
+    } catch (e) {
+        console.error('Failed to update session expiry', e);
+    }
+}
+
+async function loadNotificationEmails() {
+    if (!notificationEmailsInput || !currentSessionId || !currentEditSecret) return;
+
+    try {
+        const response = await fetch(`/api/v1/sessions/${currentSessionId}/notifications`, {
+            headers: { 'X-Edit-Secret': currentEditSecret }
+        });
+        if (response.ok) {
+            const data = await response.json();
+            notificationEmailsInput.value = data.emails.join(', ');
+        }
+    } catch (e) {
+        console.error('Failed to load notification emails', e);
+    }
+}
+
+async function saveNotificationEmails() {
+    if (!currentSessionId || !currentEditSecret) return;
+
+    const emails = notificationEmailsInput.value.split(',').map(e => e.trim()).filter(e => e);
+    try {
+        const response = await fetch(`/api/v1/sessions/${currentSessionId}/notifications`, {
+            method: 'PUT',
+            headers: {
+                'Content-Type': 'application/json',
+                'X-Edit-Secret': currentEditSecret
+            },
+            body: JSON.stringify({ emails })
+        });
+        if (response.ok) {
+            saveNotificationEmailsBtn.textContent = 'Saved!';
+            setTimeout(() => { saveNotificationEmailsBtn.textContent = 'Save'; }, 2000);
+        } else {
+            alert('Please enter valid email addresses (up to 10).');
+        }
+    } catch (e) {
+        console.error('Failed to save notification emails', e);
+    }
+}
+
+async function loadReminders() {
+    if (!remindersEnabledCheckbox || !currentSessionId || !currentEditSecret) return;
+
+    try {
+        const response = await fetch(`/api/v1/sessions/${currentSessionId}/reminders`, {
+            headers: { 'X-Edit-Secret': currentEditSecret }
+        });
+        if (response.ok) {
+            showReminders(await response.json());
+        }
+    } catch (e) {
+        console.error('Failed to load reminders', e);
+    }
+}
+
+function showReminders(data) {
+    remindersEnabledCheckbox.checked = !!data.schedule;
+    if (data.schedule) {
+        reminderFirstDaysInput.value = data.schedule.first_after_days;
+        reminderRepeatDaysInput.value = data.schedule.repeat_days;
+    }
+    if (data.reminders.length > 0) {
+        reminderStatus.textContent = data.reminders
+            .map(r => `${r.name}: ${r.paid_at ? 'paid' : `reminded ${r.reminders_sent}×`}`)
+            .join(', ');
+    }
+}
+
+// Debtors are reminded by email until they, or whoever they pay, mark the debt paid
+async function saveReminders() {
+    if (!currentSessionId || !currentEditSecret) return;
+
+    const url = `/api/v1/sessions/${currentSessionId}/reminders`;
+    try {
+        let response;
+        if (remindersEnabledCheckbox.checked) {
+            response = await fetch(url, {
+                method: 'PUT',
+                headers: { 'Content-Type': 'application/json', 'X-Edit-Secret': currentEditSecret },
+                body: JSON.stringify({
+                    first_after_days: parseInt(reminderFirstDaysInput.value, 10) || 0,
+                    repeat_days: parseInt(reminderRepeatDaysInput.value, 10) || 0
+                })
+            });
+            if (response.ok) showReminders(await response.json());
+        } else {
+            response = await fetch(url, { method: 'DELETE', headers: { 'X-Edit-Secret': currentEditSecret } });
+        }
+        if (response.ok) {
+            saveRemindersBtn.textContent = 'Saved!';
+            setTimeout(() => { saveRemindersBtn.textContent = 'Save'; }, 2000);
+        } else {
+            const err = await response.json().catch(() => ({}));
+            alert(err.message || 'Failed to save reminders');
+        }
+    } catch (e) {
+        console.error('Failed to save reminders', e);
+    }
+}
+
+// Personal links
+
+function getRememberedParticipant(sessionId) {
+    try {
+        const stored = JSON.parse(localStorage.getItem('splitBillsMe') || '{}');
+        return stored[sessionId] || null;
+    } catch (e) {
+        return null;
+    }
+}
+
+function rememberParticipant(sessionId, token) {
+    let stored = {};
+    try {
+        stored = JSON.parse(localStorage.getItem('splitBillsMe') || '{}');
+    } catch (e) {
+        stored = {};
+    }
+    stored[sessionId] = token;
+    localStorage.setItem('splitBillsMe', JSON.stringify(stored));
+}
+
+async function claimParticipant(token) {
+    try {
+        const response = await fetch(`/api/v1/participants/${token}/claim`, {
+            method: 'POST',
+            headers: authHeaders()
+        });
+        if (!response.ok) {
+            alert('This personal link is no longer valid.');
+            return null;
+        }
+        const data = await response.json();
+        rememberParticipant(data.session_id, data.token);
+        return data;
+    } catch (e) {
+        console.error('Failed to claim personal link', e);
+        return null;
+    }
+}
+
+async function loadPersonalLinks() {
+    if (!personalLinks || !currentSessionId || !currentEditSecret) return;
+
+    try {
+        const response = await fetch(`/api/v1/sessions/${currentSessionId}/participants`, {
+            headers: { 'X-Edit-Secret': currentEditSecret }
+        });
+        if (!response.ok) return;
+
+        const links = await response.json();
+        const baseUrl = window.location.origin;
+        personalLinksList.innerHTML = '';
+        links.forEach(link => {
+            const row = document.createElement('div');
+            row.className = 'input-group';
+            row.innerHTML = `
+                <span style="font-size: 0.85em; min-width: 70px; align-self: center;"></span>
+                <input type="text" readonly value="${baseUrl}/?me=${link.token}">
+                <input type="email" placeholder="Email" style="max-width: 160px;">
+                <select title="Email language" style="max-width: 70px;">
+                    <option value="en">EN</option>
+                    <option value="vi">VI</option>
+                </select>
+                <button class="btn btn-copy">Copy</button>
+            `;
+            row.querySelector('span').textContent = link.name + (link.claimed ? ' ✓' : '');
+            const [input, emailInput] = row.querySelectorAll('input');
+            const button = row.querySelector('button');
+            button.addEventListener('click', () => copyToClipboard(input, button));
+            emailInput.value = link.email || '';
+            emailInput.addEventListener('change', () => saveParticipantEmail(link.name, emailInput));
+            const languageSelect = row.querySelector('select');
+            languageSelect.value = link.language || 'en';
+            languageSelect.addEventListener('change', () => saveParticipantLanguage(link.name, languageSelect.value));
+            personalLinksList.appendChild(row);
+        });
+        participantEmails = Object.fromEntries(links.filter(l => l.email).map(l => [l.name, l.email]));
+        personalLinks.style.display = links.length > 0 ? 'block' : 'none';
+        shareLinks.style.display = 'block';
+    } catch (e) {
+        console.error('Failed to load personal links', e);
+    }
+}
+
+// Results can only be emailed to the addresses recorded here
+async function saveParticipantEmail(name, input) {
+    const email = input.value.trim();
+    try {
+        const response = await fetch(`/api/v1/sessions/${currentSessionId}/participants`, {
+            method: 'PUT',
+            headers: { 'Content-Type': 'application/json', 'X-Edit-Secret': currentEditSecret },
+            body: JSON.stringify({ emails: { [name]: email || null } })
+        });
+        if (!response.ok) {
+            const err = await response.json().catch(() => ({}));
+            alert(err.message || 'Failed to save email');
+            return;
+        }
+        if (email) {
+            participantEmails[name] = email.toLowerCase();
+        } else {
+            delete participantEmails[name];
+        }
+    } catch (e) {
+        console.error('Failed to save participant email', e);
+    }
+}
+
+// Each participant's results email is written in the language picked here
+async function saveParticipantLanguage(name, language) {
+    try {
+        const response = await fetch(`/api/v1/sessions/${currentSessionId}/participants`, {
+            method: 'PUT',
+            headers: { 'Content-Type': 'application/json', 'X-Edit-Secret': currentEditSecret },
+            body: JSON.stringify({ languages: { [name]: language } })
+        });
+        if (!response.ok) {
+            const err = await response.json().catch(() => ({}));
+            alert(err.message || 'Failed to save language');
+        }
+    } catch (e) {
+        console.error('Failed to save participant language', e);
+    }
+}
+
+async function loadMyBalance() {
+    if (!myBalanceSection || !myParticipantToken) return;
+
+    try {
+        const response = await fetch(`/api/v1/participants/${myParticipantToken}`);
+        if (!response.ok) {
+            myBalanceSection.style.display = 'none';
+            return;
+        }
+        renderMyBalance(await response.json());
+    } catch (e) {
+        console.error('Failed to load my balance', e);
+    }
+}
+
+function renderMyBalance(view) {
+    let headline = '';
+    if (view.settlement_type === 'pay') {
+        headline = `<strong>${view.name}</strong>, you should pay <span class="settlement-amount">$${formatMoney(Math.abs(view.balance))}</span>`;
+    } else if (view.settlement_type === 'receive') {
+        headline = `<strong>${view.name}</strong>, you should receive <span class="settlement-amount">$${formatMoney(view.balance)}</span>`;
+    } else {
+        headline = `<strong>${view.name}</strong>, you are all settled up!`;
+    }
+
+    const transfers = view.transfers.map(t => t.from === view.name
+        ? `<li>Pay <strong>${t.to}</strong> $${formatMoney(t.amount)}</li>`
+        : `<li>Collect $${formatMoney(t.amount)} from <strong>${t.from}</strong></li>`
+    ).join('');
+
+    const ledger = view.ledger.map(e =>
+        `<tr><td style="padding: 4px;">${e.label}</td><td style="padding: 4px; text-align: right;">${e.amount < 0 ? '-' : ''}$${formatMoney(Math.abs(e.amount))}</td></tr>`
+    ).join('');
+
+    const expenses = view.expenses.map(p =>
+        `<li>${p.description || 'Expense'}: $${formatMoney(p.amount_spent * p.quantity)}${p.paid_by && p.paid_by !== p.name ? ` (paid by ${p.paid_by})` : ''}</li>`
+    ).join('');
+
+    myBalanceContent.innerHTML = `
+        <div class="settlement-item ${view.settlement_type}">
+            <div class="settlement-header">${headline}</div>
+        </div>
+        ${transfers ? `<h3>Transfers</h3><ul style="padding-left: 20px;">${transfers}</ul>` : ''}
+        <h3>Breakdown</h3>
+        <table style="width: 100%; border-collapse: collapse;">${ledger}
+            <tr style="border-top: 1px solid #e2e8f0;"><td style="padding: 4px;"><strong>Balance</strong></td><td style="padding: 4px; text-align: right;"><strong>${view.balance < 0 ? '-' : ''}$${formatMoney(Math.abs(view.balance))}</strong></td></tr>
+        </table>
+        ${expenses ? `<h3>My Expenses</h3><ul style="padding-left: 20px;">${expenses}</ul>` : ''}
+    `;
+    myBalanceSection.style.display = 'block';
+}
+
 // Add person to the list
 function handleAddPerson(e) {
     e.preventDefault();
@@ -722,6 +1268,32 @@ function setReceiver(id) {
     savePeople();
 }
 
+// Calculate in the browser so splitting works without reception. The server is only
+// asked when the WebAssembly engine couldn't be loaded.
+async function calculateRequest(request) {
+    let engineLoaded = false;
+    try {
+        await splitEngine.load();
+        engineLoaded = true;
+    } catch (error) {
+        console.warn('Local calculation unavailable, using the server:', error);
+    }
+    if (engineLoaded) {
+        return splitEngine.calculate(request);
+    }
+
+    const response = await fetch('/api/v1/calculate', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify(request)
+    });
+    if (!response.ok) {
+        const err = await response.json().catch(() => ({}));
+        throw new Error(err.message || 'Calculation failed');
+    }
+    return response.json();
+}
+
 // Calculate bill split
 async function calculateSplit() {
     try {
@@ -734,24 +1306,12 @@ async function calculateSplit() {
         const fundAmount = fundAmountInput ? (parseFloat(fundAmountInput.value.replace(/,/g, '')) || 0) : 0;
         const tipPercentage = (addTipCheckbox && addTipCheckbox.checked && tipPercentageInput) ? (parseFloat(tipPercentageInput.value) || 0) : 0;
         
-        const calcResponse = await fetch('/api/calculate', {
-            method: 'POST',
-            headers: {
-                'Content-Type': 'application/json',
-            },
-            body: JSON.stringify({
-                people,
-                include_sponsor: includeSponsor,
-                fund_amount: fundAmount,
-                tip_percentage: tipPercentage
-            })
+        const result = await calculateRequest({
+            people,
+            include_sponsor: includeSponsor,
+            fund_amount: fundAmount,
+            tip_percentage: tipPercentage
         });
-        
-        if (!calcResponse.ok) {
-            throw new Error('Calculation failed');
-        }
-        
-        const result = await calcResponse.json();
         lastCalculationResult = result; // Save the last calculation result
         
         if (result.num_participants === 0 && result.amount_to_share > 0) {
@@ -852,24 +1412,69 @@ function displayResults(result) {
 }
 
 // History Management
+// Archives live on the server, keyed by a per-browser owner token. Entries archived
+// before server-side history existed are still read from localStorage.
+
+function getOwnerToken() {
+    let token = localStorage.getItem('splitBillsOwnerToken');
+    if (!token) {
+        token = generateUUID();
+        localStorage.setItem('splitBillsOwnerToken', token);
+    }
+    return token;
+}
+
+// Show this browser's history key, or paste the key from another device to share its history
+function changeHistoryKey(e) {
+    e.preventDefault();
+    const current = getOwnerToken();
+    const entered = prompt('Your history key. Paste the key from another device to see its archives here:', current);
+    if (entered && entered.trim() && entered.trim() !== current) {
+        localStorage.setItem('splitBillsOwnerToken', entered.trim());
+        loadHistory();
+        loadTemplates();
+    }
+}
 
-function loadHistoryFromLocalStorage() {
+async function loadHistory() {
+    let localHistory = [];
     try {
         const storedHistory = localStorage.getItem('splitBillsHistory');
         if (storedHistory) {
-            history = JSON.parse(storedHistory);
-        } else {
-            history = [];
+            localHistory = JSON.parse(storedHistory).map(item => ({ ...item, source: 'local' }));
+        }
+    } catch (error) {
+        console.error('Error loading local history:', error);
+    }
+
+    let serverHistory = [];
+    try {
+        const response = await fetch('/api/v1/archives', {
+            headers: { 'X-Owner-Token': getOwnerToken() }
+        });
+        if (response.ok) {
+            const archives = await response.json();
+            serverHistory = archives.map(archive => ({
+                source: 'server',
+                archiveId: archive.id,
+                timestamp: Date.parse(archive.archived_at),
+                people: archive.people,
+                fundAmount: archive.fund_amount,
+                tipPercentage: archive.tip_percentage,
+                sessionId: archive.session_id
+            }));
         }
-        renderHistory();
     } catch (error) {
         console.error('Error loading history:', error);
-        history = [];
     }
+
+    history = [...localHistory, ...serverHistory].sort((a, b) => a.timestamp - b.timestamp);
+    renderHistory();
 }
 
 function saveHistoryToLocalStorage() {
-    localStorage.setItem('splitBillsHistory', JSON.stringify(history));
+    const localHistory = history.filter(item => item.source === 'local');
+    localStorage.setItem('splitBillsHistory', JSON.stringify(localHistory));
     renderHistory();
 }
 
@@ -901,10 +1506,11 @@ function renderHistory() {
         const uniqueNames = [...new Set(item.people.map(p => p.name))].sort();
         const count = uniqueNames.length;
         const namesList = uniqueNames.join(', ');
+        const localBadge = item.source === 'local' ? ' <span style="font-size: 0.75em; color: #a0aec0;">(this device)</span>' : '';
         
         el.innerHTML = `
             <div style="display: flex; justify-content: space-between; align-items: center; margin-bottom: 5px;">
-                <span style="font-weight: bold; font-size: 0.9em;">${date}</span>
+                <span style="font-weight: bold; font-size: 0.9em;">${date}${localBadge}</span>
                 <span style="font-size: 0.8em; color: #666;">$${formatMoney(total)}</span>
             </div>
             <div style="font-size: 0.8em; color: #666; margin-bottom: 8px;">
@@ -920,7 +1526,7 @@ function renderHistory() {
     });
 }
 
-function archiveSession() {
+async function archiveSession() {
     if (people.length === 0) {
         alert('Nothing to archive!');
         return;
@@ -930,19 +1536,36 @@ function archiveSession() {
         return;
     }
     
-    const fundAmount = fundAmountInput ? (parseFloat(fundAmountInput.value.replace(/,/g, '')) || 0) : 0;
-    const tipPercentage = (addTipCheckbox && addTipCheckbox.checked && tipPercentageInput) ? (parseFloat(tipPercentageInput.value) || 0) : 0;
+    try {
+        // Archives are made from server sessions, so share the workspace first if needed
+        if (!currentSessionId || !currentEditSecret) {
+            await shareSplit();
+        } else {
+            await savePeople();
+        }
+        if (!currentSessionId || !currentEditSecret) {
+            return;
+        }
 
-    const historyItem = {
-        timestamp: Date.now(),
-        people: JSON.parse(JSON.stringify(people)), // Deep copy
-        fundAmount: fundAmount,
-        tipPercentage: tipPercentage,
-        sessionId: currentSessionId
-    };
-    
-    history.push(historyItem);
-    saveHistoryToLocalStorage();
+        const response = await fetch(`/api/v1/sessions/${currentSessionId}/archive`, {
+            method: 'POST',
+            headers: {
+                'Content-Type': 'application/json',
+                'X-Edit-Secret': currentEditSecret,
+                'X-Owner-Token': getOwnerToken()
+            },
+            body: JSON.stringify({})
+        });
+        if (!response.ok) {
+            throw new Error('Archive failed');
+        }
+    } catch (e) {
+        console.error(e);
+        alert('Failed to archive session. Please try again.');
+        return;
+    }
+
+    await loadHistory();
     
     // Clear current session
     if (fundAmountInput) fundAmountInput.value = '';
@@ -952,9 +1575,8 @@ function archiveSession() {
     }
     localStorage.removeItem('splitBillsFund');
     localStorage.removeItem('splitBillsTip');
-    clearAllPeople(true);
     
-    // If we were in a shared session, reset URL to home
+    // Leave the shared session before clearing, so the archived session keeps its data
     if (currentSessionId) {
         window.history.pushState({}, '', '/');
         currentSessionId = null;
@@ -966,9 +1588,10 @@ function archiveSession() {
         if (shareBtn) shareBtn.style.display = 'block';
         if (shareLinks) shareLinks.style.display = 'none';
     }
+    clearAllPeople(true);
 }
 
-function useTemplate(index) {
+async function useTemplate(index) {
     if (people.length > 0) {
         if (!confirm('This will overwrite your current workspace. Continue?')) {
             return;
@@ -977,6 +1600,22 @@ function useTemplate(index) {
     
     const item = history[index];
     if (!item) return;
+
+    // Server archives are cloned into a fresh shared session
+    if (item.source === 'server') {
+        try {
+            const response = await fetch(`/api/v1/archives/${item.archiveId}/clone`, { method: 'POST' });
+            if (!response.ok) {
+                throw new Error('Clone failed');
+            }
+            const data = await response.json();
+            window.location.href = `/?session=${data.id}&secret=${data.edit_secret}`;
+        } catch (e) {
+            console.error(e);
+            alert('Failed to restore archive. Please try again.');
+        }
+        return;
+    }
     
     // Deep copy people from history
     people = JSON.parse(JSON.stringify(item.people));
@@ -1017,12 +1656,145 @@ function useTemplate(index) {
     renderPeople(people);
 }
 
-function deleteHistoryItem(index) {
+async function deleteHistoryItem(index) {
     if (!confirm('Delete this archive?')) return;
+
+    const item = history[index];
+    if (!item) return;
+
+    if (item.source === 'server') {
+        try {
+            const response = await fetch(`/api/v1/archives/${item.archiveId}`, {
+                method: 'DELETE',
+                headers: { 'X-Owner-Token': getOwnerToken() }
+            });
+            if (!response.ok) {
+                throw new Error('Delete failed');
+            }
+        } catch (e) {
+            console.error(e);
+            alert('Failed to delete archive.');
+            return;
+        }
+    }
+
     history.splice(index, 1);
     saveHistoryToLocalStorage();
 }
 
+// Server-side templates
+
+async function loadTemplates() {
+    if (!templateList) return;
+
+    try {
+        const response = await fetch('/api/v1/templates', {
+            headers: { 'X-Owner-Token': getOwnerToken() }
+        });
+        if (!response.ok) return;
+
+        const templates = await response.json();
+        templateList.innerHTML = '';
+        if (templates.length === 0) {
+            templateList.innerHTML = '<div style="text-align: center; color: #999; padding: 10px;">No templates yet</div>';
+            return;
+        }
+
+        templates.forEach(template => {
+            const el = document.createElement('div');
+            el.style.background = '#f7fafc';
+            el.style.border = '1px solid #e2e8f0';
+            el.style.borderRadius = '6px';
+            el.style.padding = '10px';
+            el.innerHTML = `
+                <div style="font-weight: bold; font-size: 0.9em; margin-bottom: 5px;">${template.name}</div>
+                <div style="font-size: 0.8em; color: #666; margin-bottom: 8px;">${template.roster.length} people, ${template.expenses.length} recurring lines</div>
+                <div style="display: flex; gap: 5px;">
+                    <button class="btn btn-sm" data-action="start" style="flex: 1; background: #4299e1; color: white; padding: 4px; font-size: 0.8em; border: none; border-radius: 4px; cursor: pointer;">Start Session</button>
+                    <button class="btn btn-sm" data-action="delete" style="background: #e53e3e; color: white; padding: 4px 8px; font-size: 0.8em; border: none; border-radius: 4px; cursor: pointer;">×</button>
+                </div>
+            `;
+            el.querySelector('[data-action="start"]').addEventListener('click', () => startFromTemplate(template.id));
+            el.querySelector('[data-action="delete"]').addEventListener('click', () => deleteTemplate(template.id));
+            templateList.appendChild(el);
+        });
+    } catch (e) {
+        console.error('Failed to load templates', e);
+    }
+}
+
+async function saveTemplate() {
+    if (people.length === 0) {
+        alert('Add some people first!');
+        return;
+    }
+
+    const name = prompt('Template name (e.g. Weekly badminton):');
+    if (!name || !name.trim()) return;
+
+    const includeLines = confirm('Save the current expense lines as recurring expenses?\n\nCancel saves only the roster and options.');
+    const receiver = people.find(p => p.is_receiver);
+    const template = {
+        name: name.trim(),
+        roster: [...new Set(people.map(p => p.name))].sort(),
+        fund_amount: fundAmountInput ? (parseFloat(fundAmountInput.value.replace(/,/g, '')) || 0) : 0,
+        tip_percentage: (addTipCheckbox && addTipCheckbox.checked && tipPercentageInput) ? (parseFloat(tipPercentageInput.value) || 0) : 0,
+        include_sponsor: includeSponsorCheckbox.checked,
+        receiver: receiver ? receiver.name : null,
+        expenses: includeLines ? people.filter(p => p.amount_spent > 0 || p.sponsor_amount > 0) : []
+    };
+
+    try {
+        const response = await fetch('/api/v1/templates', {
+            method: 'POST',
+            headers: {
+                'Content-Type': 'application/json',
+                'X-Owner-Token': getOwnerToken()
+            },
+            body: JSON.stringify(template)
+        });
+        if (!response.ok) {
+            throw new Error('Save failed');
+        }
+        loadTemplates();
+    } catch (e) {
+        console.error(e);
+        alert('Failed to save template.');
+    }
+}
+
+async function startFromTemplate(templateId) {
+    if (people.length > 0 && !confirm('This will start a new session and leave the current workspace. Continue?')) {
+        return;
+    }
+
+    try {
+        const response = await fetch(`/api/v1/sessions?template=${templateId}`, { method: 'POST' });
+        if (!response.ok) {
+            throw new Error('Create failed');
+        }
+        const data = await response.json();
+        window.location.href = `/?session=${data.id}&secret=${data.edit_secret}`;
+    } catch (e) {
+        console.error(e);
+        alert('Failed to start a session from this template.');
+    }
+}
+
+async function deleteTemplate(templateId) {
+    if (!confirm('Delete this template?')) return;
+
+    try {
+        await fetch(`/api/v1/templates/${templateId}`, {
+            method: 'DELETE',
+            headers: { 'X-Owner-Token': getOwnerToken() }
+        });
+        loadTemplates();
+    } catch (e) {
+        console.error('Failed to delete template', e);
+    }
+}
+
 // Expose functions to global scope for inline onclick handlers
 window.useTemplate = useTemplate;
 window.deleteHistoryItem = deleteHistoryItem;
@@ -1088,6 +1860,15 @@ function exportToCSV() {
     document.body.removeChild(link);
 }
 
+// The PDF report is generated by the server from the saved session
+function downloadPdfReport() {
+    if (!currentSessionId) {
+        alert('Share the split first to download it as a PDF.');
+        return;
+    }
+    window.location.href = `/api/v1/sessions/${currentSessionId}/report?format=pdf`;
+}
+
 // Export Results to Excel (HTML Table method)
 function exportToExcel() {
     if (!lastCalculationResult) {
@@ -1249,13 +2030,9 @@ async function processAiSplit() {
     loading.style.display = 'block';
 
     try {
-        const requestId = generateUUID();
-        const response = await fetch('/api/ai/split', {
+        const response = await fetchIdempotent('/api/v1/ai/split', {
             method: 'POST',
-            headers: { 
-                'Content-Type': 'application/json',
-                'X-Request-ID': requestId
-            },
+            headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify({ text })
         });
 
@@ -1340,13 +2117,9 @@ async function processText() {
     }
 
     try {
-        const requestId = generateUUID();
-        const response = await fetch('/api/ai/text', {
+        const response = await fetchIdempotent('/api/v1/ai/text', {
             method: 'POST',
-            headers: { 
-                'Content-Type': 'application/json',
-                'X-Request-ID': requestId
-            },
+            headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify({ text })
         });
 
@@ -1391,12 +2164,8 @@ async function processImage() {
     formData.append('image', input.files[0]);
 
     try {
-        const requestId = generateUUID();
-        const response = await fetch('/api/ai/image', {
+        const response = await fetchIdempotent('/api/v1/ai/image', {
             method: 'POST',
-            headers: {
-                'X-Request-ID': requestId
-            },
             body: formData
         });
 
@@ -1500,26 +2269,18 @@ async function sendEmail() {
         alert('Please enter at least one email address.');
         return;
     }
-
-    if (!lastCalculationResult) {
-        alert('Please calculate the split first.');
-        return;
-    }
-
-    const htmlContent = generateEmailHtml(lastCalculationResult);
+    const attachments = [];
+    if (document.getElementById('attachCsv').checked) attachments.push('csv');
+    if (document.getElementById('attachPdf').checked) attachments.push('pdf');
 
     sendEmailBtn.disabled = true;
     sendEmailBtn.textContent = 'Sending...';
 
     try {
-        const response = await fetch('/api/email', {
+        const response = await fetch(`/api/v1/sessions/${currentSessionId}/email`, {
             method: 'POST',
-            headers: { 'Content-Type': 'application/json' },
-            body: JSON.stringify({
-                to: recipients,
-                subject: 'Split Bills Results',
-                html_content: htmlContent
-            })
+            headers: { 'Content-Type': 'application/json', 'X-Edit-Secret': currentEditSecret },
+            body: JSON.stringify({ to: recipients, attachments })
         });
 
         if (response.ok) {
@@ -1527,8 +2288,8 @@ async function sendEmail() {
             emailModal.style.display = 'none';
             emailRecipientsInput.value = '';
         } else {
-            const err = await response.text();
-            alert('Failed to send email: ' + err);
+            const err = await response.json().catch(() => ({}));
+            alert('Failed to send email: ' + (err.message || response.statusText));
         }
     } catch (e) {
         console.error(e);
@@ -1539,72 +2300,20 @@ async function sendEmail() {
     }
 }
 
-function generateEmailHtml(result) {
-    let html = `
-        <div style="font-family: Arial, sans-serif; max-width: 800px; margin: 0 auto;">
-            <h2 style="color: #2c5282;">Split Bills Results</h2>
-            
-            <h3 style="color: #2d3748;">Summary</h3>
-            <table style="border-collapse: collapse; margin-bottom: 20px;">
-                <tr><td style="padding: 5px;"><strong>Total Spent:</strong></td><td style="padding: 5px;">$${formatMoney(result.total_spent)}</td></tr>
-                <tr><td style="padding: 5px;"><strong>Total Sponsored:</strong></td><td style="padding: 5px;">$${formatMoney(result.total_sponsored)}</td></tr>
-                ${result.total_tip > 0 ? `<tr><td style="padding: 5px;"><strong>Total Tip/Tax:</strong></td><td style="padding: 5px;">$${formatMoney(result.total_tip)}</td></tr>` : ''}
-                ${result.fund_amount > 0 ? `<tr><td style="padding: 5px;"><strong>Fund Used:</strong></td><td style="padding: 5px;">$${formatMoney(result.fund_amount)}</td></tr>` : ''}
-                <tr><td style="padding: 5px;"><strong>Amount to Share:</strong></td><td style="padding: 5px;">$${formatMoney(result.amount_to_share)}</td></tr>
-                <tr><td style="padding: 5px;"><strong>Number of Participants:</strong></td><td style="padding: 5px;">${result.num_participants}</td></tr>
-                <tr><td style="padding: 5px;"><strong>Per Person Share:</strong></td><td style="padding: 5px;">$${formatMoney(result.per_person_share)}</td></tr>
-            </table>
-            
-            <h3 style="color: #2d3748;">Settlements</h3>
-            <table border="1" cellpadding="8" cellspacing="0" style="border-collapse: collapse; width: 100%; border: 1px solid #cbd5e0;">
-                <thead>
-                    <tr style="background-color: #edf2f7;">
-                        <th style="text-align: left; padding: 8px; border: 1px solid #cbd5e0;">Name</th>
-                        <th style="text-align: right; padding: 8px; border: 1px solid #cbd5e0;">Spent</th>
-                        <th style="text-align: right; padding: 8px; border: 1px solid #cbd5e0;">Tip/Tax Paid</th>
-                        <th style="text-align: right; padding: 8px; border: 1px solid #cbd5e0;">Sponsor Cost</th>
-                        <th style="text-align: right; padding: 8px; border: 1px solid #cbd5e0;">Share Cost</th>
-                        <th style="text-align: right; padding: 8px; border: 1px solid #cbd5e0;">Balance</th>
-                        <th style="text-align: left; padding: 8px; border: 1px solid #cbd5e0;">Action</th>
-                    </tr>
-                </thead>
-                <tbody>
-    `;
-
-    result.settlements.forEach(s => {
-        let action = "";
-        let bgColor = "#ffffff";
-        if (s.settlement_type === 'pay') {
-            action = `Pay <strong>$${formatMoney(Math.abs(s.balance))}</strong>`;
-            bgColor = "#fed7d7";
-        } else if (s.settlement_type === 'receive') {
-            action = `Receive <strong>$${formatMoney(s.balance)}</strong>`;
-            bgColor = "#c6f6d5";
-        } else {
-            action = "Settled";
+// Send a request that is safe to repeat. The Idempotency-Key is made once per call, so
+// when the connection drops (fetch throws) or the first attempt is still running (409),
+// the retry carries the same key and the server answers it without doing the work twice.
+async function fetchIdempotent(url, options, attempts = 3) {
+    const headers = { ...(options.headers || {}), 'Idempotency-Key': generateUUID() };
+    for (let attempt = 1; ; attempt++) {
+        try {
+            const response = await fetch(url, { ...options, headers });
+            if (response.status !== 409 || attempt >= attempts) return response;
+        } catch (e) {
+            if (!(e instanceof TypeError) || attempt >= attempts) throw e;
         }
-
-        html += `
-            <tr>
-                <td style="padding: 8px; border: 1px solid #cbd5e0;">${s.name}</td>
-                <td style="text-align: right; padding: 8px; border: 1px solid #cbd5e0;">$${formatMoney(s.amount_spent)}</td>
-                <td style="text-align: right; padding: 8px; border: 1px solid #cbd5e0;">$${formatMoney(s.tip_paid)}</td>
-                <td style="text-align: right; padding: 8px; border: 1px solid #cbd5e0;">$${formatMoney(s.sponsor_cost)}</td>
-                <td style="text-align: right; padding: 8px; border: 1px solid #cbd5e0;">$${formatMoney(s.share_cost)}</td>
-                <td style="text-align: right; padding: 8px; border: 1px solid #cbd5e0; background-color: ${bgColor}; font-weight: bold;">$${formatMoney(s.balance)}</td>
-                <td style="padding: 8px; border: 1px solid #cbd5e0; background-color: ${bgColor};">${action}</td>
-            </tr>
-        `;
-    });
-
-    html += `
-                </tbody>
-            </table>
-            <p style="margin-top: 20px; color: #718096; font-size: 12px;"><em>Generated by Split Bills</em></p>
-        </div>
-    `;
-    
-    return html;
+        await new Promise(resolve => setTimeout(resolve, 1000 * attempt));
+    }
 }
 
 // UUID Generation
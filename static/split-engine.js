@@ -0,0 +1,59 @@
+// The server's calculation engine compiled to WebAssembly (see split-bills-wasm), so a
+// split can be calculated without reaching /api/v1/calculate. splitEngine.calculate takes
+// and returns the same JSON as the API and gives identical numbers.
+
+const splitEngine = (() => {
+    const WASM_URL = '/static/split_bills_wasm.wasm?v=1';
+    const STATUS_OK = 0;
+    let enginePromise = null;
+
+    async function instantiate() {
+        // Fall back to compiling from bytes when the server doesn't send application/wasm
+        if (WebAssembly.instantiateStreaming) {
+            try {
+                const { instance } = await WebAssembly.instantiateStreaming(fetch(WASM_URL), {});
+                return instance.exports;
+            } catch (error) {
+                console.warn('Streaming WebAssembly compile failed, retrying:', error);
+            }
+        }
+        const response = await fetch(WASM_URL);
+        const { instance } = await WebAssembly.instantiate(await response.arrayBuffer(), {});
+        return instance.exports;
+    }
+
+    function load() {
+        if (!enginePromise) {
+            enginePromise = instantiate().catch(error => {
+                enginePromise = null;
+                throw error;
+            });
+        }
+        return enginePromise;
+    }
+
+    // Resolves with a CalculateResponse; rejects with the engine's message for a bad request
+    async function calculate(request) {
+        const engine = await load();
+        const input = new TextEncoder().encode(JSON.stringify(request));
+
+        const ptr = engine.alloc(input.length);
+        let status;
+        try {
+            new Uint8Array(engine.memory.buffer, ptr, input.length).set(input);
+            status = engine.calculate(ptr, input.length);
+        } finally {
+            engine.dealloc(ptr, input.length);
+        }
+
+        const output = new TextDecoder().decode(
+            new Uint8Array(engine.memory.buffer, engine.result_ptr(), engine.result_len())
+        );
+        if (status !== STATUS_OK) {
+            throw new Error(output);
+        }
+        return JSON.parse(output);
+    }
+
+    return { load, calculate };
+})();